@@ -1,8 +1,8 @@
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use redoxql::pagerange::PageRange;
-use redoxql::record::{Record, RecordAddress, RecordLock};
+use redoxql::record::{Record, RecordAddress};
 use redoxql::table::PageDirectory;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 fn setup_benchmark_data(
@@ -18,17 +18,14 @@ fn setup_benchmark_data(
         // We usually have less columns (30 seems realistic)
         for i in 0..30 {
             let rid = i as i64;
-            let mut addresses = Vec::new();
-
-            addresses.push(RecordAddress {
+            let addresses = vec![RecordAddress {
                 page: page_range.base_container.rid_page(),
                 offset: i as i64,
-            });
+            }];
 
             let record = Record {
                 rid,
-                addresses: Arc::new(Mutex::new(addresses)),
-                lock: Arc::new(RwLock::new(RecordLock::default())),
+                addresses: Arc::new(addresses),
             };
 
             pd.directory.insert(rid, record);
@@ -36,31 +33,24 @@ fn setup_benchmark_data(
 
         for i in 0..num_records {
             let rid = (1000 + i) as i64;
-            let mut addresses = Vec::new();
-
-            addresses.push(RecordAddress {
+            let addresses = vec![RecordAddress {
                 page: page_range.base_container.rid_page(),
                 offset: (i % 100) as i64,
-            });
+            }];
 
             let record = Record {
                 rid,
-                addresses: Arc::new(Mutex::new(addresses)),
-                lock: Arc::new(RwLock::new(RecordLock::default())),
+                addresses: Arc::new(addresses),
             };
 
             pd.directory.insert(rid, record);
         }
     }
 
-    {
-        let a = page_range.tail_container.rid_page();
-        let mut rid_guard = a.lock().unwrap();
-        rid_guard.data.resize(num_records, 0);
-        for i in 0..num_records {
-            rid_guard.data[i] = (1000 + i) as i64;
-        }
-    }
+    page_range
+        .tail_container
+        .rid_page()
+        .extend((0..num_records).map(|i| (1000 + i) as i64));
 
     (page_range, page_directory)
 }
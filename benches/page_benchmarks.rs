@@ -9,7 +9,7 @@ fn bench_single_write(c: &mut Criterion) {
     c.bench_function("single write", |b| {
         b.iter_with_setup(
             || PhysicalPage::new(0),
-            |mut page| {
+            |page| {
                 page.write(black_box(42));
             },
         )
@@ -20,7 +20,7 @@ fn bench_single_read(c: &mut Criterion) {
     c.bench_function("single read", |b| {
         b.iter_with_setup(
             || {
-                let mut page = PhysicalPage::new(0);
+                let page = PhysicalPage::new(0);
                 page.write(42);
                 page
             },
@@ -39,7 +39,7 @@ fn bench_sequential_writes(c: &mut Criterion) {
         group.bench_with_input(format!("{} writes", size), size, |b, &size| {
             b.iter_with_setup(
                 || PhysicalPage::new(0),
-                |mut page| {
+                |page| {
                     for i in 0..size {
                         page.write(black_box(i));
                     }
@@ -58,7 +58,7 @@ fn bench_sequential_reads(c: &mut Criterion) {
         group.bench_with_input(format!("{} reads", size), size, |b, &size| {
             b.iter_with_setup(
                 || {
-                    let mut page = PhysicalPage::new(0);
+                    let page = PhysicalPage::new(0);
                     for i in 0..size {
                         page.write(i);
                     }
@@ -83,7 +83,7 @@ fn bench_mixed_workload(c: &mut Criterion) {
         group.bench_with_input(format!("{} operations", size), size, |b, &size| {
             b.iter_with_setup(
                 || PhysicalPage::new(0),
-                |mut page| {
+                |page| {
                     for i in 0..size {
                         if i % 2 == 0 {
                             page.write(black_box(i));
@@ -1,13 +1,14 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use redoxql::database::RDatabase;
 use redoxql::query::RQuery;
+use std::thread;
 
 fn bench_insert(c: &mut Criterion) {
     c.bench_function("single insert", |b| {
         b.iter_with_setup(
             || {
                 let mut db = RDatabase::new();
-                let table = db.create_table(String::from("Grades"), 3, 0);
+                let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                 RQuery::new(table)
             },
             |mut query| {
@@ -22,7 +23,7 @@ fn bench_select(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let mut db = RDatabase::new();
-                let table = db.create_table(String::from("Grades"), 3, 0);
+                let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                 let mut query = RQuery::new(table);
                 query.insert(vec![1, 2, 3]);
                 query
@@ -39,7 +40,7 @@ fn bench_update(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let mut db = RDatabase::new();
-                let table = db.create_table(String::from("Grades"), 3, 0);
+                let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                 let mut query = RQuery::new(table);
                 query.insert(vec![1, 2, 3]);
                 query
@@ -56,7 +57,7 @@ fn bench_delete(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let mut db = RDatabase::new();
-                let table = db.create_table(String::from("Grades"), 3, 0);
+                let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                 let mut query = RQuery::new(table);
                 query.insert(vec![1, 2, 3]);
                 query
@@ -73,7 +74,7 @@ fn bench_increment(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let mut db = RDatabase::new();
-                let table = db.create_table(String::from("Grades"), 3, 0);
+                let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                 let mut query = RQuery::new(table);
                 query.insert(vec![1, 2, 3]);
                 query
@@ -90,7 +91,7 @@ fn bench_select_version(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let mut db = RDatabase::new();
-                let table = db.create_table(String::from("Grades"), 3, 0);
+                let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                 let mut query = RQuery::new(table);
                 query.insert(vec![1, 2, 3]);
                 query.update(1, vec![Some(1), Some(4), Some(5)]);
@@ -116,7 +117,7 @@ fn bench_multiple_updates(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     let mut query = RQuery::new(table);
                     query.insert(vec![1, 2, 3]);
                     query
@@ -139,7 +140,7 @@ fn bench_sum(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     let mut query = RQuery::new(table);
                     for i in 0..size {
                         query.insert(vec![i as i64, i as i64 * 2, i as i64 * 3]);
@@ -162,7 +163,7 @@ fn bench_bulk_insert(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     RQuery::new(table)
                 },
                 |mut query| {
@@ -176,6 +177,28 @@ fn bench_bulk_insert(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_insert_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batched inserts");
+    for size in [10, 100, 1000, 10000].iter() {
+        group.bench_with_input(format!("{} inserts", size), size, |b, &size| {
+            b.iter_with_setup(
+                || {
+                    let mut db = RDatabase::new();
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
+                    let rows: Vec<Vec<i64>> = (0..size)
+                        .map(|i| vec![i as i64, i as i64 * 2, i as i64 * 3])
+                        .collect();
+                    (RQuery::new(table), rows)
+                },
+                |(mut query, rows)| {
+                    black_box(query.insert_many(black_box(rows)));
+                },
+            )
+        });
+    }
+    group.finish();
+}
+
 fn bench_bulk_select(c: &mut Criterion) {
     let mut group = c.benchmark_group("bulk selects");
     for size in [10, 100, 1000, 10000].iter() {
@@ -183,7 +206,7 @@ fn bench_bulk_select(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     let mut query = RQuery::new(table);
 
                     for i in 0..size {
@@ -213,7 +236,7 @@ fn bench_bulk_update(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     let mut query = RQuery::new(table);
 
                     for i in 0..size {
@@ -235,6 +258,39 @@ fn bench_bulk_update(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_update_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batched updates");
+    for size in [10, 100, 1000, 10000].iter() {
+        group.bench_with_input(format!("{} updates", size), size, |b, &size| {
+            b.iter_with_setup(
+                || {
+                    let mut db = RDatabase::new();
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
+                    let mut query = RQuery::new(table);
+
+                    for i in 0..size {
+                        query.insert(vec![i as i64, i as i64 * 2, i as i64 * 3]);
+                    }
+
+                    let updates: Vec<(i64, Vec<Option<i64>>)> = (0..size)
+                        .map(|i| {
+                            (
+                                i as i64,
+                                vec![Some(i as i64), Some(i as i64 * 5), Some(i as i64 * 6)],
+                            )
+                        })
+                        .collect();
+                    (query, updates)
+                },
+                |(mut query, updates)| {
+                    black_box(query.update_many(black_box(updates)));
+                },
+            )
+        });
+    }
+    group.finish();
+}
+
 fn bench_bulk_delete(c: &mut Criterion) {
     let mut group = c.benchmark_group("bulk deletes");
     for size in [10, 100, 1000, 10000].iter() {
@@ -242,7 +298,7 @@ fn bench_bulk_delete(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     let mut query = RQuery::new(table);
 
                     for i in 0..size {
@@ -268,7 +324,7 @@ fn bench_mixed_workload(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut db = RDatabase::new();
-                    let table = db.create_table(String::from("Grades"), 3, 0);
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                     let mut query = RQuery::new(table);
 
                     for i in 0..size / 2 {
@@ -326,7 +382,7 @@ fn bench_version_history(c: &mut Criterion) {
                 b.iter_with_setup(
                     || {
                         let mut db = RDatabase::new();
-                        let table = db.create_table(String::from("Grades"), 3, 0);
+                        let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
                         let mut query = RQuery::new(table);
 
                         query.insert(vec![1, 2, 3]);
@@ -353,6 +409,50 @@ fn bench_version_history(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_concurrent_select(c: &mut Criterion) {
+    let size = 10_000;
+
+    let mut group = c.benchmark_group("concurrent selects");
+    for threads in [1, 2, 4, 8].iter() {
+        group.bench_with_input(format!("{} threads", threads), threads, |b, &threads| {
+            b.iter_with_setup(
+                || {
+                    let mut db = RDatabase::new();
+                    let table = db.create_table(String::from("Grades"), 3, 0).unwrap();
+                    let mut query = RQuery::new(table.clone());
+
+                    for i in 0..size {
+                        query.insert(vec![i as i64, i as i64 * 2, i as i64 * 3]);
+                    }
+                    table
+                },
+                |table| {
+                    // Every thread selects the same keys so they all read the same column pages
+                    let handles: Vec<_> = (0..threads)
+                        .map(|_| {
+                            let mut query = RQuery::new(table.clone());
+                            thread::spawn(move || {
+                                for i in 0..size / threads {
+                                    black_box(query.select(
+                                        black_box(i as i64),
+                                        black_box(0),
+                                        black_box(vec![1, 1, 1]),
+                                    ));
+                                }
+                            })
+                        })
+                        .collect();
+
+                    for h in handles {
+                        h.join().unwrap();
+                    }
+                },
+            )
+        });
+    }
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_insert,
@@ -366,8 +466,11 @@ criterion_group!(
     bench_bulk_insert,
     bench_bulk_select,
     bench_bulk_update,
+    bench_insert_many,
+    bench_update_many,
     bench_bulk_delete,
     bench_mixed_workload,
-    bench_version_history
+    bench_version_history,
+    bench_concurrent_select
 );
 criterion_main!(benches);
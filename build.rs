@@ -0,0 +1,19 @@
+fn main() {
+    // The header only changes with the C API, so it is written on request and checked in
+    #[cfg(feature = "header")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+            .expect("Should be able to read cbindgen.toml");
+
+        cbindgen::Builder::new()
+            .with_crate(&crate_dir)
+            .with_config(config)
+            .generate()
+            .expect("Should be able to generate the C header")
+            .write_to_file(format!("{}/include/redoxql.h", crate_dir));
+    }
+}
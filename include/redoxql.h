@@ -0,0 +1,209 @@
+/* The C API of RedoxQL, generated from src/ffi.rs with `cargo build --features header` */
+
+#ifndef REDOXQL_H
+#define REDOXQL_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// What a call of the C API did
+typedef enum RedoxStatus {
+  REDOX_STATUS_OK = 0,
+  // A pointer that has to point to something was null
+  REDOX_STATUS_NULL_POINTER,
+  // A string was not UTF-8
+  REDOX_STATUS_INVALID_UTF8,
+  // A file of the database could not be read or written
+  REDOX_STATUS_IO,
+  REDOX_STATUS_NO_SUCH_TABLE,
+  REDOX_STATUS_TABLE_EXISTS,
+  REDOX_STATUS_DUPLICATE_KEY,
+  REDOX_STATUS_NO_SUCH_RECORD,
+  // A column, projection or argument does not fit the table
+  REDOX_STATUS_INVALID_ARGUMENT,
+  // The transaction has not begun, already began, or already ended
+  REDOX_STATUS_TRANSACTION_STATE,
+  REDOX_STATUS_NO_SUCH_SAVEPOINT,
+  // A query of an interactive transaction failed, check if the transaction is still active
+  REDOX_STATUS_QUERY_FAILED,
+  // The transaction was aborted and none of its writes were applied
+  REDOX_STATUS_ABORTED,
+  // A row or column past the end of a select result
+  REDOX_STATUS_OUT_OF_RANGE,
+  // The column was not projected, so it has no value
+  REDOX_STATUS_NULL_VALUE,
+  // Something went wrong inside RedoxQL, the message says what
+  REDOX_STATUS_PANIC,
+} RedoxStatus;
+
+typedef struct RedoxDatabase RedoxDatabase;
+
+// The records a select found
+typedef struct RedoxRows RedoxRows;
+
+typedef struct RedoxTable RedoxTable;
+
+typedef struct RedoxTransaction RedoxTransaction;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The message of the last call on this thread that did not return `REDOX_STATUS_OK`
+//
+// The string stays valid until the next failing call on the same thread.
+const char *redoxql_last_error(void);
+
+// A database that only lives in memory, free it with `redoxql_database_free`
+struct RedoxDatabase *redoxql_database_new(void);
+
+// Load the database whose manifest is at `path`, a missing manifest gives an empty database
+enum RedoxStatus redoxql_database_open(const char *path, struct RedoxDatabase **out);
+
+// Save the database to the path it was opened with, the handle can still be used after
+enum RedoxStatus redoxql_database_close(struct RedoxDatabase *db);
+
+// Free a database without saving it, tables taken from it stay usable until they are freed
+void redoxql_database_free(struct RedoxDatabase *db);
+
+// Fails with TableExists if the database already has a table with this name, and with
+// InvalidArgument if the primary key column is not one of the columns
+enum RedoxStatus redoxql_create_table(struct RedoxDatabase *db,
+                                      const char *name,
+                                      size_t num_columns,
+                                      size_t primary_key_column,
+                                      struct RedoxTable **out);
+
+enum RedoxStatus redoxql_get_table(const struct RedoxDatabase *db,
+                                   const char *name,
+                                   struct RedoxTable **out);
+
+enum RedoxStatus redoxql_drop_table(struct RedoxDatabase *db, const char *name);
+
+// Free a table handle, the table itself stays in its database
+void redoxql_table_free(struct RedoxTable *table);
+
+// The number of columns of the table, 0 if it is null
+size_t redoxql_table_num_columns(const struct RedoxTable *table);
+
+// Insert a record, `values` has a value for every column
+enum RedoxStatus redoxql_insert(const struct RedoxTable *table, const int64_t *values, size_t len);
+
+// Update a record, only the columns whose entry in `present` is true are set
+enum RedoxStatus redoxql_update(const struct RedoxTable *table,
+                                int64_t primary_key,
+                                const int64_t *values,
+                                const bool *present,
+                                size_t len);
+
+enum RedoxStatus redoxql_delete(const struct RedoxTable *table, int64_t primary_key);
+
+enum RedoxStatus redoxql_increment(const struct RedoxTable *table,
+                                   int64_t primary_key,
+                                   size_t column);
+
+// Every record whose `column` is `key`, `projected` has a 1 for each column to return
+enum RedoxStatus redoxql_select(const struct RedoxTable *table,
+                                int64_t key,
+                                size_t column,
+                                const int64_t *projected,
+                                size_t len,
+                                struct RedoxRows **out);
+
+// An older version of a record, -1 is the version before the newest one
+enum RedoxStatus redoxql_select_version(const struct RedoxTable *table,
+                                        int64_t primary_key,
+                                        const int64_t *projected,
+                                        size_t len,
+                                        int64_t relative_version,
+                                        struct RedoxRows **out);
+
+// The sum of `column` over the records with a primary key from `start` to `end`, both included
+enum RedoxStatus redoxql_sum(const struct RedoxTable *table,
+                             int64_t start,
+                             int64_t end,
+                             size_t column,
+                             int64_t *out);
+
+enum RedoxStatus redoxql_sum_version(const struct RedoxTable *table,
+                                     int64_t start,
+                                     int64_t end,
+                                     size_t column,
+                                     int64_t relative_version,
+                                     int64_t *out);
+
+// How many records a select found, 0 if `rows` is null
+size_t redoxql_rows_count(const struct RedoxRows *rows);
+
+// One value of a select result, `REDOX_STATUS_NULL_VALUE` if the column was not projected
+enum RedoxStatus redoxql_rows_value(const struct RedoxRows *rows,
+                                    size_t row,
+                                    size_t column,
+                                    int64_t *out);
+
+void redoxql_rows_free(struct RedoxRows *rows);
+
+// A transaction that has begun, run queries in it with the `redoxql_transaction_` functions
+struct RedoxTransaction *redoxql_transaction_begin(void);
+
+// Apply the writes of the transaction and release its locks, Aborted if a write could not be
+// applied, then none of them are
+enum RedoxStatus redoxql_transaction_commit(struct RedoxTransaction *t);
+
+// Drop the writes of the transaction and release its locks
+enum RedoxStatus redoxql_transaction_abort(struct RedoxTransaction *t);
+
+// False once the transaction committed or aborted, also after a failed query aborted it
+bool redoxql_transaction_is_active(const struct RedoxTransaction *t);
+
+// Free a transaction, one that is still active is aborted first
+void redoxql_transaction_free(struct RedoxTransaction *t);
+
+enum RedoxStatus redoxql_transaction_insert(struct RedoxTransaction *t,
+                                            const struct RedoxTable *table,
+                                            const int64_t *values,
+                                            size_t len);
+
+enum RedoxStatus redoxql_transaction_update(struct RedoxTransaction *t,
+                                            const struct RedoxTable *table,
+                                            int64_t primary_key,
+                                            const int64_t *values,
+                                            const bool *present,
+                                            size_t len);
+
+enum RedoxStatus redoxql_transaction_delete(struct RedoxTransaction *t,
+                                            const struct RedoxTable *table,
+                                            int64_t primary_key);
+
+enum RedoxStatus redoxql_transaction_increment(struct RedoxTransaction *t,
+                                               const struct RedoxTable *table,
+                                               int64_t primary_key,
+                                               size_t column);
+
+enum RedoxStatus redoxql_transaction_select(struct RedoxTransaction *t,
+                                            const struct RedoxTable *table,
+                                            int64_t key,
+                                            size_t column,
+                                            const int64_t *projected,
+                                            size_t len,
+                                            struct RedoxRows **out);
+
+enum RedoxStatus redoxql_transaction_sum(struct RedoxTransaction *t,
+                                         const struct RedoxTable *table,
+                                         int64_t start,
+                                         int64_t end,
+                                         size_t column,
+                                         int64_t *out);
+
+enum RedoxStatus redoxql_transaction_savepoint(struct RedoxTransaction *t, const char *name);
+
+// Undo everything done since the savepoint, the savepoint itself stays
+enum RedoxStatus redoxql_transaction_rollback_to_savepoint(struct RedoxTransaction *t,
+                                                           const char *name);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* REDOXQL_H */
@@ -0,0 +1,99 @@
+import json
+import socket
+
+
+class ServerError(Exception):
+    """The server could not do what was asked"""
+
+
+class Client:
+    """A connection to redoxql-server, it can have one transaction at a time
+    Example:
+    client = Client("127.0.0.1", 7878)
+    client.create_table("Grades", 3, 0)
+    client.insert("Grades", [1, 90, 80])
+    client.select("Grades", 1, 0, [1, 1, 1])  # [[1, 90, 80]]
+    """
+
+    def __init__(self, host: str, port: int):
+        self.sock = socket.create_connection((host, port))
+        self.file = self.sock.makefile("rw", encoding="utf-8", newline="\n")
+
+    def request(self, op: str, **args):
+        """Sends one request and returns what the server answered
+        :param op: string     #The operation, like "insert" or "begin"
+        """
+        self.file.write(json.dumps({"op": op, **args}) + "\n")
+        self.file.flush()
+
+        line = self.file.readline()
+        if not line:
+            raise ConnectionError("The server closed the connection")
+
+        response = json.loads(line)
+        if "error" in response:
+            raise ServerError(response["error"])
+        return response["ok"]
+
+    def sql(self, sql: str):
+        """Returns the rows of a SELECT, the result of SUM, or how many records changed"""
+        return self.request("sql", sql=sql)
+
+    def create_table(self, name: str, num_columns: int, primary_key_column: int):
+        self.request(
+            "create_table",
+            name=name,
+            num_columns=num_columns,
+            primary_key_column=primary_key_column,
+        )
+
+    def drop_table(self, name: str):
+        self.request("drop_table", name=name)
+
+    def list_tables(self) -> list:
+        return self.request("list_tables")
+
+    def insert(self, table: str, values: list):
+        self.request("insert", table=table, values=values)
+
+    def update(self, table: str, primary_key: int, columns: list):
+        """None in columns keeps the old value"""
+        self.request("update", table=table, primary_key=primary_key, columns=columns)
+
+    def delete(self, table: str, primary_key: int):
+        self.request("delete", table=table, primary_key=primary_key)
+
+    def increment(self, table: str, primary_key: int, column: int):
+        self.request("increment", table=table, primary_key=primary_key, column=column)
+
+    def select(self, table: str, key: int, column: int, projected: list) -> list:
+        """Returns every record whose column is key, None in the columns not projected"""
+        return self.request(
+            "select", table=table, key=key, column=column, projected=projected
+        )
+
+    def sum(self, table: str, start: int, end: int, column: int) -> int:
+        return self.request("sum", table=table, start=start, end=end, column=column)
+
+    def begin(self) -> int:
+        """Starts a transaction, the queries of this client run in it until commit or abort"""
+        return self.request("begin")
+
+    def commit(self):
+        self.request("commit")
+
+    def abort(self):
+        self.request("abort")
+
+    def checkpoint(self):
+        """Has the server save the database"""
+        self.request("checkpoint")
+
+    def shutdown(self):
+        """Has the server save the database and stop accepting connections"""
+        self.request("shutdown")
+
+    def close(self):
+        """Closes the connection, a transaction that is still open is aborted"""
+        self.file.close()
+        self.sock.close()
@@ -25,9 +25,48 @@ class Database:
         return self.db.create_table(name, num_columns, key_index)
 
     def drop_table(self, name: str):
-        """Deletes the specified table"""
+        """Deletes the specified table and its files on disk"""
         self.db.drop_table(name)
 
     def get_table(self, name: str) -> RTable:
         """Returns table with the passed name"""
         return self.db.get_table(name)
+
+    def rename_table(self, name: str, new_name: str):
+        """Gives the table a new name, columns are changed on the table itself with
+        add_column, drop_column and reorder_columns
+        """
+        self.db.rename_table(name, new_name)
+
+    def get_table_by_id(self, table_id: int) -> RTable:
+        """Returns the table with this id, ids are never reused after a drop"""
+        return self.db.get_table_by_id(table_id)
+
+    def list_tables(self) -> list:
+        """Returns the names of all tables"""
+        return self.db.list_tables()
+
+    def describe_table(self, name: str):
+        """Returns the columns, key column, record counts, indexes and size on disk of a table"""
+        return self.db.describe_table(name)
+
+    def execute(self, sql: str):
+        """Run one SQL statement, columns are called c0, c1, ...
+        Returns the rows of a SELECT, the result of SUM, or how many records changed
+        """
+        return self.db.execute(sql)
+
+    def set_deadlock_strategy(self, strategy: str, timeout_ms: int = None):
+        """Choose what a transaction does when a lock it needs is taken
+        :param strategy: string     #"no_wait", "wait_die", "wound_wait" or "timeout"
+        :param timeout_ms: int      #How long "timeout" waits before aborting
+        """
+        self.db.set_deadlock_strategy(strategy, timeout_ms)
+
+    def lock_holders(self, table: str, primary_key: int) -> list:
+        """Returns (transaction id, lock type) for every holder of the lock on a key"""
+        return self.db.lock_holders(table, primary_key)
+
+    def dump_locks(self) -> list:
+        """Returns (table, primary key, transaction id, lock type) for every held lock"""
+        return self.db.dump_locks()
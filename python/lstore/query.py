@@ -20,7 +20,7 @@ class Query:
         Returns True upon succesful deletion
         Return False if record doesn't exist or is locked due to 2PL
         """
-        self.rquery.delete(primary_key)
+        return self.rquery.delete(primary_key)
 
     def insert(self, *columns):
         """Insert a record with specified columns
@@ -76,6 +76,21 @@ class Query:
         """
         return self.rquery.update(primary_key, columns)
 
+    def insert_many(self, rows: List[List[int]]) -> List[bool]:
+        """Insert many records at once, taking the table lock once
+        Returns a list with True for every row that was inserted and False for
+        every row that failed like insert would
+        """
+        return self.rquery.insert_many(rows)
+
+    def update_many(self, updates) -> List[bool]:
+        """Update many records at once, taking the table lock once
+        :param updates: a list of (primary_key, columns) pairs like update takes
+        Returns a list with True for every update that was applied and False for
+        every update that failed like update would
+        """
+        return self.rquery.update_many(updates)
+
     def sum(
         self,
         start_range: int,
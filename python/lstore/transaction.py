@@ -1,11 +1,25 @@
 from .lstore import RTransaction
 
 
+def flatten_args(args):
+    # Rust takes a flat list, so unpack the projected columns of select and select_version
+    flat_args = []
+    for arg in args:
+        if isinstance(arg, (list, tuple)):
+            flat_args.extend(arg)
+        else:
+            flat_args.append(arg)
+
+    return flat_args
+
+
 class Transaction:
     def __init__(self):
         """Creates a transaction object."""
         self.transaction = RTransaction()
 
+        # What each query returned on the last commit, in the order they were added
+        self.results = []
 
     def add_query(self, query, table, *args):
         """Adds the given query to this transaction
@@ -13,18 +27,76 @@ class Transaction:
         q = Query(grades_table)
         t = Transaction()
         t.add_query(q.update, grades_table, 0, *[None, 1, None, 2, None])
+        t.add_query(q.select, grades_table, 0, 0, [1, 1, 1, 1, 1])
+
+        Raises ValueError if the query is unknown or gets the wrong number of arguments, and for
+        a select that searches another column than the primary key.
+        """
+        self.transaction.add_query(query.__name__, table, flatten_args(args))
+
+    def begin(self):
+        """Starts an interactive transaction, every query given to execute runs right away
+        Example:
+        t = Transaction().begin()
+        record = t.execute(q.select, grades_table, 1, 0, [1, 1, 1, 1, 1])[0]
+        t.execute(q.update, grades_table, 1, *[None, record.columns[1] + 1, None, None, None])
+        t.commit()
+        """
+        self.transaction.begin()
+        return self
+
+    def execute(self, query, table, *args):
+        """Runs the query now under this transaction's locks and returns what it gives back
+        Returns False and aborts the whole transaction if the query fails. With a savepoint set
+        only a query that can't get its locks aborts, others return False and can be rolled back.
         """
-        function_name = query.__name__
+        result = self.transaction.execute(query.__name__, table, flatten_args(args))
+        self.results = self.transaction.results
+        return result
 
-        # TODO: Figure out of we need select and select_version in transaction
-        if function_name != "select" and function_name != "select_version":
-            self.transaction.add_query(function_name, table, args)
+    def savepoint(self, name):
+        """Remembers the state of an interactive transaction so it can be rolled back to later
+        Example:
+        t.savepoint("before_load")
+        if t.execute(q.insert, grades_table, *record) is False:
+            t.rollback_to_savepoint("before_load")
+        """
+        self.transaction.savepoint(name)
+
+    def rollback_to_savepoint(self, name):
+        """Undoes everything done since the savepoint, the transaction keeps going"""
+        self.transaction.rollback_to_savepoint(name)
+        self.results = self.transaction.results
+
+    def release_savepoint(self, name):
+        """Forgets the savepoint, nothing is undone"""
+        self.transaction.release_savepoint(name)
+
+    @property
+    def active(self):
+        return self.transaction.active
+
+    def __enter__(self):
+        return self.begin()
+
+    def __exit__(self, exc_type, exc_value, traceback):
+        # Commit when the block finished normally, throw everything away when it raised
+        if self.active:
+            if exc_type is None:
+                self.commit()
+            else:
+                self.abort()
+
+        return False
 
     # If you choose to implement this differently this method must still return
     # True if transaction commits or False on abort
     def run(self):
-        # TODO: Python expects a bool
-        return self.transaction.run()
+        committed = self.transaction.run()
+
+        # Selects give a list of records, sums give a number and writes give True
+        self.results = self.transaction.results
+        return committed
 
     def abort(self):
         return self.transaction.abort()
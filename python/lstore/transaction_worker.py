@@ -1,36 +1,68 @@
-from .lstore import RTransactionWorker
+from .lstore import RTransactionWorker, RWorkerPool
+
+
+class WorkerPool:
+
+    def __init__(self, num_threads=None):
+        """Creates a pool of threads that transaction workers can share.
+
+        Defaults to one thread per core.
+        """
+        self.pool = RWorkerPool(num_threads)
+
+    @property
+    def num_threads(self):
+        return self.pool.num_threads
 
 
 class TransactionWorker:
 
-    def __init__(self, transactions=()):
-        """Creates a transaction worker object."""
-        self.transaction_worker = RTransactionWorker()
+    def __init__(
+        self,
+        transactions=(),
+        max_attempts=10,
+        initial_backoff_ms=1,
+        max_backoff_ms=50,
+        pool=None,
+    ):
+        """Creates a transaction worker object.
+
+        Aborted transactions are run again, waiting longer each time, until they commit or
+        have been tried max_attempts times. Transactions run on pool, or on a pool shared by
+        every worker if none is given.
+        """
+        self.transaction_worker = RTransactionWorker(
+            max_attempts,
+            initial_backoff_ms,
+            max_backoff_ms,
+            pool.pool if pool is not None else None,
+        )
 
         for t in transactions:
             self.transaction_worker.add_transaction(t)
 
-        self.stats = []
-        self.result = 0
-
     def add_transaction(self, t):
         """Appends t to transactions"""
         self.transaction_worker.add_transaction(t)
 
     def run(self):
-        """Runs all transaction as a thread"""
+        """Starts running all transactions on the pool and returns right away"""
         self.transaction_worker.run()
 
     def join(self):
-        """Waits for the worker to finish"""
+        """Waits for every transaction of this worker to finish"""
         self.transaction_worker.join()
 
-    def __run(self):
-        """
-        for transaction in self.transactions:
-            # each transaction returns True if committed or False if aborted
-            self.stats.append(transaction.run())
-        # stores the number of transactions that committed
-        self.result = len(list(filter(lambda x: x, self.stats)))
-        """
-        pass
+    @property
+    def stats(self):
+        """How many transactions committed, aborted for good, and how many retries it took"""
+        return {
+            "committed": self.transaction_worker.committed,
+            "aborted": self.transaction_worker.aborted,
+            "retries": self.transaction_worker.retries,
+        }
+
+    @property
+    def result(self):
+        """The number of transactions that committed"""
+        return self.transaction_worker.committed
@@ -0,0 +1,550 @@
+//! The Rust API of RedoxQL
+//!
+//! `Database`, `Table`, `Query` and `Transaction` wrap the types the Python bindings use and
+//! report every failure as an `Error` instead of a bool or a panic. None of it needs pyo3, so it
+//! builds with `default-features = false`.
+//!
+//! ```
+//! use redoxql::api::Database;
+//!
+//! let mut db = Database::new();
+//! let grades = db.create_table("Grades", 3, 0).unwrap();
+//!
+//! let mut query = grades.query();
+//! query.insert(&[1, 90, 80]).unwrap();
+//! query.update(1, &[None, Some(95), None]).unwrap();
+//!
+//! let rows = query.select(1, 0, &[1, 1, 1]).unwrap();
+//! assert_eq!(rows[0], vec![Some(1), Some(95), Some(80)]);
+//! ```
+
+use super::database::{DatabaseError, RDatabase};
+use super::operation::OperationError;
+use super::query::RQuery;
+use super::record::RReturnRecord;
+use super::sql::SqlError;
+use super::table::{RTableHandle, SchemaError};
+use super::transaction::RTransaction;
+use std::fmt;
+
+pub use super::lock_manager::DeadlockStrategy;
+pub use super::operation::Operation;
+pub use super::sql::SqlOutput;
+pub use super::table::TableDescription;
+pub use super::transaction::QueryResult;
+
+/// Everything that can go wrong when using the database from Rust
+#[derive(Debug)]
+pub enum Error {
+    Database(DatabaseError),
+    Schema(SchemaError),
+    Operation(OperationError),
+    /// A statement of `Database::execute` is not valid SQL or does not fit its table
+    Sql(SqlError),
+    /// A record with this primary key already exists
+    DuplicateKey(i64),
+    /// There is no record with this primary key
+    NoSuchRecord(i64),
+    /// `projected` needs an entry for every column of the table
+    WrongProjection {
+        expected: usize,
+        found: usize,
+    },
+    /// The transaction has not begun, or was already committed or aborted
+    NotActive(i64),
+    /// `begin` was called twice
+    AlreadyActive(i64),
+    NoSuchSavepoint(String),
+    /// A query of an interactive transaction failed, `aborted` says if the transaction ended
+    QueryFailed {
+        operation: &'static str,
+        aborted: bool,
+    },
+    /// A query of `Transaction::run` failed or a write could not be applied at commit, so none
+    /// of them were applied
+    Aborted(i64),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Database(err) => write!(f, "{}", err),
+            Error::Schema(err) => write!(f, "{}", err),
+            Error::Operation(err) => write!(f, "{}", err),
+            Error::Sql(err) => write!(f, "{}", err),
+            Error::DuplicateKey(key) => write!(f, "A record with key {} already exists", key),
+            Error::NoSuchRecord(key) => write!(f, "No record with key {}", key),
+            Error::WrongProjection { expected, found } => write!(
+                f,
+                "Projection has {} columns but the table has {}",
+                found, expected
+            ),
+            Error::NotActive(id) => write!(f, "Transaction {} is not active", id),
+            Error::AlreadyActive(id) => write!(f, "Transaction {} has already begun", id),
+            Error::NoSuchSavepoint(name) => write!(f, "No savepoint named {}", name),
+            Error::QueryFailed { operation, aborted } => {
+                write!(f, "{} failed", operation)?;
+                if *aborted {
+                    write!(f, ", the transaction was aborted")?;
+                }
+                Ok(())
+            }
+            Error::Aborted(id) => write!(f, "Transaction {} aborted", id),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<DatabaseError> for Error {
+    fn from(err: DatabaseError) -> Self {
+        Error::Database(err)
+    }
+}
+
+impl From<SqlError> for Error {
+    fn from(err: SqlError) -> Self {
+        match err {
+            SqlError::Database(err) => Error::Database(err),
+            SqlError::DuplicateKey(key) => Error::DuplicateKey(key),
+            SqlError::NoSuchRecord(key) => Error::NoSuchRecord(key),
+            _ => Error::Sql(err),
+        }
+    }
+}
+
+impl From<SchemaError> for Error {
+    fn from(err: SchemaError) -> Self {
+        Error::Schema(err)
+    }
+}
+
+impl From<OperationError> for Error {
+    fn from(err: OperationError) -> Self {
+        Error::Operation(err)
+    }
+}
+
+/// A set of tables that is saved to and loaded from one manifest file
+#[derive(Default)]
+pub struct Database {
+    inner: RDatabase,
+}
+
+impl Database {
+    /// A database that only lives in memory until `close` is given a path with `open`
+    pub fn new() -> Self {
+        Database {
+            inner: RDatabase::new(),
+        }
+    }
+
+    /// Load the database at `path`, a path without a manifest yet gives an empty database
+    pub fn open(path: &str) -> Result<Self> {
+        let mut db = Database::new();
+        db.inner.open(path.to_string())?;
+        Ok(db)
+    }
+
+    /// Save every table into a new snapshot, does nothing if the database was never opened
+    pub fn close(&mut self) -> Result<()> {
+        Ok(self.inner.close()?)
+    }
+
+    /// Fails if the name is taken or the primary key column is not one of the columns
+    pub fn create_table(
+        &mut self,
+        name: &str,
+        num_columns: usize,
+        primary_key_column: usize,
+    ) -> Result<Table> {
+        let handle = self.inner.create_table(
+            name.to_string(),
+            num_columns as i64,
+            primary_key_column as i64,
+        )?;
+        Ok(Table { handle })
+    }
+
+    pub fn table(&self, name: &str) -> Result<Table> {
+        let handle = self.inner.get_table(name.to_string())?;
+        Ok(Table { handle })
+    }
+
+    pub fn table_by_id(&self, table_id: i64) -> Result<Table> {
+        let handle = self.inner.get_table_by_id(table_id)?;
+        Ok(Table { handle })
+    }
+
+    pub fn drop_table(&mut self, name: &str) -> Result<()> {
+        Ok(self.inner.drop_table(name.to_string())?)
+    }
+
+    pub fn rename_table(&mut self, name: &str, new_name: &str) -> Result<()> {
+        Ok(self
+            .inner
+            .rename_table(name.to_string(), new_name.to_string())?)
+    }
+
+    pub fn list_tables(&self) -> Vec<String> {
+        self.inner.list_tables()
+    }
+
+    pub fn describe_table(&self, name: &str) -> Result<TableDescription> {
+        Ok(self.inner.describe_table(name.to_string())?)
+    }
+
+    /// Run one SQL statement, see the `sql` module for the dialect
+    pub fn execute(&mut self, sql: &str) -> Result<SqlOutput> {
+        Ok(self.inner.execute(sql)?)
+    }
+
+    /// What transactions on any table of this database do when a lock they want is taken
+    pub fn set_deadlock_strategy(&self, strategy: DeadlockStrategy) {
+        self.inner.lock_manager.set_strategy(strategy);
+    }
+
+    /// The database the Python bindings wrap, for anything this API does not cover
+    pub fn inner(&mut self) -> &mut RDatabase {
+        &mut self.inner
+    }
+}
+
+/// A table of a `Database`, cloning it gives another handle to the same table
+#[derive(Clone)]
+pub struct Table {
+    handle: RTableHandle,
+}
+
+impl Table {
+    pub fn name(&self) -> String {
+        self.handle.get_name()
+    }
+
+    pub fn id(&self) -> i64 {
+        self.handle.get_table_id()
+    }
+
+    pub fn num_columns(&self) -> usize {
+        self.handle.get_num_columns()
+    }
+
+    pub fn primary_key_column(&self) -> usize {
+        self.handle.get_primary_key_column()
+    }
+
+    /// Records that can be selected right now
+    pub fn num_records(&self) -> usize {
+        self.handle.get_num_live_records()
+    }
+
+    pub fn create_index(&self, column: usize) -> Result<()> {
+        self.check_column(column)?;
+        self.handle.index().create_index(column as i64);
+        Ok(())
+    }
+
+    pub fn drop_index(&self, column: usize) -> Result<()> {
+        self.check_column(column)?;
+        self.handle.index().drop_index(column as i64);
+        Ok(())
+    }
+
+    /// Add a column at the end with `default` in every existing record, returns its index
+    pub fn add_column(&self, default: i64) -> usize {
+        self.handle.add_column(default)
+    }
+
+    pub fn drop_column(&self, column: usize) -> Result<()> {
+        Ok(self.handle.drop_column(column)?)
+    }
+
+    /// `order[i]` is the current index of the column that goes to position i
+    pub fn reorder_columns(&self, order: &[usize]) -> Result<()> {
+        Ok(self.handle.reorder_columns(order)?)
+    }
+
+    /// Run queries on this table outside of a transaction
+    pub fn query(&self) -> Query {
+        Query {
+            inner: RQuery::new(self.handle.clone()),
+            table: self.clone(),
+        }
+    }
+
+    /// The handle the Python bindings use, for anything this API does not cover
+    pub fn handle(&self) -> &RTableHandle {
+        &self.handle
+    }
+
+    fn contains(&self, primary_key: i64) -> bool {
+        self.handle.read(primary_key).is_some()
+    }
+
+    fn check_column(&self, column: usize) -> Result<()> {
+        if column >= self.num_columns() {
+            return Err(Error::Schema(SchemaError::NoSuchColumn(column)));
+        }
+        Ok(())
+    }
+
+    fn check_projection(&self, projected: &[i64]) -> Result<()> {
+        if projected.len() != self.num_columns() {
+            return Err(Error::WrongProjection {
+                expected: self.num_columns(),
+                found: projected.len(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// The columns of every record a select found
+fn into_rows(records: Option<Vec<Option<RReturnRecord>>>) -> Vec<Vec<Option<i64>>> {
+    records
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .map(|r| r.columns)
+        .collect()
+}
+
+/// Reads and writes on one table, each one is applied right away
+pub struct Query {
+    inner: RQuery,
+    table: Table,
+}
+
+impl Query {
+    /// Insert a record, `values` has a value for every column
+    pub fn insert(&mut self, values: &[i64]) -> Result<()> {
+        if values.len() != self.table.num_columns() {
+            return Err(Error::WrongProjection {
+                expected: self.table.num_columns(),
+                found: values.len(),
+            });
+        }
+
+        if !self.inner.insert(values.to_vec()) {
+            return Err(Error::DuplicateKey(values[self.table.primary_key_column()]));
+        }
+        Ok(())
+    }
+
+    /// Set the columns that are Some, changing the primary key moves the record to the new key
+    pub fn update(&mut self, primary_key: i64, columns: &[Option<i64>]) -> Result<()> {
+        if columns.len() != self.table.num_columns() {
+            return Err(Error::WrongProjection {
+                expected: self.table.num_columns(),
+                found: columns.len(),
+            });
+        }
+
+        if self.inner.update(primary_key, columns.to_vec()) {
+            return Ok(());
+        }
+
+        // The update checks both under the table lock, this only tells which one failed
+        let new_key = columns[self.table.primary_key_column()].unwrap_or(primary_key);
+        if new_key != primary_key && self.table.contains(new_key) {
+            return Err(Error::DuplicateKey(new_key));
+        }
+        Err(Error::NoSuchRecord(primary_key))
+    }
+
+    pub fn delete(&mut self, primary_key: i64) -> Result<()> {
+        if !self.inner.delete(primary_key) {
+            return Err(Error::NoSuchRecord(primary_key));
+        }
+        Ok(())
+    }
+
+    /// Add one to a column of a record
+    pub fn increment(&mut self, primary_key: i64, column: usize) -> Result<()> {
+        self.table.check_column(column)?;
+
+        if !self.inner.increment(primary_key, column as i64) {
+            return Err(Error::NoSuchRecord(primary_key));
+        }
+        Ok(())
+    }
+
+    /// Every record whose `column` is `key`, with None in the columns that are not projected
+    ///
+    /// `projected` has a 1 for every column to return and a 0 for the others.
+    pub fn select(
+        &mut self,
+        key: i64,
+        column: usize,
+        projected: &[i64],
+    ) -> Result<Vec<Vec<Option<i64>>>> {
+        self.table.check_column(column)?;
+        self.table.check_projection(projected)?;
+
+        let records = self.inner.select(key, column as i64, projected.to_vec());
+        Ok(into_rows(records))
+    }
+
+    /// An older version of the record with this primary key, -1 is the one before the newest
+    pub fn select_version(
+        &mut self,
+        primary_key: i64,
+        projected: &[i64],
+        relative_version: i64,
+    ) -> Result<Vec<Vec<Option<i64>>>> {
+        self.table.check_projection(projected)?;
+
+        let column = self.table.primary_key_column() as i64;
+        let records =
+            self.inner
+                .select_version(primary_key, column, projected.to_vec(), relative_version);
+        Ok(into_rows(records))
+    }
+
+    /// The sum of `column` over the records with a primary key from `start` to `end`, both included
+    pub fn sum(&mut self, start: i64, end: i64, column: usize) -> Result<i64> {
+        self.sum_version(start, end, column, 0)
+    }
+
+    pub fn sum_version(
+        &mut self,
+        start: i64,
+        end: i64,
+        column: usize,
+        relative_version: i64,
+    ) -> Result<i64> {
+        self.table.check_column(column)?;
+
+        Ok(if relative_version == 0 {
+            self.inner.sum(start, end, column as i64)
+        } else {
+            self.inner
+                .sum_version(start, end, column as i64, relative_version)
+        })
+    }
+}
+
+/// Queries that either all happen or none of them do
+///
+/// Queue queries with `add` and apply them with `run`, or `begin` an interactive transaction
+/// that runs each query with `execute` until `commit` or `abort`.
+#[derive(Default)]
+pub struct Transaction {
+    inner: RTransaction,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Transaction {
+            inner: RTransaction::new(),
+        }
+    }
+
+    /// Smaller ids are older, the deadlock strategies compare them
+    pub fn id(&self) -> i64 {
+        self.inner.transaction_id()
+    }
+
+    /// Queue a query for the next `run`
+    ///
+    /// Fails for a select on another column than the primary key, records are locked by it.
+    pub fn add(&mut self, table: &Table, op: Operation) -> Result<()> {
+        op.check_in_transaction(table.handle.get_primary_key_column())?;
+        self.inner.add_operation(table.handle.clone(), op);
+        Ok(())
+    }
+
+    /// Run the queued queries, returns what each one produced or `Error::Aborted`
+    pub fn run(&mut self) -> Result<Vec<QueryResult>> {
+        if !self.inner.run() {
+            return Err(Error::Aborted(self.id()));
+        }
+        Ok(self.inner.query_results().to_vec())
+    }
+
+    pub fn begin(&mut self) -> Result<()> {
+        if self.inner.active() {
+            return Err(Error::AlreadyActive(self.id()));
+        }
+
+        self.inner.begin_now();
+        Ok(())
+    }
+
+    /// Run one query of an interactive transaction right away
+    ///
+    /// A failed query aborts the transaction, unless a savepoint is set and the query did not
+    /// fail on a lock. A query `add` would refuse is refused without running or aborting.
+    pub fn execute(&mut self, table: &Table, op: Operation) -> Result<QueryResult> {
+        if !self.inner.active() {
+            return Err(Error::NotActive(self.id()));
+        }
+
+        op.check_in_transaction(table.handle.get_primary_key_column())?;
+
+        let operation = op.name();
+        self.inner
+            .execute_now(table.handle.clone(), op)
+            .ok_or_else(|| Error::QueryFailed {
+                operation,
+                aborted: !self.inner.active(),
+            })
+    }
+
+    pub fn savepoint(&mut self, name: &str) -> Result<()> {
+        if !self.inner.savepoint_now(name) {
+            return Err(Error::NotActive(self.id()));
+        }
+        Ok(())
+    }
+
+    /// Undo everything done since the savepoint, the savepoint itself stays
+    pub fn rollback_to_savepoint(&mut self, name: &str) -> Result<()> {
+        if !self.inner.rollback_to_savepoint_now(name) {
+            return Err(Error::NoSuchSavepoint(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Forget the savepoint and every one set after it, their writes stay
+    pub fn release_savepoint(&mut self, name: &str) -> Result<()> {
+        if !self.inner.release_savepoint_now(name) {
+            return Err(Error::NoSuchSavepoint(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Apply the writes of an interactive transaction and release its locks, fails with
+    /// `Error::Aborted` if a write could not be applied, then none of them are
+    pub fn commit(&mut self) -> Result<()> {
+        if !self.inner.active() {
+            return Err(Error::NotActive(self.id()));
+        }
+
+        if !self.inner.commit() {
+            return Err(Error::Aborted(self.id()));
+        }
+        Ok(())
+    }
+
+    /// Drop the writes of an interactive transaction and release its locks
+    pub fn abort(&mut self) -> Result<()> {
+        if !self.inner.active() {
+            return Err(Error::NotActive(self.id()));
+        }
+
+        self.inner.abort();
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.inner.active()
+    }
+
+    /// What every query produced so far, or in the last `run`
+    pub fn results(&self) -> &[QueryResult] {
+        self.inner.query_results()
+    }
+}
@@ -0,0 +1,44 @@
+use redoxql::api::Database;
+use redoxql::server::Server;
+use std::process::ExitCode;
+
+/// `redoxql-server <address> [path]` serves the database saved at path, or an in-memory one
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(addr) = args.next() else {
+        eprintln!("Usage: redoxql-server <address> [path]");
+        return ExitCode::FAILURE;
+    };
+
+    let db = match args.next() {
+        Some(path) => Database::open(&path),
+        None => Ok(Database::new()),
+    };
+    let db = match db {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("Could not open the database: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let server = match Server::bind(&addr, db) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Could not listen on {}: {}", addr, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Ok(addr) = server.local_addr() {
+        println!("Serving on {}", addr);
+    }
+
+    // Runs until a client sends shutdown, which also saves the database
+    if let Err(e) = server.run() {
+        eprintln!("{}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
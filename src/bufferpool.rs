@@ -1,7 +1,7 @@
-use super::filewriter::{build_binary_writer, Writer};
-use super::page::PhysicalPage;
+use super::filewriter::{build_binary_writer, FileError, Writer};
+use super::page::PageHandle;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
+use std::io;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct BufferPool {
@@ -16,22 +16,20 @@ impl BufferPool {
         }
     }
 
-    pub fn write_page(page: Arc<Mutex<PhysicalPage>>, value: i64) {
-        let mut m = page.lock().unwrap();
-        m.write(value);
+    pub fn write_page(page: PageHandle, value: i64) {
+        page.write(value);
     }
 
-    pub fn read_page(page: Arc<Mutex<PhysicalPage>>, offset: i64) -> Option<i64> {
-        let m = page.lock().unwrap();
-        return m.read(offset as usize);
+    pub fn read_page(page: PageHandle, offset: i64) -> Option<i64> {
+        page.read(offset as usize)
     }
 
-    pub fn save_state(&self) {
+    pub fn save_state(&self) -> io::Result<()> {
         let writer: Writer<BufferPool> = build_binary_writer();
-        writer.write_file("./redoxdata/bufferpull.data", self);
+        writer.write_file("./redoxdata/bufferpull.data", self)
     }
 
-    pub fn load_state(&self, _directory: &str) -> BufferPool {
+    pub fn load_state(&self, _directory: &str) -> Result<BufferPool, FileError> {
         let writer: Writer<BufferPool> = build_binary_writer();
         writer.read_file("./redoxdata/bufferpull.data")
     }
@@ -45,9 +43,9 @@ mod tests {
     fn load_and_save_test() {
         let b = BufferPool::new("/data");
 
-        b.save_state();
+        b.save_state().unwrap();
 
-        let new_b = b.load_state("/data");
+        let new_b = b.load_state("/data").unwrap();
 
         assert_eq!(
             b.physical_directory.to_string(),
@@ -56,6 +54,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn bufferpool_test() {
         assert!(true);
     }
@@ -0,0 +1,698 @@
+//! Importing and exporting whole tables as CSV or JSON Lines
+//!
+//! Columns are called by position, `c0`, `c1`, ..., like in `sql`, unless the options map other
+//! names to them. Every value is an integer. A CSV field may be quoted but can't hold the
+//! delimiter. A JSON line is either an object of column names to values or an array with a value
+//! for every column.
+
+use super::container::NUM_RESERVED_COLUMNS;
+use super::mvcc;
+use super::sql::column_index;
+use super::table::RTableHandle;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+/// What an import does with a record whose primary key is taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDuplicate {
+    /// Leave the record out and go on
+    Skip,
+    /// Stop the import, the batch with the duplicate is not inserted
+    Abort,
+}
+
+impl OnDuplicate {
+    /// Parse the names Python uses: "skip" and "abort"
+    pub fn from_name(name: &str) -> Option<OnDuplicate> {
+        match name {
+            "skip" => Some(OnDuplicate::Skip),
+            "abort" => Some(OnDuplicate::Abort),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Separates the fields of a CSV line
+    pub delimiter: char,
+    /// The first CSV line names the columns of the fields, without it the fields are in column order
+    pub has_header: bool,
+    /// Which column each header name or JSON key goes to, names that are not in it are ignored
+    pub columns: Option<HashMap<String, usize>>,
+    /// Records inserted under one lock of the table
+    pub batch_size: usize,
+    pub on_duplicate: OnDuplicate,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            delimiter: ',',
+            has_header: true,
+            columns: None,
+            batch_size: 1000,
+            on_duplicate: OnDuplicate::Abort,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Separates the fields of a CSV line
+    pub delimiter: char,
+    /// Start a CSV file with a line of column names
+    pub has_header: bool,
+    /// The name of every column, `c0`, `c1`, ... by default
+    pub columns: Option<Vec<String>>,
+    /// 0 exports the newest version of every record, -1 the one before it and so on
+    pub relative_version: i64,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions {
+            delimiter: ',',
+            has_header: true,
+            columns: None,
+            relative_version: 0,
+        }
+    }
+}
+
+/// What an import did
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub inserted: usize,
+    /// Records left out because their primary key was taken, only with `OnDuplicate::Skip`
+    pub skipped: usize,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl ImportSummary {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// Why an import or export stopped, lines count from 1
+#[derive(Debug)]
+pub enum BulkError {
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    /// A line is not a record of the table
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// With `OnDuplicate::Abort`, the batches before the one with this line were inserted
+    DuplicateKey {
+        line: usize,
+        key: i64,
+    },
+    /// The header or the options name a column the table does not have
+    NoSuchColumn(String),
+    /// Nothing in the file or the options goes to this column
+    MissingColumn(usize),
+}
+
+impl fmt::Display for BulkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BulkError::Io { path, error } => write!(f, "{}: {}", path, error),
+            BulkError::Parse { line, message } => write!(f, "Line {}: {}", line, message),
+            BulkError::DuplicateKey { line, key } => {
+                write!(f, "Line {}: a record with key {} already exists", line, key)
+            }
+            BulkError::NoSuchColumn(name) => write!(f, "No column {}", name),
+            BulkError::MissingColumn(column) => write!(f, "Nothing goes to column {}", column),
+        }
+    }
+}
+
+impl std::error::Error for BulkError {}
+
+#[cfg(feature = "python")]
+impl From<BulkError> for PyErr {
+    fn from(err: BulkError) -> PyErr {
+        match err {
+            BulkError::Io { .. } => pyo3::exceptions::PyIOError::new_err(err.to_string()),
+            _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+fn io_err(path: &str, error: io::Error) -> BulkError {
+    BulkError::Io {
+        path: path.to_string(),
+        error,
+    }
+}
+
+fn parse_err<T>(line: usize, message: impl Into<String>) -> Result<T, BulkError> {
+    Err(BulkError::Parse {
+        line,
+        message: message.into(),
+    })
+}
+
+// The column a header name or JSON key goes to, None if the mapping leaves it out
+fn target(
+    name: &str,
+    columns: &Option<HashMap<String, usize>>,
+    num_columns: usize,
+) -> Result<Option<usize>, BulkError> {
+    let column = match columns {
+        Some(columns) => match columns.get(name) {
+            Some(&column) => column,
+            None => return Ok(None),
+        },
+        None => column_index(name).ok_or_else(|| BulkError::NoSuchColumn(name.to_string()))?,
+    };
+
+    if column >= num_columns {
+        return Err(BulkError::NoSuchColumn(name.to_string()));
+    }
+    Ok(Some(column))
+}
+
+fn number(line: usize, field: &str) -> Result<i64, BulkError> {
+    let field = field.trim();
+    let field = field
+        .strip_prefix('"')
+        .and_then(|f| f.strip_suffix('"'))
+        .unwrap_or(field);
+
+    match field.parse() {
+        Ok(n) => Ok(n),
+        Err(_) => parse_err(line, format!("{:?} is not an integer", field)),
+    }
+}
+
+// Inserts records in batches, each under one write lock of the table
+struct Loader<'a> {
+    table: &'a RTableHandle,
+    options: &'a ImportOptions,
+    // Each record with the line it came from
+    batch: Vec<(usize, Vec<i64>)>,
+    summary: ImportSummary,
+}
+
+impl Loader<'_> {
+    fn push(&mut self, line: usize, values: Vec<i64>) -> Result<(), BulkError> {
+        self.batch.push((line, values));
+        if self.batch.len() >= self.options.batch_size.max(1) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), BulkError> {
+        let writer = self.write_batch()?;
+        self.table.publish(writer);
+        Ok(())
+    }
+
+    // Write the batch, returns the writer the caller commits once the table is unlocked
+    fn write_batch(&mut self) -> Result<i64, BulkError> {
+        let mut table = self.table.table.write().unwrap();
+        let writer = mvcc::autocommit_writer();
+        let primary_key_column = table.primary_key_column;
+
+        // Check the whole batch first, so a failing one leaves the table as it was
+        if self.options.on_duplicate == OnDuplicate::Abort {
+            let index = table.index.read().unwrap();
+            let mut keys = HashSet::new();
+
+            for (line, values) in &self.batch {
+                let key = values[primary_key_column];
+                if index.get(key).is_some() || !keys.insert(key) {
+                    return Err(BulkError::DuplicateKey { line: *line, key });
+                }
+            }
+        }
+
+        for (_, values) in self.batch.drain(..) {
+            let taken = {
+                let index = table.index.read().unwrap();
+                index.get(values[primary_key_column]).is_some()
+            };
+
+            if taken {
+                self.summary.skipped += 1;
+            } else {
+                let record = table.write(values);
+                table.versions.record_write(record.rid, writer);
+                self.summary.inserted += 1;
+            }
+        }
+
+        Ok(writer)
+    }
+}
+
+fn open(path: &str) -> Result<io::Lines<BufReader<File>>, BulkError> {
+    let file = File::open(path).map_err(|e| io_err(path, e))?;
+    Ok(BufReader::new(file).lines())
+}
+
+/// Insert every line of a CSV file as a record
+pub fn import_csv(
+    table: &RTableHandle,
+    path: &str,
+    options: &ImportOptions,
+) -> Result<ImportSummary, BulkError> {
+    let num_columns = table.get_num_columns();
+    let mut lines = open(path)?.enumerate();
+
+    // The column of every field
+    let mut fields: Vec<Option<usize>> = (0..num_columns).map(Some).collect();
+    if options.has_header {
+        let header = match lines.next() {
+            Some((_, line)) => line.map_err(|e| io_err(path, e))?,
+            None => return Ok(ImportSummary::default()),
+        };
+
+        fields = header
+            .split(options.delimiter)
+            .map(|name| target(name.trim().trim_matches('"'), &options.columns, num_columns))
+            .collect::<Result<_, _>>()?;
+
+        for column in 0..num_columns {
+            match fields.iter().filter(|&&f| f == Some(column)).count() {
+                0 => return Err(BulkError::MissingColumn(column)),
+                1 => {}
+                _ => return parse_err(1, format!("Two fields go to column {}", column)),
+            }
+        }
+    }
+
+    let mut loader = Loader {
+        table,
+        options,
+        batch: Vec::new(),
+        summary: ImportSummary::default(),
+    };
+
+    for (i, line) in lines {
+        let line = line.map_err(|e| io_err(path, e))?;
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let values: Vec<&str> = line.split(options.delimiter).collect();
+        if values.len() != fields.len() {
+            return parse_err(
+                line_number,
+                format!(
+                    "Expected {} fields but found {}",
+                    fields.len(),
+                    values.len()
+                ),
+            );
+        }
+
+        let mut record = vec![0; num_columns];
+        for (field, value) in fields.iter().zip(values) {
+            if let Some(column) = field {
+                record[*column] = number(line_number, value)?;
+            }
+        }
+
+        loader.push(line_number, record)?;
+    }
+
+    loader.flush()?;
+    Ok(loader.summary)
+}
+
+/// Insert every line of a JSON Lines file as a record
+pub fn import_jsonl(
+    table: &RTableHandle,
+    path: &str,
+    options: &ImportOptions,
+) -> Result<ImportSummary, BulkError> {
+    let num_columns = table.get_num_columns();
+    let mut loader = Loader {
+        table,
+        options,
+        batch: Vec::new(),
+        summary: ImportSummary::default(),
+    };
+
+    for (i, line) in open(path)?.enumerate() {
+        let line = line.map_err(|e| io_err(path, e))?;
+        let line_number = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let value: Value = match serde_json::from_str(&line) {
+            Ok(value) => value,
+            Err(e) => return parse_err(line_number, e.to_string()),
+        };
+
+        let mut record: Vec<Option<i64>> = vec![None; num_columns];
+        match value {
+            Value::Object(object) => {
+                for (name, value) in object {
+                    if let Some(column) = target(&name, &options.columns, num_columns)? {
+                        record[column] = value.as_i64();
+                        if record[column].is_none() {
+                            return parse_err(line_number, format!("{} is not an integer", value));
+                        }
+                    }
+                }
+            }
+            Value::Array(values) if values.len() == num_columns => {
+                for (column, value) in values.iter().enumerate() {
+                    record[column] = value.as_i64();
+                    if record[column].is_none() {
+                        return parse_err(line_number, format!("{} is not an integer", value));
+                    }
+                }
+            }
+            Value::Array(values) => {
+                return parse_err(
+                    line_number,
+                    format!("Expected {} values but found {}", num_columns, values.len()),
+                );
+            }
+            _ => return parse_err(line_number, "Expected an object or an array"),
+        }
+
+        let Some(record) = record.iter().copied().collect::<Option<Vec<i64>>>() else {
+            let column = record.iter().position(Option::is_none).unwrap();
+            return parse_err(line_number, format!("No value for column {}", column));
+        };
+
+        loader.push(line_number, record)?;
+    }
+
+    loader.flush()?;
+    Ok(loader.summary)
+}
+
+// The name of every column on export
+fn column_names(options: &ExportOptions, num_columns: usize) -> Result<Vec<String>, BulkError> {
+    let Some(names) = &options.columns else {
+        return Ok((0..num_columns).map(|i| format!("c{}", i)).collect());
+    };
+
+    if names.len() < num_columns {
+        return Err(BulkError::MissingColumn(names.len()));
+    }
+    if names.len() > num_columns {
+        return Err(BulkError::NoSuchColumn(names[num_columns].clone()));
+    }
+    Ok(names.clone())
+}
+
+// Write the chosen version of every record in primary key order, returns how many there were
+fn export(
+    table: &RTableHandle,
+    path: &str,
+    relative_version: i64,
+    mut write_header: impl FnMut(&mut BufWriter<File>) -> io::Result<()>,
+    mut write_record: impl FnMut(&mut BufWriter<File>, &[i64]) -> io::Result<()>,
+) -> Result<usize, BulkError> {
+    let file = File::create(path).map_err(|e| io_err(path, e))?;
+    let mut out = BufWriter::new(file);
+    write_header(&mut out).map_err(|e| io_err(path, e))?;
+
+    let table = table.table.read().unwrap();
+    let keys: Vec<i64> = table.index.read().unwrap().index.keys().copied().collect();
+
+    let mut count = 0;
+    for key in keys {
+        let Some(row) = table.read_relative(key, relative_version) else {
+            continue;
+        };
+
+        write_record(&mut out, &row[NUM_RESERVED_COLUMNS as usize..])
+            .map_err(|e| io_err(path, e))?;
+        count += 1;
+    }
+
+    out.flush().map_err(|e| io_err(path, e))?;
+    Ok(count)
+}
+
+/// Write every record as a line of a CSV file, returns how many were written
+pub fn export_csv(
+    table: &RTableHandle,
+    path: &str,
+    options: &ExportOptions,
+) -> Result<usize, BulkError> {
+    let names = column_names(options, table.get_num_columns())?;
+    let delimiter = options.delimiter.to_string();
+
+    export(
+        table,
+        path,
+        options.relative_version,
+        |out| {
+            if options.has_header {
+                writeln!(out, "{}", names.join(&delimiter))?;
+            }
+            Ok(())
+        },
+        |out, values| {
+            let fields: Vec<String> = values.iter().map(i64::to_string).collect();
+            writeln!(out, "{}", fields.join(&delimiter))
+        },
+    )
+}
+
+/// Write every record as a JSON object on its own line, returns how many were written
+pub fn export_jsonl(
+    table: &RTableHandle,
+    path: &str,
+    options: &ExportOptions,
+) -> Result<usize, BulkError> {
+    let names = column_names(options, table.get_num_columns())?;
+
+    export(
+        table,
+        path,
+        options.relative_version,
+        |_| Ok(()),
+        |out, values| {
+            // Written by hand to keep the keys in column order
+            let fields: Vec<String> = names
+                .iter()
+                .zip(values)
+                .map(|(name, value)| format!("{}:{}", Value::from(name.as_str()), value))
+                .collect();
+            writeln!(out, "{{{}}}", fields.join(","))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RDatabase;
+    use crate::query::RQuery;
+    use std::fs;
+
+    fn write(name: &str, contents: &str) -> String {
+        fs::create_dir_all("./test-outputs").unwrap();
+        let path = format!("./test-outputs/{}", name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn grades(db: &mut RDatabase, name: &str) -> RTableHandle {
+        db.create_table(name.to_string(), 3, 0).unwrap()
+    }
+
+    #[test]
+    fn csv_round_trip_test() {
+        let mut db = RDatabase::new();
+        let table = grades(&mut db, "Grades");
+        let path = write("bulk-grades.csv", "c2,c0,c1\n80,1,90\n\"60\", 2 ,70\n\n");
+
+        let summary = table.import_csv(&path, &ImportOptions::default()).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                inserted: 2,
+                skipped: 0
+            }
+        );
+        assert_eq!(
+            table.read(2).unwrap()[NUM_RESERVED_COLUMNS as usize..],
+            [2, 70, 60]
+        );
+
+        RQuery::new(table.clone()).update(1, vec![None, Some(95), None]);
+
+        let options = ExportOptions {
+            delimiter: ';',
+            ..ExportOptions::default()
+        };
+        let out = "./test-outputs/bulk-grades-out.csv";
+        assert_eq!(table.export_csv(out, &options).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(out).unwrap(),
+            "c0;c1;c2\n1;95;80\n2;70;60\n"
+        );
+
+        // The version before the update
+        let options = ExportOptions {
+            has_header: false,
+            relative_version: -1,
+            ..ExportOptions::default()
+        };
+        table.export_csv(out, &options).unwrap();
+        assert_eq!(fs::read_to_string(out).unwrap(), "1,90,80\n2,70,60\n");
+
+        // What was exported without a header imports without one
+        let copy = grades(&mut db, "Copy");
+        let options = ImportOptions {
+            has_header: false,
+            ..ImportOptions::default()
+        };
+        assert_eq!(copy.import_csv(out, &options).unwrap().inserted, 2);
+        assert_eq!(
+            copy.read(1).unwrap()[NUM_RESERVED_COLUMNS as usize..],
+            [1, 90, 80]
+        );
+    }
+
+    #[test]
+    fn jsonl_round_trip_test() {
+        let mut db = RDatabase::new();
+        let table = grades(&mut db, "Grades");
+        let path = write(
+            "bulk-grades.jsonl",
+            "{\"id\": 1, \"math\": 90, \"art\": 80, \"note\": \"ignored\"}\n[2, 70, 60]\n",
+        );
+
+        let options = ImportOptions {
+            columns: Some(HashMap::from([
+                (String::from("id"), 0),
+                (String::from("math"), 1),
+                (String::from("art"), 2),
+            ])),
+            ..ImportOptions::default()
+        };
+        assert_eq!(table.import_jsonl(&path, &options).unwrap().inserted, 2);
+
+        let out = "./test-outputs/bulk-grades-out.jsonl";
+        let options = ExportOptions {
+            columns: Some(vec![
+                String::from("id"),
+                String::from("math"),
+                String::from("art"),
+            ]),
+            ..ExportOptions::default()
+        };
+        assert_eq!(table.export_jsonl(out, &options).unwrap(), 2);
+        assert_eq!(
+            fs::read_to_string(out).unwrap(),
+            "{\"id\":1,\"math\":90,\"art\":80}\n{\"id\":2,\"math\":70,\"art\":60}\n"
+        );
+
+        let copy = grades(&mut db, "Copy");
+        table.export_jsonl(out, &ExportOptions::default()).unwrap();
+        assert_eq!(
+            copy.import_jsonl(out, &ImportOptions::default())
+                .unwrap()
+                .inserted,
+            2
+        );
+        assert_eq!(
+            copy.read(2).unwrap()[NUM_RESERVED_COLUMNS as usize..],
+            [2, 70, 60]
+        );
+    }
+
+    #[test]
+    fn duplicates_test() {
+        let mut db = RDatabase::new();
+        let table = grades(&mut db, "Grades");
+        RQuery::new(table.clone()).insert(vec![3, 0, 0]);
+        let path = write("bulk-duplicates.csv", "1,1,1\n2,2,2\n3,3,3\n4,4,4\n4,5,5\n");
+
+        let skip = ImportOptions {
+            has_header: false,
+            on_duplicate: OnDuplicate::Skip,
+            ..ImportOptions::default()
+        };
+        let copy = grades(&mut db, "Skip");
+        RQuery::new(copy.clone()).insert(vec![3, 0, 0]);
+        assert_eq!(
+            copy.import_csv(&path, &skip).unwrap(),
+            ImportSummary {
+                inserted: 3,
+                skipped: 2
+            }
+        );
+        // The first record with a key wins
+        assert_eq!(
+            copy.read(4).unwrap()[NUM_RESERVED_COLUMNS as usize..],
+            [4, 4, 4]
+        );
+
+        // Only the first batch goes in, the second one has the taken key 3
+        let abort = ImportOptions {
+            has_header: false,
+            batch_size: 2,
+            ..ImportOptions::default()
+        };
+        assert!(matches!(
+            table.import_csv(&path, &abort),
+            Err(BulkError::DuplicateKey { line: 3, key: 3 })
+        ));
+        assert!(table.read(2).is_some());
+        assert!(table.read(4).is_none());
+    }
+
+    #[test]
+    fn bad_input_test() {
+        let mut db = RDatabase::new();
+        let table = grades(&mut db, "Grades");
+        let options = ImportOptions::default();
+
+        let path = write("bulk-bad-1.csv", "c0,c1\n1,2\n");
+        assert!(matches!(
+            table.import_csv(&path, &options),
+            Err(BulkError::MissingColumn(2))
+        ));
+
+        let path = write("bulk-bad-2.csv", "c0,c1,c5\n1,2,3\n");
+        assert!(matches!(
+            table.import_csv(&path, &options),
+            Err(BulkError::NoSuchColumn(name)) if name == "c5"
+        ));
+
+        let path = write("bulk-bad-3.csv", "c0,c1,c2\n1,2,3\n4,x,6\n");
+        assert!(matches!(
+            table.import_csv(&path, &options),
+            Err(BulkError::Parse { line: 3, .. })
+        ));
+
+        let path = write("bulk-bad-4.jsonl", "{\"c0\": 1, \"c1\": 2}\n");
+        assert!(matches!(
+            table.import_jsonl(&path, &options),
+            Err(BulkError::Parse { line: 1, .. })
+        ));
+
+        assert!(matches!(
+            table.import_csv("./test-outputs/missing.csv", &options),
+            Err(BulkError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+/// Lookup table for the CRC-32 (IEEE 802.3) polynomial, built at compile time
+const CRC32_TABLE: [u32; 256] = build_crc32_table();
+
+const fn build_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            if crc & 1 == 1 {
+                crc = (crc >> 1) ^ 0xEDB8_8320;
+            } else {
+                crc >>= 1;
+            }
+            bit += 1;
+        }
+
+        table[i] = crc;
+        i += 1;
+    }
+
+    table
+}
+
+/// Compute the CRC-32 of some bytes
+///
+/// This is the same checksum used by zip and gzip, so a file can be checked with standard tools
+/// if needed.
+///
+/// # Example
+///
+/// ```
+/// use redoxql::checksum::crc32;
+///
+/// assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+/// ```
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+
+    for &b in bytes {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_known_values_test() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"a"), 0xE8B7_BE43);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn crc32_detects_single_bit_flip_test() {
+        let data = vec![1u8, 2, 3, 4, 5, 6, 7, 8];
+        let mut flipped = data.clone();
+        flipped[3] ^= 0b0000_0100;
+
+        assert_ne!(crc32(&data), crc32(&flipped));
+    }
+}
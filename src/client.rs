@@ -0,0 +1,199 @@
+//! A client for `server`, one method per request
+//!
+//! ```no_run
+//! use redoxql::client::Client;
+//!
+//! let mut client = Client::connect("127.0.0.1:7878").unwrap();
+//! client.create_table("Grades", 3, 0).unwrap();
+//! client.insert("Grades", &[1, 90, 80]).unwrap();
+//! assert_eq!(client.select("Grades", 1, 0, &[1, 1, 1]).unwrap(), vec![vec![Some(1), Some(90), Some(80)]]);
+//! ```
+
+use super::server::{Request, Response};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// Why a request did not get an answer
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// The server could not do what was asked, holds its message
+    Server(String),
+    /// The server answered with something that is not a response to the request
+    Protocol(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "{}", err),
+            ClientError::Server(message) => write!(f, "{}", message),
+            ClientError::Protocol(message) => write!(f, "Bad response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(err: io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(err: serde_json::Error) -> Self {
+        ClientError::Protocol(err.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// One connection to a server, and with it at most one transaction
+pub struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Client> {
+        let writer = TcpStream::connect(addr)?;
+        let reader = BufReader::new(writer.try_clone()?);
+
+        Ok(Client { reader, writer })
+    }
+
+    /// Send any request and wait for what the server answers
+    pub fn request(&mut self, request: &Request) -> Result<Value> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(ClientError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        match serde_json::from_str(&line)? {
+            Response::Ok(value) => Ok(value),
+            Response::Error(message) => Err(ClientError::Server(message)),
+        }
+    }
+
+    // A request whose answer has a known type
+    fn call<T: DeserializeOwned>(&mut self, request: Request) -> Result<T> {
+        Ok(serde_json::from_value(self.request(&request)?)?)
+    }
+
+    /// Rows of a SELECT, the result of SUM, how many records changed or null
+    pub fn sql(&mut self, sql: &str) -> Result<Value> {
+        self.request(&Request::Sql {
+            sql: sql.to_string(),
+        })
+    }
+
+    pub fn create_table(
+        &mut self,
+        name: &str,
+        num_columns: usize,
+        primary_key_column: usize,
+    ) -> Result<()> {
+        self.call(Request::CreateTable {
+            name: name.to_string(),
+            num_columns,
+            primary_key_column,
+        })
+    }
+
+    pub fn drop_table(&mut self, name: &str) -> Result<()> {
+        self.call(Request::DropTable {
+            name: name.to_string(),
+        })
+    }
+
+    pub fn list_tables(&mut self) -> Result<Vec<String>> {
+        self.call(Request::ListTables)
+    }
+
+    pub fn insert(&mut self, table: &str, values: &[i64]) -> Result<()> {
+        self.call(Request::Insert {
+            table: table.to_string(),
+            values: values.to_vec(),
+        })
+    }
+
+    /// None in `columns` keeps the old value
+    pub fn update(&mut self, table: &str, primary_key: i64, columns: &[Option<i64>]) -> Result<()> {
+        self.call(Request::Update {
+            table: table.to_string(),
+            primary_key,
+            columns: columns.to_vec(),
+        })
+    }
+
+    pub fn delete(&mut self, table: &str, primary_key: i64) -> Result<()> {
+        self.call(Request::Delete {
+            table: table.to_string(),
+            primary_key,
+        })
+    }
+
+    pub fn increment(&mut self, table: &str, primary_key: i64, column: usize) -> Result<()> {
+        self.call(Request::Increment {
+            table: table.to_string(),
+            primary_key,
+            column,
+        })
+    }
+
+    /// Every record whose `column` is `key`, with None in the columns that are not projected
+    pub fn select(
+        &mut self,
+        table: &str,
+        key: i64,
+        column: usize,
+        projected: &[i64],
+    ) -> Result<Vec<Vec<Option<i64>>>> {
+        self.call(Request::Select {
+            table: table.to_string(),
+            key,
+            column,
+            projected: projected.to_vec(),
+        })
+    }
+
+    pub fn sum(&mut self, table: &str, start: i64, end: i64, column: usize) -> Result<i64> {
+        self.call(Request::Sum {
+            table: table.to_string(),
+            start,
+            end,
+            column,
+        })
+    }
+
+    /// Start a transaction, the queries of this client run in it until `commit` or `abort`
+    pub fn begin(&mut self) -> Result<i64> {
+        self.call(Request::Begin)
+    }
+
+    pub fn commit(&mut self) -> Result<()> {
+        self.call(Request::Commit)
+    }
+
+    pub fn abort(&mut self) -> Result<()> {
+        self.call(Request::Abort)
+    }
+
+    /// Have the server save the database
+    pub fn checkpoint(&mut self) -> Result<()> {
+        self.call(Request::Checkpoint)
+    }
+
+    /// Have the server save the database and stop accepting connections
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.call(Request::Shutdown)
+    }
+}
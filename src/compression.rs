@@ -0,0 +1,401 @@
+use serde::{Deserialize, Serialize};
+
+/// The ways a page of integers can be stored on disk
+///
+/// Every codec is lossless. `compress` tries all of them and keeps whichever is smallest, so each
+/// page gets the codec that suits its column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    /// Eight little endian bytes per value
+    Raw,
+    /// The first value and then the difference to the previous value, as zigzag varints.
+    /// Great for sorted keys and RIDs.
+    DeltaVarint,
+    /// (value, run length) pairs as varints. Great for columns that repeat the same value.
+    RunLength,
+    /// The minimum and then every value minus the minimum in as few bits as possible.
+    /// Great for small counters.
+    BitPacked,
+    /// The distinct values and then a bit packed index into them for every value.
+    /// Great for columns with only a few different values.
+    Dictionary,
+}
+
+/// Every codec, in the order `compress` prefers them when two are the same size
+pub const CODECS: [Codec; 5] = [
+    Codec::Raw,
+    Codec::DeltaVarint,
+    Codec::RunLength,
+    Codec::BitPacked,
+    Codec::Dictionary,
+];
+
+impl Codec {
+    pub fn encode(&self, values: &[i64]) -> Vec<u8> {
+        match self {
+            Codec::Raw => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            Codec::DeltaVarint => encode_delta(values),
+            Codec::RunLength => encode_run_length(values),
+            Codec::BitPacked => encode_bit_packed(values),
+            Codec::Dictionary => encode_dictionary(values),
+        }
+    }
+
+    /// Decode `len` values, or None if the bytes were not made by this codec
+    pub fn decode(&self, bytes: &[u8], len: usize) -> Option<Vec<i64>> {
+        let values = match self {
+            Codec::Raw => decode_raw(bytes)?,
+            Codec::DeltaVarint => decode_delta(bytes, len)?,
+            Codec::RunLength => decode_run_length(bytes, len)?,
+            Codec::BitPacked => decode_bit_packed(bytes, len)?,
+            Codec::Dictionary => decode_dictionary(bytes, len)?,
+        };
+
+        if values.len() != len {
+            return None;
+        }
+
+        Some(values)
+    }
+}
+
+/// Compress values with whichever codec makes them the smallest
+///
+/// # Example
+///
+/// ```
+/// use redoxql::compression::{compress, Codec};
+///
+/// let keys: Vec<i64> = (1000..2000).collect();
+/// let (codec, bytes) = compress(&keys);
+///
+/// assert_eq!(codec, Codec::DeltaVarint);
+/// assert_eq!(codec.decode(&bytes, keys.len()), Some(keys));
+/// ```
+pub fn compress(values: &[i64]) -> (Codec, Vec<u8>) {
+    let mut best = (Codec::Raw, Codec::Raw.encode(values));
+
+    for codec in &CODECS[1..] {
+        let bytes = codec.encode(values);
+        if bytes.len() < best.1.len() {
+            best = (*codec, bytes);
+        }
+    }
+
+    best
+}
+
+/// Map signed numbers to unsigned ones so small negative numbers stay small
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    ((n >> 1) as i64) ^ -((n & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// Read a varint at `pos` and move `pos` past it
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut n = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let b = *bytes.get(*pos)?;
+        *pos += 1;
+
+        if shift >= 64 {
+            return None;
+        }
+
+        n |= ((b & 0x7F) as u64) << shift;
+        if b & 0x80 == 0 {
+            return Some(n);
+        }
+        shift += 7;
+    }
+}
+
+fn decode_raw(bytes: &[u8]) -> Option<Vec<i64>> {
+    if !bytes.len().is_multiple_of(8) {
+        return None;
+    }
+
+    Some(
+        bytes
+            .chunks_exact(8)
+            .map(|c| i64::from_le_bytes(c.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+fn encode_delta(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut prev = 0i64;
+
+    for &v in values {
+        write_varint(&mut out, zigzag(v.wrapping_sub(prev)));
+        prev = v;
+    }
+
+    out
+}
+
+fn decode_delta(bytes: &[u8], len: usize) -> Option<Vec<i64>> {
+    // Every value takes at least a byte
+    let mut values = Vec::with_capacity(len.min(bytes.len()));
+    let mut pos = 0;
+    let mut prev = 0i64;
+
+    while pos < bytes.len() {
+        prev = prev.wrapping_add(unzigzag(read_varint(bytes, &mut pos)?));
+        values.push(prev);
+    }
+
+    Some(values)
+}
+
+fn encode_run_length(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < values.len() {
+        let mut run = 1;
+        while i + run < values.len() && values[i + run] == values[i] {
+            run += 1;
+        }
+
+        write_varint(&mut out, zigzag(values[i]));
+        write_varint(&mut out, run as u64);
+        i += run;
+    }
+
+    out
+}
+
+fn decode_run_length(bytes: &[u8], len: usize) -> Option<Vec<i64>> {
+    let mut values = Vec::with_capacity(len);
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let value = unzigzag(read_varint(bytes, &mut pos)?);
+        let run = read_varint(bytes, &mut pos)? as usize;
+
+        // A run longer than the page can only come from a corrupted file
+        if run > len - values.len() {
+            return None;
+        }
+
+        values.extend(std::iter::repeat_n(value, run));
+    }
+
+    Some(values)
+}
+
+/// How many bits it takes to store n
+fn bit_width(n: u64) -> u8 {
+    (64 - n.leading_zeros()) as u8
+}
+
+/// Pack every number into `width` bits, lowest bits first
+fn pack_bits(out: &mut Vec<u8>, numbers: &[u64], width: u8) {
+    let mut acc = 0u128;
+    let mut bits = 0;
+
+    for &n in numbers {
+        acc |= (n as u128) << bits;
+        bits += width as u32;
+
+        while bits >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    }
+
+    if bits > 0 {
+        out.push(acc as u8);
+    }
+}
+
+fn unpack_bits(bytes: &[u8], len: usize, width: u8) -> Option<Vec<u64>> {
+    let needed = len.checked_mul(width as usize)?.div_ceil(8);
+    if width > 64 || bytes.len() < needed {
+        return None;
+    }
+
+    let mask = if width == 64 {
+        u64::MAX
+    } else {
+        (1u64 << width) - 1
+    };
+
+    let mut numbers = Vec::with_capacity(len);
+    let mut acc = 0u128;
+    let mut bits = 0;
+    let mut pos = 0;
+
+    for _ in 0..len {
+        while bits < width as u32 {
+            acc |= (bytes[pos] as u128) << bits;
+            pos += 1;
+            bits += 8;
+        }
+
+        numbers.push((acc as u64) & mask);
+        acc >>= width;
+        bits -= width as u32;
+    }
+
+    Some(numbers)
+}
+
+fn encode_bit_packed(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let min = values.iter().copied().min().unwrap_or(0);
+
+    // Subtracting in u64 keeps the offsets right even when the range overflows i64
+    let offsets: Vec<u64> = values
+        .iter()
+        .map(|&v| (v as u64).wrapping_sub(min as u64))
+        .collect();
+    let width = bit_width(offsets.iter().copied().max().unwrap_or(0));
+
+    out.extend_from_slice(&min.to_le_bytes());
+    out.push(width);
+    pack_bits(&mut out, &offsets, width);
+
+    out
+}
+
+fn decode_bit_packed(bytes: &[u8], len: usize) -> Option<Vec<i64>> {
+    let min = i64::from_le_bytes(bytes.get(0..8)?.try_into().unwrap());
+    let width = *bytes.get(8)?;
+
+    let offsets = unpack_bits(&bytes[9..], len, width)?;
+
+    Some(
+        offsets
+            .into_iter()
+            .map(|o| (min as u64).wrapping_add(o) as i64)
+            .collect(),
+    )
+}
+
+fn encode_dictionary(values: &[i64]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    let mut dictionary = values.to_vec();
+    dictionary.sort_unstable();
+    dictionary.dedup();
+
+    write_varint(&mut out, dictionary.len() as u64);
+    for &v in &dictionary {
+        write_varint(&mut out, zigzag(v));
+    }
+
+    let indexes: Vec<u64> = values
+        .iter()
+        .map(|v| dictionary.binary_search(v).unwrap() as u64)
+        .collect();
+    let width = bit_width(dictionary.len().saturating_sub(1) as u64);
+
+    out.push(width);
+    pack_bits(&mut out, &indexes, width);
+
+    out
+}
+
+fn decode_dictionary(bytes: &[u8], len: usize) -> Option<Vec<i64>> {
+    let mut pos = 0;
+
+    let size = read_varint(bytes, &mut pos)? as usize;
+    // Every entry takes at least a byte, so a bigger dictionary can only come from a bad file
+    if size > bytes.len() {
+        return None;
+    }
+
+    let mut dictionary = Vec::with_capacity(size);
+    for _ in 0..size {
+        dictionary.push(unzigzag(read_varint(bytes, &mut pos)?));
+    }
+
+    let width = *bytes.get(pos)?;
+    let indexes = unpack_bits(&bytes[pos + 1..], len, width)?;
+
+    indexes
+        .into_iter()
+        .map(|i| dictionary.get(i as usize).copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip_all(values: &[i64]) {
+        for codec in CODECS {
+            let bytes = codec.encode(values);
+            assert_eq!(
+                codec.decode(&bytes, values.len()),
+                Some(values.to_vec()),
+                "{:?} did not round trip",
+                codec
+            );
+        }
+    }
+
+    #[test]
+    fn codecs_round_trip_test() {
+        round_trip_all(&[]);
+        round_trip_all(&[0]);
+        round_trip_all(&[1, 2, 3, 4, 5]);
+        round_trip_all(&[5, -5, 5, -5, 0, 0, 0]);
+        round_trip_all(&[i64::MIN, i64::MAX, 0, -1, 1]);
+        round_trip_all(&(0..1000).map(|x| (x * 7919) % 113).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn compress_picks_smallest_codec_test() {
+        // Sorted keys
+        let keys: Vec<i64> = (90_000..91_000).collect();
+        assert_eq!(compress(&keys).0, Codec::DeltaVarint);
+
+        // One repeated value
+        let zeros = vec![0; 1000];
+        assert_eq!(compress(&zeros).0, Codec::RunLength);
+
+        // Few distinct, large values
+        let flags: Vec<i64> = (0..1000)
+            .map(|x| if x % 3 == 0 { i64::MAX } else { i64::MIN })
+            .collect();
+        assert_eq!(compress(&flags).0, Codec::Dictionary);
+
+        // Small unsorted counters
+        let counters: Vec<i64> = (0..1000).map(|x| (x * 37) % 16).collect();
+        assert_eq!(compress(&counters).0, Codec::BitPacked);
+
+        // Random looking values do not compress
+        let noise: Vec<i64> = (0..100)
+            .map(|x: i64| x.wrapping_mul(0x9E37_79B9_7F4A_7C15u64 as i64))
+            .collect();
+        assert_eq!(compress(&noise).0, Codec::Raw);
+    }
+
+    #[test]
+    fn decode_rejects_garbage_test() {
+        assert_eq!(Codec::Raw.decode(&[1, 2, 3], 1), None);
+        assert_eq!(Codec::DeltaVarint.decode(&[0xFF], 1), None);
+        assert_eq!(Codec::RunLength.decode(&[2, 100], 3), None);
+        assert_eq!(Codec::BitPacked.decode(&[0; 9], 3), Some(vec![0, 0, 0]));
+        assert_eq!(Codec::BitPacked.decode(&[0; 4], 3), None);
+        assert_eq!(Codec::Dictionary.decode(&[1, 2, 1, 0xFF], 3), None);
+    }
+}
@@ -1,10 +1,9 @@
-use crate::record::RecordLock;
-
-use super::filewriter::{build_binary_writer, Writer};
-use super::page::PhysicalPage;
+use super::filewriter::{build_binary_writer, FileError, Writer};
+use super::page::{PageHandle, PhysicalPage};
 use super::record::{Record, RecordAddress};
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex, RwLock};
+use std::io;
+use std::sync::Arc;
 
 #[derive(Clone, Default, Deserialize, Serialize, Debug)]
 pub struct BaseContainerMetadata {
@@ -26,28 +25,38 @@ pub enum ReservedColumns {
 
 pub static NUM_RESERVED_COLUMNS: i64 = 4;
 
+/// The file of a base page, base and tail pages with the same index must not share a file
+pub fn base_page_path(dir: &str, index: usize) -> String {
+    format!("{}/base-{}-page.data", dir, index)
+}
+
+/// The file of a tail page
+pub fn tail_page_path(dir: &str, index: usize) -> String {
+    format!("{}/tail-{}-page.data", dir, index)
+}
+
 impl BaseContainerMetadata {
-    pub fn load_state(&self) -> BaseContainer {
+    pub fn load_state(&self, dir: &str) -> Result<BaseContainer, FileError> {
         let mut base = BaseContainer::new(self.num_cols);
 
         for i in 0..self.num_pages {
             // Load the page
-            let p = PhysicalPage::load_state(i as i64);
-            // Put the page into an Arc Mutex
-            let m = Arc::new(Mutex::new(p));
+            let p = PhysicalPage::load_state(&base_page_path(dir, i))?;
+            // Put the page into a shared handle
+            let m = p.into_handle();
 
             // Add the physical page
             base.physical_pages.push(m);
         }
 
-        return base;
+        Ok(base)
     }
 }
 
 #[derive(Clone, Default)]
 pub struct BaseContainer {
     // pages
-    pub physical_pages: Vec<Arc<Mutex<PhysicalPage>>>,
+    pub physical_pages: Vec<PageHandle>,
 
     // tail-page sequence number
     pub tail_page_sequence: i64,
@@ -72,38 +81,35 @@ impl BaseContainer {
         let indirection_page = PhysicalPage::new(ReservedColumns::Indirection as i64);
         let base_rid_page = PhysicalPage::new(ReservedColumns::BaseRID as i64);
 
-        self.physical_pages.push(Arc::new(Mutex::new(rid_page)));
-        self.physical_pages
-            .push(Arc::new(Mutex::new(schema_encoding_page)));
-        self.physical_pages
-            .push(Arc::new(Mutex::new(indirection_page)));
-        self.physical_pages
-            .push(Arc::new(Mutex::new(base_rid_page)));
+        self.physical_pages.push(rid_page.into_handle());
+        self.physical_pages.push(schema_encoding_page.into_handle());
+        self.physical_pages.push(indirection_page.into_handle());
+        self.physical_pages.push(base_rid_page.into_handle());
 
         // initialize the rest of the columns
         for i in 0..self.num_cols {
             let new_page = PhysicalPage::new(NUM_RESERVED_COLUMNS + i);
-            self.physical_pages.push(Arc::new(Mutex::new(new_page)));
+            self.physical_pages.push(new_page.into_handle());
         }
     }
 
     /// Returns a reference to the RID column page
-    pub fn rid_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn rid_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::RID as usize].clone()
     }
 
     /// Returns a reference to the schema encoding column page
-    pub fn schema_encoding_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn schema_encoding_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::SchemaEncoding as usize].clone()
     }
 
     /// Returns a reference to the indirection column page
-    pub fn indirection_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn indirection_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::Indirection as usize].clone()
     }
 
     /// Returns a reference to the base RID column page
-    pub fn base_rid_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn base_rid_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::BaseRID as usize].clone()
     }
 
@@ -112,18 +118,16 @@ impl BaseContainer {
     /// ### Arguments
     ///
     /// - `col_idx`: The index of the column
-    pub fn column_page(&self, col_idx: i64) -> Arc<Mutex<PhysicalPage>> {
+    pub fn column_page(&self, col_idx: i64) -> PageHandle {
         self.physical_pages[(col_idx + NUM_RESERVED_COLUMNS) as usize].clone()
     }
 
     pub fn deep_copy(&self) -> BaseContainer {
         let mut base = BaseContainer::new(self.num_cols);
 
-        for i in 0..self.physical_pages.len() {
-            let p = self.physical_pages[i].lock().unwrap();
-            let new_page = p.clone();
-            let m = Arc::new(Mutex::new(new_page));
-            base.physical_pages.push(m);
+        for page in &self.physical_pages {
+            base.physical_pages
+                .push(PhysicalPage::clone(page).into_handle());
         }
 
         base
@@ -134,116 +138,63 @@ impl BaseContainer {
             panic!("Number of values does not match number of columns");
         }
 
-        let rid_page = self.rid_page();
-        let mut rp = rid_page.lock().unwrap();
-
-        rp.write(rid);
-
-        let schema_encoding_page = self.schema_encoding_page();
-        let mut sep = schema_encoding_page.lock().unwrap();
-        sep.write(0);
-
-        let indirection_page = self.indirection_page();
-        let mut ip = indirection_page.lock().unwrap();
-
-        ip.write(rid);
-
-        let base_rid_page = self.base_rid_page();
-        let mut brp = base_rid_page.lock().unwrap();
-        brp.write(rid);
-
-        for i in 0..self.num_cols {
-            let col_page = self.column_page(i);
-            let mut col_page = col_page.lock().unwrap();
-            col_page.write(values[i as usize]);
-        }
-
-        let addresses: Arc<Mutex<Vec<RecordAddress>>> = Arc::new(Mutex::new(Vec::new()));
-        let mut a = addresses.lock().unwrap();
-
-        a.push(RecordAddress {
-            page: rid_page.clone(),
-            offset: rp.num_records - 1,
-        });
-
-        a.push(RecordAddress {
-            page: schema_encoding_page.clone(),
-            offset: sep.num_records - 1,
-        });
-
-        a.push(RecordAddress {
-            page: indirection_page.clone(),
-            offset: ip.num_records - 1,
-        });
-
-        a.push(RecordAddress {
-            page: base_rid_page.clone(),
-            offset: brp.num_records - 1,
-        });
-
-        for i in 0..self.num_cols {
-            let col_page = self.column_page(i);
-            let cp = col_page.lock().unwrap();
-            a.push(RecordAddress {
-                page: col_page.clone(),
-                offset: cp.num_records - 1,
-            });
-        }
+        // RID, schema encoding, indirection and base RID followed by the user columns
+        let reserved = [rid, 0, rid, rid];
 
         Record {
             rid,
-            addresses: addresses.clone(),
-            lock: Arc::new(RwLock::new(RecordLock::default())),
+            addresses: Arc::new(append_to_pages(&self.physical_pages, &reserved, &values)),
         }
     }
 
-    pub fn read_record(&self, record: Record) -> Vec<i64> {
-        let mut values = Vec::<i64>::new();
-
-        let addrs = record.addresses.lock().unwrap();
-        let addrs_clone = addrs.clone();
-        for addr in addrs_clone {
-            let a = addr.page.lock().unwrap();
-            let b = a.read(addr.offset as usize);
-            values.push(b.expect("Value should be there"));
-        }
+    /// Insert `(rid, values)` records, each page takes all of its values in one append
+    pub fn insert_records(&mut self, records: Vec<(i64, Vec<i64>)>) -> Vec<Record> {
+        let records: Vec<([i64; 4], Vec<i64>)> = records
+            .into_iter()
+            .map(|(rid, values)| ([rid, 0, rid, rid], values))
+            .collect();
 
-        values
+        append_records(&self.physical_pages, self.num_cols, records)
     }
 
-    pub fn find_rid_offset(&mut self, rid: i64) -> usize {
-        let rid_page = self.rid_page();
-        let data = {
-            let guard = rid_page.lock().unwrap();
-            guard.data.clone()
-        };
+    pub fn read_record(&self, record: Record) -> Vec<i64> {
+        read_from_pages(&record)
+    }
 
-        for (i, &value) in data.iter().enumerate() {
-            if value == rid {
-                return i;
-            }
-        }
-        panic!("Could not find RID in RID page");
+    pub fn find_rid_offset(&mut self, rid: i64) -> usize {
+        self.rid_page()
+            .values()
+            .position(|value| value == rid)
+            .expect("Could not find RID in RID page")
     }
 
-    pub fn save_state(&self) {
+    pub fn save_state(&self, dir: &str) -> io::Result<()> {
         let base_meta = self.get_metadata();
 
-        let mut index = 0;
         // The Rust compiler suggested that I clone here but it's definitely way better to not copy
         // all of the data and just use a reference
-        for p in &self.physical_pages {
+        for (index, p) in self.physical_pages.iter().enumerate() {
             // Save the page
-            let m = p.lock().unwrap();
-            m.save_state(index);
-            index += 1;
+            p.save_state(&base_page_path(dir, index))?;
         }
 
         // It looks like we don't actually load the metadata of base container because we just save
         // each page individually and we know how many pages there will be from other places. We
         // likely don't need need to save this even in future version
         let writer: Writer<BaseContainerMetadata> = build_binary_writer();
-        writer.write_file("./redoxdata/base_container.data", &base_meta);
+        writer.write_file(&format!("{}/base_container.data", dir), &base_meta)
+    }
+
+    /// Add a column at the end, see `add_column_page`
+    pub fn add_column(&mut self, default: i64) -> PageHandle {
+        self.num_cols += 1;
+        add_column_page(&mut self.physical_pages, default)
+    }
+
+    /// Reorder or drop columns, see `arrange_column_pages`
+    pub fn arrange_columns(&mut self, order: &[usize]) {
+        self.num_cols = order.len() as i64;
+        arrange_column_pages(&mut self.physical_pages, order);
     }
 
     pub fn get_metadata(&self) -> BaseContainerMetadata {
@@ -255,6 +206,112 @@ impl BaseContainer {
     }
 }
 
+/// Add a page for a new last column, holding `default` for every row already in the container
+fn add_column_page(pages: &mut Vec<PageHandle>, default: i64) -> PageHandle {
+    let num_rows = pages[ReservedColumns::RID as usize].num_records();
+
+    let page = PhysicalPage::from_values(pages.len() as i64, vec![default; num_rows as usize]);
+
+    let handle = page.into_handle();
+    pages.push(handle.clone());
+    handle
+}
+
+/// Keep the reserved pages and the column pages in `order`, where `order[i]` is the column that
+/// goes to position i. Columns that are not in `order` are dropped.
+fn arrange_column_pages(pages: &mut Vec<PageHandle>, order: &[usize]) {
+    let reserved = NUM_RESERVED_COLUMNS as usize;
+
+    let mut arranged = pages[..reserved].to_vec();
+    arranged.extend(order.iter().map(|&col| pages[reserved + col].clone()));
+
+    // Pages are found by their column index when they are loaded again
+    for (i, page) in arranged.iter().enumerate().skip(reserved) {
+        page.set_column_index(i as i64);
+    }
+
+    *pages = arranged;
+}
+
+/// Append one value to each page and return where each value landed
+///
+/// Appending never blocks readers, they see a value once it is completely written.
+fn append_to_pages(pages: &[PageHandle], reserved: &[i64], values: &[i64]) -> Vec<RecordAddress> {
+    let mut addresses = Vec::with_capacity(pages.len());
+
+    for (page, value) in pages.iter().zip(reserved.iter().chain(values.iter())) {
+        let offset = page.write(*value);
+
+        addresses.push(RecordAddress {
+            page: page.clone(),
+            offset,
+        });
+    }
+
+    addresses
+}
+
+/// Append many records, all values of a page go in with one append
+fn append_records(
+    pages: &[PageHandle],
+    num_cols: i64,
+    records: Vec<([i64; 4], Vec<i64>)>,
+) -> Vec<Record> {
+    if records
+        .iter()
+        .any(|(_, values)| values.len() != num_cols as usize)
+    {
+        panic!("Number of values does not match number of columns");
+    }
+
+    let mut addresses: Vec<Vec<RecordAddress>> = records
+        .iter()
+        .map(|_| Vec::with_capacity(pages.len()))
+        .collect();
+
+    for (column, page) in pages.iter().enumerate() {
+        let last = page.extend(records.iter().map(
+            |(reserved, values)| match reserved.get(column) {
+                Some(&value) => value,
+                None => values[column - reserved.len()],
+            },
+        ));
+        let first = last + 1 - records.len() as i64;
+
+        for (offset, record) in (first..).zip(addresses.iter_mut()) {
+            record.push(RecordAddress {
+                page: page.clone(),
+                offset,
+            });
+        }
+    }
+
+    records
+        .iter()
+        .zip(addresses)
+        .map(|((reserved, _), addresses)| Record {
+            rid: reserved[ReservedColumns::RID as usize],
+            addresses: Arc::new(addresses),
+        })
+        .collect()
+}
+
+/// Read every column of a record
+///
+/// Pages are read without locks, so any number of threads can read the same record (or other
+/// records on the same pages) at the same time, even while values are appended.
+fn read_from_pages(record: &Record) -> Vec<i64> {
+    record
+        .addresses
+        .iter()
+        .map(|addr| {
+            addr.page
+                .read(addr.offset as usize)
+                .expect("Value should be there")
+        })
+        .collect()
+}
+
 use std::fmt;
 
 impl fmt::Display for BaseContainer {
@@ -268,7 +325,7 @@ impl fmt::Display for BaseContainer {
         // Determine the number of rows by inspecting the first physical page.
         // (Assumes all pages have the same number of records.)
         let num_rows = if let Some(first_page) = self.physical_pages.first() {
-            first_page.lock().unwrap().data.len()
+            first_page.num_records() as usize
         } else {
             0
         };
@@ -290,10 +347,8 @@ impl fmt::Display for BaseContainer {
         for row in 0..num_rows {
             write!(f, "|")?;
             for page in &self.physical_pages {
-                // Lock the page; in a production setting, handle poisoning appropriately.
-                let page = page.lock().unwrap();
-                // Retrieve the value from the data vector. If missing, print 0.
-                let value = page.data.get(row).unwrap_or(&0);
+                // Retrieve the value from the page. If missing, print 0.
+                let value = page.read(row).unwrap_or(0);
                 write!(f, " {:^15} |", value)?;
             }
             writeln!(f)?;
@@ -312,27 +367,27 @@ pub struct TailContainerMetadata {
 }
 
 impl TailContainerMetadata {
-    pub fn load_state(&self) -> TailContainer {
+    pub fn load_state(&self, dir: &str) -> Result<TailContainer, FileError> {
         let mut tail = TailContainer::new(self.num_cols);
 
         for i in 0..self.num_pages {
             // Load the page
-            let p = PhysicalPage::load_state(i as i64);
-            // Put the page into an Arc Mutex
-            let m = Arc::new(Mutex::new(p));
+            let p = PhysicalPage::load_state(&tail_page_path(dir, i))?;
+            // Put the page into a shared handle
+            let m = p.into_handle();
 
             // Add the physical page
             tail.physical_pages.push(m);
         }
 
-        return tail;
+        Ok(tail)
     }
 }
 
 #[derive(Clone, Default)]
 pub struct TailContainer {
     // pages
-    pub physical_pages: Vec<Arc<Mutex<PhysicalPage>>>,
+    pub physical_pages: Vec<PageHandle>,
 
     // number of additional columns
     pub num_cols: i64,
@@ -376,43 +431,40 @@ impl TailContainer {
         let indirection_page = PhysicalPage::new(ReservedColumns::Indirection as i64);
         let base_rid_page = PhysicalPage::new(ReservedColumns::BaseRID as i64);
 
-        self.physical_pages.push(Arc::new(Mutex::new(rid_page)));
-        self.physical_pages
-            .push(Arc::new(Mutex::new(schema_encoding_page)));
-        self.physical_pages
-            .push(Arc::new(Mutex::new(indirection_page)));
-        self.physical_pages
-            .push(Arc::new(Mutex::new(base_rid_page)));
+        self.physical_pages.push(rid_page.into_handle());
+        self.physical_pages.push(schema_encoding_page.into_handle());
+        self.physical_pages.push(indirection_page.into_handle());
+        self.physical_pages.push(base_rid_page.into_handle());
 
         // initialize the rest of the columns
         for i in 0..self.num_cols {
             let new_page = PhysicalPage::new(NUM_RESERVED_COLUMNS + i);
-            self.physical_pages.push(Arc::new(Mutex::new(new_page)));
+            self.physical_pages.push(new_page.into_handle());
         }
     }
 
     /// Returns a reference to the RID column page
-    pub fn rid_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn rid_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::RID as usize].clone()
     }
 
     /// Returns a reference to the schema encoding column page
-    pub fn schema_encoding_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn schema_encoding_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::SchemaEncoding as usize].clone()
     }
 
     /// Returns a reference to the indirection column page
-    pub fn indirection_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn indirection_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::Indirection as usize].clone()
     }
 
     // Returns a reference to the base RID column page
-    pub fn base_rid_page(&self) -> Arc<Mutex<PhysicalPage>> {
+    pub fn base_rid_page(&self) -> PageHandle {
         self.physical_pages[ReservedColumns::BaseRID as usize].clone()
     }
 
     /// Returns a reference to the specified column page
-    pub fn column_page(&self, col_idx: i64) -> Arc<Mutex<PhysicalPage>> {
+    pub fn column_page(&self, col_idx: i64) -> PageHandle {
         self.physical_pages[(col_idx + NUM_RESERVED_COLUMNS) as usize].clone()
     }
 
@@ -427,102 +479,50 @@ impl TailContainer {
             panic!("Number of values does not match number of columns");
         }
 
-        let rid_page = self.rid_page();
-        let mut rp = rid_page.lock().unwrap();
-
-        rp.write(rid);
-
-        let schema_encoding_page = self.schema_encoding_page();
-        let mut sep = schema_encoding_page.lock().unwrap();
-        sep.write(0);
-
-        let indirection_page = self.indirection_page();
-        let mut ip = indirection_page.lock().unwrap();
-
-        ip.write(indirection_rid as i64);
-
-        let base_rid_page = self.base_rid_page();
-        let mut brp = base_rid_page.lock().unwrap();
-        brp.write(base_rid as i64);
-
-        for i in 0..self.num_cols {
-            let col_page = self.column_page(i);
-            let mut col_page = col_page.lock().unwrap();
-            col_page.write(values[i as usize] as i64);
-        }
-
-        let addresses: Arc<Mutex<Vec<RecordAddress>>> = Arc::new(Mutex::new(Vec::new()));
-        let mut a = addresses.lock().unwrap();
-
-        a.push(RecordAddress {
-            page: rid_page.clone(),
-            offset: rp.num_records - 1,
-        });
-
-        a.push(RecordAddress {
-            page: schema_encoding_page.clone(),
-            offset: sep.num_records - 1,
-        });
-
-        a.push(RecordAddress {
-            page: indirection_page.clone(),
-            offset: ip.num_records - 1,
-        });
-
-        a.push(RecordAddress {
-            page: base_rid_page.clone(),
-            offset: brp.num_records - 1,
-        });
-
-        for i in 0..self.num_cols {
-            let col_page = self.column_page(i);
-            let cp = col_page.lock().unwrap();
-            a.push(RecordAddress {
-                page: col_page.clone(),
-                offset: cp.num_records - 1,
-            });
-        }
+        // RID, schema encoding, indirection and base RID followed by the user columns
+        let reserved = [rid, 0, indirection_rid, base_rid];
 
         Record {
             rid,
-            addresses: addresses.clone(),
-            lock: Arc::new(RwLock::new(RecordLock::default())),
+            addresses: Arc::new(append_to_pages(&self.physical_pages, &reserved, &values)),
         }
     }
 
-    pub fn read_record(&self, record: Record) -> Vec<i64> {
-        let mut values = Vec::<i64>::new();
-
-        let addrs = record.addresses.lock().unwrap();
-        let addrs_clone = addrs.clone();
-        for addr in addrs_clone {
-            let a = addr.page.lock().unwrap();
-            let b = a.read(addr.offset as usize);
-            values.push(b.expect("Value should be there"));
-        }
+    /// Insert records given as their reserved columns and values, one append per page
+    ///
+    /// The reserved columns are RID, schema encoding, indirection and base RID.
+    pub fn insert_records(&mut self, records: Vec<([i64; 4], Vec<i64>)>) -> Vec<Record> {
+        append_records(&self.physical_pages, self.num_cols, records)
+    }
 
-        values
+    pub fn read_record(&self, record: Record) -> Vec<i64> {
+        read_from_pages(&record)
     }
 
-    pub fn save_state(&self) {
+    pub fn save_state(&self, dir: &str) -> io::Result<()> {
         let tail_meta = self.get_metadata();
 
-        let mut index = 0;
         // The Rust compiler suggested that I clone here but it's definitely way better to not copy
         // all of the data and just use a reference
-        for p in &self.physical_pages {
-            // Save the page
-            let m = p.lock().unwrap();
-
-            // If there is any data in the tail page, save it and overwrite the base page
-            if m.data.len() > 0 {
-                m.save_state(index);
-            }
-            index += 1;
+        for (index, p) in self.physical_pages.iter().enumerate() {
+            // Save the page, empty ones too since loading the container expects every page
+            p.save_state(&tail_page_path(dir, index))?;
         }
 
         let writer: Writer<TailContainerMetadata> = build_binary_writer();
-        writer.write_file("./redoxdata/tail_container.data", &tail_meta);
+        writer.write_file(&format!("{}/tail_container.data", dir), &tail_meta)
+    }
+
+    /// Add a column at the end, see `add_column_page`
+    pub fn add_column(&mut self, default: i64) -> PageHandle {
+        self.num_cols += 1;
+        add_column_page(&mut self.physical_pages, default)
+    }
+
+    /// Reorder or drop columns, see `arrange_column_pages`
+    pub fn arrange_columns(&mut self, order: &[usize]) {
+        self.num_cols = order.len() as i64;
+        arrange_column_pages(&mut self.physical_pages, order);
     }
 
     pub fn get_metadata(&self) -> TailContainerMetadata {
@@ -544,7 +544,7 @@ impl fmt::Display for TailContainer {
         // Determine the number of rows by inspecting the first physical page.
         // (Assumes all pages have the same number of records.)
         let num_rows = if let Some(first_page) = self.physical_pages.first() {
-            first_page.lock().unwrap().data.len()
+            first_page.num_records() as usize
         } else {
             0
         };
@@ -566,10 +566,8 @@ impl fmt::Display for TailContainer {
         for row in 0..num_rows {
             write!(f, "|")?;
             for page in &self.physical_pages {
-                // Lock the page; in a production setting, handle poisoning appropriately.
-                let page = page.lock().unwrap();
-                // Retrieve the value from the data vector. If missing, print 0.
-                let value = page.data.get(row).unwrap_or(&0);
+                // Retrieve the value from the page. If missing, print 0.
+                let value = page.read(row).unwrap_or(0);
                 write!(f, " {:^15} |", value)?;
             }
             writeln!(f)?;
@@ -641,11 +639,12 @@ mod tests {
         // - The schema encoding page gets 0
         // - The indirection and base RID pages get 'rid'
         // - The additional column pages get the values provided.
-        let mut expected = Vec::new();
-        expected.push(rid); // RID page
-        expected.push(0); // schema encoding page
-        expected.push(rid); // indirection page
-        expected.push(rid); // base RID page
+        let mut expected = vec![
+            rid, // RID page
+            0,   // schema encoding page
+            rid, // indirection page
+            rid, // base RID page
+        ];
         expected.extend(values);
 
         let read_values = base.read_record(record);
@@ -655,6 +654,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_base_container_concurrent_reads() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let mut base = BaseContainer::new(2);
+        base.initialize();
+        let record = base.insert_record(7, vec![70, 700]);
+
+        // Keep appending to a column page and overwriting the record's indirection while the
+        // readers run, neither of them waits for the other
+        let column_page = base.column_page(0);
+        let indirection = base.indirection_page();
+        let writer = thread::spawn(move || {
+            for i in 0..10_000 {
+                column_page.write(i);
+                indirection.overwrite(0, 7 + i % 2);
+            }
+        });
+
+        let (tx, rx) = mpsc::channel();
+        let mut handles = Vec::new();
+        for _ in 0..4 {
+            let base = base.clone();
+            let record = record.clone();
+            let tx = tx.clone();
+            handles.push(thread::spawn(move || {
+                tx.send(base.read_record(record)).unwrap();
+            }));
+        }
+
+        for _ in 0..4 {
+            let values = rx
+                .recv_timeout(Duration::from_secs(5))
+                .expect("Readers should not block each other");
+            assert_eq!(values[..2], [7, 0]);
+            assert!(values[2] == 7 || values[2] == 8);
+            assert_eq!(values[3..], [7, 70, 700]);
+        }
+
+        for h in handles {
+            h.join().unwrap();
+        }
+        writer.join().unwrap();
+        assert_eq!(base.column_page(0).num_records(), 10_001);
+    }
+
     #[test]
     #[should_panic(expected = "Number of values does not match number of columns")]
     fn test_base_container_insert_record_panic_on_wrong_columns() {
@@ -679,6 +726,27 @@ mod tests {
         assert_eq!(meta.num_cols, num_cols);
     }
 
+    #[test]
+    fn test_base_container_add_and_arrange_columns() {
+        let mut base = BaseContainer::new(2);
+        base.initialize();
+        let record = base.insert_record(1, vec![10, 20]);
+
+        let page = base.add_column(7);
+        assert_eq!(base.num_cols, 3);
+        assert_eq!(page.values().collect::<Vec<i64>>(), vec![7]);
+
+        // Swap the first two columns and drop the new one
+        base.arrange_columns(&[1, 0]);
+        assert_eq!(base.num_cols, 2);
+        assert_eq!(base.physical_pages.len(), 6);
+        assert_eq!(base.column_page(0).values().collect::<Vec<i64>>(), vec![20]);
+        assert_eq!(base.column_page(0).column_index(), 4);
+
+        // Records still point at the pages they were written to
+        assert_eq!(base.read_record(record), vec![1, 0, 1, 1, 10, 20]);
+    }
+
     // #[test]
     // fn test_base_container_save_state() {
     //     // Use a temporary directory so as not to interfere with real data.
@@ -756,11 +824,7 @@ mod tests {
         // Expected order of written values:
         // Reserved pages: RID page gets rid, schema encoding gets 0, indirection gets indirection_rid,
         // base RID gets base_rid, then additional columns get the provided values.
-        let mut expected = Vec::new();
-        expected.push(rid);
-        expected.push(0);
-        expected.push(indirection_rid);
-        expected.push(base_rid);
+        let mut expected = vec![rid, 0, indirection_rid, base_rid];
         expected.extend(values);
 
         let read_values = tail.read_record(record);
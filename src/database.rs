@@ -1,14 +1,23 @@
 use super::bufferpool::BufferPool;
-use super::filewriter::{build_binary_writer, Writer};
+use super::filewriter::{build_binary_writer, sync_dir, FileError, Writer, FORMAT_VERSION};
 use super::index::RIndex;
+use super::lock_manager::{DeadlockStrategy, LockManager};
+use super::migration;
+use super::mvcc::VersionMap;
 use super::pagerange::PageRange;
-use super::table::{PageDirectory, RTable, RTableMetadata, StatePersistence};
+use super::sql::{self, SqlError, SqlOutput};
+use super::table::{PageDirectory, RTable, RTableMetadata, StatePersistence, TableDescription};
 use crate::table::RTableHandle;
-use log::info;
+use log::{info, warn};
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyIOError, PyKeyError, PyValueError};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use std::fs::create_dir_all;
+use std::fmt;
+use std::fs::{create_dir_all, read_dir, remove_dir_all};
+use std::io;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 
@@ -16,9 +25,17 @@ type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct RDatabaseMetadata {
-    tables: Vec<RTableMetadata>,
-    tables_hashmap: RedoxQLHashMap<String, usize>,
-    db_filepath: Option<String>,
+    /// The on-disk format this database was written in, `migration` upgrades anything older
+    pub format_version: u16,
+    pub tables: Vec<RTableMetadata>,
+    pub tables_hashmap: RedoxQLHashMap<String, usize>,
+    pub db_filepath: Option<String>,
+    /// Which snapshot directory holds the files of this database
+    pub generation: u64,
+    /// Every file in the snapshot, relative to the snapshot directory
+    pub files: Vec<String>,
+    /// The id the next created table gets, ids are never reused
+    pub next_table_id: i64,
 }
 
 // Define lock types for 2PL
@@ -37,11 +54,7 @@ impl std::fmt::Display for LockType {
     }
 }
 
-// A unique identifier for a record: (RTableHandle, record_id, lock_type)
-pub type RecordId = (RTableHandle, i64, LockType);
-pub type RecordId2 = (String, i64, LockType);
-
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct RDatabase {
     /// This is where we keep all of the tables
     pub tables: Vec<Arc<RwLock<RTable>>>,
@@ -50,6 +63,15 @@ pub struct RDatabase {
 
     db_filepath: Option<String>,
 
+    /// The snapshot that was last opened or closed
+    generation: u64,
+
+    /// The id the next created table gets
+    next_table_id: i64,
+
+    /// Every lock any transaction holds on this database's tables
+    pub lock_manager: Arc<LockManager>,
+
     pub buffer_pool: BufferPool,
 }
 
@@ -65,9 +87,151 @@ fn init_logging() {
     let _ = env_logger::try_init();
 }
 
-#[pymethods]
+/// Why the database could not do what it was asked
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// A file of the database is bad or could not be read
+    File(FileError),
+    /// Writing the snapshot failed
+    Io {
+        path: String,
+        error: io::Error,
+    },
+    /// The manifest lists a file that is not in the snapshot
+    MissingFile {
+        file: String,
+        manifest: String,
+    },
+    NoSuchTable(String),
+    NoSuchTableId(i64),
+    TableExists(String),
+    /// A table needs at least one column and its primary key has to be one of them
+    InvalidSchema {
+        num_columns: i64,
+        primary_key_column: i64,
+    },
+    UnknownDeadlockStrategy(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::File(err) => write!(f, "{}", err),
+            DatabaseError::Io { path, error } => write!(f, "Could not write {}: {}", path, error),
+            DatabaseError::MissingFile { file, manifest } => {
+                write!(f, "Missing file {} listed in manifest {}", file, manifest)
+            }
+            DatabaseError::NoSuchTable(name) => write!(f, "No table named {}", name),
+            DatabaseError::NoSuchTableId(id) => write!(f, "No table with id {}", id),
+            DatabaseError::TableExists(name) => write!(f, "A table named {} already exists", name),
+            DatabaseError::InvalidSchema {
+                num_columns,
+                primary_key_column,
+            } => write!(
+                f,
+                "Primary key column {} is not one of the {} columns",
+                primary_key_column, num_columns
+            ),
+            DatabaseError::UnknownDeadlockStrategy(name) => {
+                write!(f, "Unknown deadlock strategy {}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<FileError> for DatabaseError {
+    fn from(err: FileError) -> Self {
+        DatabaseError::File(err)
+    }
+}
+
+/// Files become IOError, missing tables KeyError and bad arguments ValueError
+#[cfg(feature = "python")]
+impl From<DatabaseError> for PyErr {
+    fn from(err: DatabaseError) -> PyErr {
+        match err {
+            DatabaseError::File(_)
+            | DatabaseError::Io { .. }
+            | DatabaseError::MissingFile { .. } => PyIOError::new_err(err.to_string()),
+            DatabaseError::NoSuchTable(_) | DatabaseError::NoSuchTableId(_) => {
+                PyKeyError::new_err(err.to_string())
+            }
+            DatabaseError::TableExists(_)
+            | DatabaseError::InvalidSchema { .. }
+            | DatabaseError::UnknownDeadlockStrategy(_) => PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+fn io_err(path: &str, error: io::Error) -> DatabaseError {
+    DatabaseError::Io {
+        path: path.to_string(),
+        error,
+    }
+}
+
+/// The directory next to the manifest that holds every snapshot of its database
+///
+/// It is named after the whole manifest path, so two databases never share snapshots even when
+/// their manifests have the same file name.
+pub(crate) fn snapshots_dir(manifest_path: &str) -> String {
+    format!("{}.d", manifest_path)
+}
+
+/// The directory that holds one generation of the database's files
+pub(crate) fn snapshot_dir(manifest_path: &str, generation: u64) -> String {
+    format!("{}/snapshot-{}", snapshots_dir(manifest_path), generation)
+}
+
+/// Each table gets its own directory in a snapshot, named after its id, so their files don't
+/// overwrite each other
+pub(crate) fn table_dir(snapshot: &str, table_id: i64) -> String {
+    format!("{}/table-{}", snapshot, table_id)
+}
+
+/// Collect every file under dir as a path relative to root
+pub(crate) fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            list_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete every snapshot of this database other than the one in the manifest
+pub(crate) fn remove_old_snapshots(manifest_path: &str, keep: u64) {
+    let keep_name = format!("snapshot-{}", keep);
+
+    let Ok(entries) = read_dir(snapshots_dir(manifest_path)) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if name.starts_with("snapshot-") && name != keep_name {
+            if let Err(e) = remove_dir_all(entry.path()) {
+                warn!("Could not remove old snapshot {}: {}", name, e);
+            }
+        }
+    }
+}
+
+impl Default for RDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RDatabase {
-    #[new]
     pub fn new() -> Self {
         init_logging();
 
@@ -75,79 +239,143 @@ impl RDatabase {
             tables: vec![],
             tables_hashmap: RedoxQLHashMap::default(),
             db_filepath: None,
+            generation: 0,
+            next_table_id: 0,
+            lock_manager: Arc::new(LockManager::default()),
             buffer_pool: BufferPool::new("./"),
         }
     }
 
-    fn open(&mut self, path: String) {
+    pub fn open(&mut self, path: String) -> Result<(), DatabaseError> {
         if self.db_filepath.is_none() {
             self.db_filepath = Some(path.clone());
         }
 
-        if !Path::new("./redoxdata").exists() {
-            create_dir_all("./redoxdata").expect("Should be able to make dir");
-        }
-
         if let Some(p) = &self.db_filepath {
             if !Path::new(&p).exists() {
                 // The database has not been closed yet
                 // Assuming the users makes sure to close the database before they want to open it
                 // again, we don't have to do anything else. We can just exit. The rest of the function
                 // only needs to load up the database if something exists to load up
-                return;
+                return Ok(());
             }
         }
 
+        // Data directories written by older versions get rewritten in the current format first
+        migration::upgrade(&path)?;
+
+        // The file at path is the manifest, it says which snapshot is the current one
         let writer: Writer<RDatabaseMetadata> = build_binary_writer();
-        let db_meta: RDatabaseMetadata = writer.read_file(&path);
+        let db_meta: RDatabaseMetadata = writer.read_file(&path)?;
+        if db_meta.format_version != FORMAT_VERSION {
+            return Err(DatabaseError::File(FileError::UnsupportedVersion {
+                path,
+                version: db_meta.format_version,
+            }));
+        }
+        let snapshot = snapshot_dir(&path, db_meta.generation);
+
+        // Make sure the whole snapshot is there before loading any of it
+        for file in &db_meta.files {
+            let file_path = format!("{}/{}", snapshot, file);
+            if !Path::new(&file_path).exists() {
+                return Err(DatabaseError::MissingFile {
+                    file: file_path,
+                    manifest: path,
+                });
+            }
+        }
 
         // Load each table metadata into this current databases' tables
-        let mut index = 0;
-        for table in &db_meta.tables {
-            let l = table.load_state(table.table_num);
+        for (index, table) in db_meta.tables.iter().enumerate() {
+            let dir = table_dir(&snapshot, table.table_num);
+            let mut l = table.load_state(&dir, table.table_num)?;
+            l.lock_manager = self.lock_manager.clone();
             // l.page_directory.display();
 
             self.tables.push(Arc::new(RwLock::new(l)));
             self.tables_hashmap.insert(table.name.clone(), index);
-            index += 1;
         }
 
+        self.generation = db_meta.generation;
+        self.next_table_id = db_meta.next_table_id;
+
+        // A crash during the last close can leave a snapshot that never made it into the manifest
+        remove_old_snapshots(&path, self.generation);
+
         info!("Database opened!");
+
+        Ok(())
     }
 
-    fn close(&self) {
+    pub fn close(&mut self) -> Result<(), DatabaseError> {
+        let Some(manifest_path) = self.db_filepath.clone() else {
+            // This actually happens in testM1.py when .close() gets called even though there
+            // never was a .open to begin with. Without a manifest there would be no way to find
+            // the snapshot again, so we just do nothing.
+            info!("Database closed without being opened, nothing saved");
+            return Ok(());
+        };
+
+        let generation = self.generation + 1;
+        let snapshot = snapshot_dir(&manifest_path, generation);
+
+        // A snapshot with this generation can only be left over from a close that crashed before
+        // it wrote the manifest, so nothing points to it
+        if Path::new(&snapshot).exists() {
+            remove_dir_all(&snapshot).map_err(|e| io_err(&snapshot, e))?;
+        }
+        create_dir_all(&snapshot).map_err(|e| io_err(&snapshot, e))?;
+
         let mut database_meta = RDatabaseMetadata {
+            format_version: FORMAT_VERSION,
             tables: Vec::<RTableMetadata>::new(),
             tables_hashmap: self.tables_hashmap.clone(),
             db_filepath: self.db_filepath.clone(),
+            generation,
+            files: Vec::new(),
+            next_table_id: self.next_table_id,
         };
 
         for table in &self.tables {
-            {
-                // Get the metadata for each table
-                let tm: RTableMetadata = table.read().unwrap().get_metadata();
-                // Push it to database_meta.tables
-                database_meta.tables.push(tm);
-            }
+            let t = table.read().unwrap();
+
+            let dir = table_dir(&snapshot, t.table_num);
+            create_dir_all(&dir).map_err(|e| io_err(&dir, e))?;
+
+            // Get the metadata for each table
+            database_meta.tables.push(t.get_metadata());
 
             // Save the table to disk
-            table.read().unwrap().save_state();
+            t.save_state(&dir).map_err(|e| io_err(&dir, e))?;
+            sync_dir(Path::new(&dir)).map_err(|e| io_err(&dir, e))?;
         }
 
-        match &self.db_filepath {
-            Some(p) => {
-                let writer: Writer<RDatabaseMetadata> = build_binary_writer();
-                writer.write_file(&p, &database_meta);
-            }
-            None => {
-                // This actually happens in testM1.py when .close() gets called even though there
-                // never was a .open to begin with. In this case, we can just create a random
-                // filename and save the database, or do nothing. In this case, we can just do
-                // nothing.
-            }
-        }
+        sync_dir(Path::new(&snapshot)).map_err(|e| io_err(&snapshot, e))?;
+        let snapshots = snapshots_dir(&manifest_path);
+        sync_dir(Path::new(&snapshots)).map_err(|e| io_err(&snapshots, e))?;
+
+        list_files(
+            Path::new(&snapshot),
+            Path::new(&snapshot),
+            &mut database_meta.files,
+        )
+        .map_err(|e| io_err(&snapshot, e))?;
+        database_meta.files.sort();
+
+        // The manifest gets renamed into place, so this is the moment the new snapshot becomes
+        // the database. Crashing before here leaves the old manifest and old snapshot untouched.
+        let writer: Writer<RDatabaseMetadata> = build_binary_writer();
+        writer
+            .write_file(&manifest_path, &database_meta)
+            .map_err(|e| io_err(&manifest_path, e))?;
+        self.generation = generation;
+
+        remove_old_snapshots(&manifest_path, generation);
 
         info!("Database closed!");
+
+        Ok(())
     }
 
     pub fn create_table(
@@ -155,19 +383,33 @@ impl RDatabase {
         name: String,
         num_columns: i64,
         primary_key_column: i64,
-    ) -> RTableHandle {
+    ) -> Result<RTableHandle, DatabaseError> {
+        if self.tables_hashmap.contains_key(&name) {
+            return Err(DatabaseError::TableExists(name));
+        }
+        if num_columns < 1 || !(0..num_columns).contains(&primary_key_column) {
+            return Err(DatabaseError::InvalidSchema {
+                num_columns,
+                primary_key_column,
+            });
+        }
+
         let table = RTable {
             name: name.clone(),
-            page_range: PageRange::new(num_columns as i64),
+            page_range: PageRange::new(num_columns),
             primary_key_column: primary_key_column as usize,
             page_directory: PageDirectory::new(),
             num_columns: num_columns as usize,
             num_records: 0,
             index: Arc::new(RwLock::new(RIndex::new())),
-            table_num: self.tables.len() as i64,
+            table_num: self.next_table_id,
             updates_since_merge: 0,
+            lock_manager: self.lock_manager.clone(),
+            versions: VersionMap::default(),
         };
 
+        self.next_table_id += 1;
+
         let arc_table = Arc::new(RwLock::new(table));
 
         // Set the owner on the index inside the table
@@ -183,26 +425,36 @@ impl RDatabase {
         // Map a name of a table to its index
         self.tables_hashmap.insert(name, i);
 
-        RTableHandle { table: arc_table }
+        Ok(RTableHandle { table: arc_table })
     }
 
-    fn get_table(&self, name: String) -> RTableHandle {
-        let i = self.tables_hashmap.get(&name).expect("Should exist");
-
-        let t = self.tables[*i].clone();
+    pub fn get_table(&self, name: String) -> Result<RTableHandle, DatabaseError> {
+        let Some(&i) = self.tables_hashmap.get(&name) else {
+            return Err(DatabaseError::NoSuchTable(name));
+        };
 
-        RTableHandle { table: t }
+        Ok(RTableHandle {
+            table: self.tables[i].clone(),
+        })
     }
 
-    fn get_table_from_index(&self, i: i64) -> RTableHandle {
-        RTableHandle {
-            table: self.tables[i as usize].clone(),
-        }
+    /// The table with this id, ids stay the same across drop_table, close and open
+    pub fn get_table_by_id(&self, table_id: i64) -> Result<RTableHandle, DatabaseError> {
+        self.tables
+            .iter()
+            .find(|t| t.read().unwrap().table_num == table_id)
+            .map(|t| RTableHandle { table: t.clone() })
+            .ok_or(DatabaseError::NoSuchTableId(table_id))
     }
 
-    fn drop_table(&mut self, name: String) {
-        let i_ref = self.tables_hashmap.get(&name).expect("Should exist");
-        let i = *i_ref;
+    /// Remove the table from the database
+    ///
+    /// Like every other change this only becomes durable with the next close. That snapshot
+    /// does not have the table, and the old snapshot with its files gets deleted with it.
+    pub fn drop_table(&mut self, name: String) -> Result<(), DatabaseError> {
+        let Some(&i) = self.tables_hashmap.get(&name) else {
+            return Err(DatabaseError::NoSuchTable(name));
+        };
 
         // Remove from tables vec
         self.tables.remove(i);
@@ -221,6 +473,201 @@ impl RDatabase {
 
         // Remove from tables hashmap
         self.tables_hashmap.remove(&name);
+
+        Ok(())
+    }
+
+    /// The names of every table, in the order they were created
+    pub fn list_tables(&self) -> Vec<String> {
+        self.tables
+            .iter()
+            .map(|t| t.read().unwrap().name.clone())
+            .collect()
+    }
+
+    /// Columns, key column, record counts, indexes and size on disk of a table
+    pub fn describe_table(&self, name: String) -> Result<TableDescription, DatabaseError> {
+        let Some(&i) = self.tables_hashmap.get(&name) else {
+            return Err(DatabaseError::NoSuchTable(name));
+        };
+
+        let mut description = self.tables[i].read().unwrap().describe();
+        description.disk_size = self.table_disk_size(description.table_id);
+
+        Ok(description)
+    }
+
+    /// Give a table a new name, its id and files stay the same
+    ///
+    /// Locks are taken by table id, so transactions that use the table keep their locks.
+    pub fn rename_table(&mut self, name: String, new_name: String) -> Result<(), DatabaseError> {
+        if self.tables_hashmap.contains_key(&new_name) {
+            return Err(DatabaseError::TableExists(new_name));
+        }
+
+        let Some(i) = self.tables_hashmap.remove(&name) else {
+            return Err(DatabaseError::NoSuchTable(name));
+        };
+
+        self.tables[i].write().unwrap().name = new_name.clone();
+        self.tables_hashmap.insert(new_name, i);
+
+        Ok(())
+    }
+
+    /// Run one SQL statement, see the `sql` module for the dialect
+    pub fn execute(&mut self, sql: &str) -> Result<SqlOutput, SqlError> {
+        sql::execute(self, sql)
+    }
+
+    /// Pick what a transaction does when a lock it wants is taken
+    ///
+    /// One of "no_wait" (the default), "wait_die", "wound_wait" or "timeout". Applies to every
+    /// table of this database, including the ones that already exist.
+    pub fn set_deadlock_strategy(
+        &self,
+        strategy: &str,
+        timeout_ms: Option<u64>,
+    ) -> Result<(), DatabaseError> {
+        let strategy = DeadlockStrategy::from_name(strategy, timeout_ms)
+            .ok_or_else(|| DatabaseError::UnknownDeadlockStrategy(strategy.to_string()))?;
+
+        self.lock_manager.set_strategy(strategy);
+
+        Ok(())
+    }
+
+    /// Which transactions hold a lock on this key, as (transaction id, "Shared" or "Exclusive")
+    pub fn lock_holders(&self, table: String, primary_key: i64) -> Vec<(i64, String)> {
+        let Ok(handle) = self.get_table(table) else {
+            return Vec::new();
+        };
+
+        self.lock_manager
+            .holders(&(handle.get_table_id(), primary_key))
+            .into_iter()
+            .map(|(t, lock_type)| (t, lock_type.to_string()))
+            .collect()
+    }
+
+    /// Every lock held right now as (table, primary key, transaction id, lock type)
+    ///
+    /// Locks on a table that was dropped while a transaction still held them are left out.
+    pub fn dump_locks(&self) -> Vec<(String, i64, i64, String)> {
+        self.lock_manager
+            .dump()
+            .into_iter()
+            .filter_map(|((table_id, pk), t, lock_type)| {
+                let name = self.get_table_by_id(table_id).ok()?.get_name();
+                Some((name, pk, t, lock_type.to_string()))
+            })
+            .collect()
+    }
+}
+
+impl RDatabase {
+    // Bytes in the directory of the table in the current snapshot, changes since then don't count
+    fn table_disk_size(&self, table_id: i64) -> u64 {
+        let Some(manifest_path) = &self.db_filepath else {
+            return 0;
+        };
+
+        let dir = table_dir(&snapshot_dir(manifest_path, self.generation), table_id);
+        let mut files = Vec::new();
+        if list_files(Path::new(&dir), Path::new(&dir), &mut files).is_err() {
+            return 0;
+        }
+
+        files
+            .iter()
+            .filter_map(|f| std::fs::metadata(format!("{}/{}", dir, f)).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RDatabase {
+    #[new]
+    fn py_new() -> Self {
+        RDatabase::new()
+    }
+
+    #[pyo3(name = "open")]
+    fn py_open(&mut self, path: String) -> PyResult<()> {
+        Ok(self.open(path)?)
+    }
+
+    #[pyo3(name = "close")]
+    fn py_close(&mut self) -> PyResult<()> {
+        Ok(self.close()?)
+    }
+
+    #[pyo3(name = "create_table")]
+    fn py_create_table(
+        &mut self,
+        name: String,
+        num_columns: i64,
+        primary_key_column: i64,
+    ) -> PyResult<RTableHandle> {
+        Ok(self.create_table(name, num_columns, primary_key_column)?)
+    }
+
+    #[pyo3(name = "get_table")]
+    fn py_get_table(&self, name: String) -> PyResult<RTableHandle> {
+        Ok(self.get_table(name)?)
+    }
+
+    #[pyo3(name = "get_table_by_id")]
+    fn py_get_table_by_id(&self, table_id: i64) -> PyResult<RTableHandle> {
+        Ok(self.get_table_by_id(table_id)?)
+    }
+
+    #[pyo3(name = "drop_table")]
+    fn py_drop_table(&mut self, name: String) -> PyResult<()> {
+        Ok(self.drop_table(name)?)
+    }
+
+    #[pyo3(name = "list_tables")]
+    fn py_list_tables(&self) -> Vec<String> {
+        self.list_tables()
+    }
+
+    #[pyo3(name = "describe_table")]
+    fn py_describe_table(&self, name: String) -> PyResult<TableDescription> {
+        Ok(self.describe_table(name)?)
+    }
+
+    #[pyo3(name = "rename_table")]
+    fn py_rename_table(&mut self, name: String, new_name: String) -> PyResult<()> {
+        Ok(self.rename_table(name, new_name)?)
+    }
+
+    /// Rows of a SELECT as lists, an int for SUM or how many records changed, None otherwise
+    #[pyo3(name = "execute")]
+    fn py_execute(&mut self, py: Python<'_>, sql: &str) -> PyResult<PyObject> {
+        Ok(match self.execute(sql)? {
+            SqlOutput::Rows(rows) => rows.into_pyobject(py)?.into_any().unbind(),
+            SqlOutput::Sum(sum) => sum.into_pyobject(py)?.into_any().unbind(),
+            SqlOutput::Affected(count) => count.into_pyobject(py)?.into_any().unbind(),
+            SqlOutput::Done => py.None(),
+        })
+    }
+
+    #[pyo3(name = "set_deadlock_strategy", signature = (strategy, timeout_ms=None))]
+    fn py_set_deadlock_strategy(&self, strategy: &str, timeout_ms: Option<u64>) -> PyResult<()> {
+        Ok(self.set_deadlock_strategy(strategy, timeout_ms)?)
+    }
+
+    #[pyo3(name = "lock_holders")]
+    fn py_lock_holders(&self, table: String, primary_key: i64) -> Vec<(i64, String)> {
+        self.lock_holders(table, primary_key)
+    }
+
+    #[pyo3(name = "dump_locks")]
+    fn py_dump_locks(&self) -> Vec<(String, i64, i64, String)> {
+        self.dump_locks()
     }
 }
 
@@ -234,11 +681,11 @@ mod tests {
         let mut db = RDatabase::new();
 
         // Create a table "users"
-        db.create_table(String::from("users"), 1, 0);
+        db.create_table(String::from("users"), 1, 0).unwrap();
 
         assert_eq!(db.tables.len(), 1);
 
-        db.drop_table("users".to_string());
+        db.drop_table("users".to_string()).unwrap();
 
         assert_eq!(db.tables.len(), 0);
     }
@@ -247,13 +694,13 @@ mod tests {
     fn drop_on_of_many_tables_test() {
         let mut db = RDatabase::new();
 
-        db.create_table(String::from("users"), 1, 0);
-        db.create_table(String::from("accounts"), 2, 0);
-        db.create_table(String::from("bikes"), 4, 0);
+        db.create_table(String::from("users"), 1, 0).unwrap();
+        db.create_table(String::from("accounts"), 2, 0).unwrap();
+        db.create_table(String::from("bikes"), 4, 0).unwrap();
 
         assert_eq!(db.tables.len(), 3);
 
-        db.drop_table("users".to_string());
+        db.drop_table("users".to_string()).unwrap();
 
         assert_eq!(db.tables.len(), 2);
     }
@@ -263,7 +710,7 @@ mod tests {
         let mut db = RDatabase::new();
 
         // Create a table
-        let table = db.create_table(String::from("users"), 3, 0);
+        let table = db.create_table(String::from("users"), 3, 0).unwrap();
 
         // Verify table was added to the database
         assert_eq!(db.tables.len(), 1);
@@ -292,7 +739,9 @@ mod tests {
         let mut db = RDatabase::new();
 
         // Create a table
-        let table = db.create_table(String::from("users_to_drop"), 3, 0);
+        let table = db
+            .create_table(String::from("users_to_drop"), 3, 0)
+            .unwrap();
 
         let weak_ref: Option<Weak<RwLock<RTable>>>;
         {
@@ -321,7 +770,7 @@ mod tests {
         }
 
         // Drop the table
-        db.drop_table("users_to_drop".to_string());
+        db.drop_table("users_to_drop".to_string()).unwrap();
 
         // Verify the table is removed from the database
         assert_eq!(db.tables.len(), 0);
@@ -339,7 +788,7 @@ mod tests {
         let mut db = RDatabase::new();
 
         // Create a table
-        let table1 = db.create_table(String::from("users"), 3, 0);
+        let table1 = db.create_table(String::from("users"), 3, 0).unwrap();
 
         {
             table1.table.write().unwrap().write(vec![1, 2, 3]);
@@ -351,7 +800,7 @@ mod tests {
         assert_eq!(*db.tables_hashmap.get("users").unwrap(), 0);
 
         // insert data into the table
-        let table2 = db.get_table("users".to_string());
+        let table2 = db.get_table("users".to_string()).unwrap();
         assert_eq!(
             table1.table.read().unwrap().num_records.clone(),
             table2.table.read().unwrap().num_records.clone()
@@ -391,4 +840,381 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn create_duplicate_table_test() {
+        let mut db = RDatabase::new();
+        db.create_table(String::from("users"), 2, 0).unwrap();
+
+        assert!(matches!(
+            db.create_table(String::from("users"), 3, 0),
+            Err(DatabaseError::TableExists(name)) if name == "users"
+        ));
+        assert_eq!(db.tables.len(), 1);
+        assert_eq!(db.next_table_id, 1);
+    }
+
+    #[test]
+    fn create_table_with_bad_schema_test() {
+        let mut db = RDatabase::new();
+
+        for (num_columns, primary_key_column) in [(3, 5), (3, 3), (3, -1), (0, 0)] {
+            assert!(matches!(
+                db.create_table(String::from("bad"), num_columns, primary_key_column),
+                Err(DatabaseError::InvalidSchema { .. })
+            ));
+        }
+        assert!(db.tables.is_empty());
+        assert_eq!(db.next_table_id, 0);
+    }
+
+    #[test]
+    fn rename_table_test() {
+        let mut db = RDatabase::new();
+        db.create_table(String::from("users"), 2, 0).unwrap();
+        db.create_table(String::from("accounts"), 2, 0).unwrap();
+
+        assert!(db
+            .rename_table(String::from("users"), String::from("accounts"))
+            .is_err());
+        assert!(db
+            .rename_table(String::from("bikes"), String::from("cars"))
+            .is_err());
+
+        db.rename_table(String::from("users"), String::from("people"))
+            .unwrap();
+        assert!(!db.tables_hashmap.contains_key("users"));
+        assert_eq!(
+            db.get_table(String::from("people")).unwrap().get_table_id(),
+            0
+        );
+    }
+
+    #[test]
+    fn set_deadlock_strategy_test() {
+        let db = RDatabase::new();
+        let table = db.lock_manager.clone();
+
+        db.set_deadlock_strategy("wound_wait", None).unwrap();
+        assert_eq!(table.strategy(), DeadlockStrategy::WoundWait);
+
+        db.set_deadlock_strategy("timeout", Some(5)).unwrap();
+        assert_eq!(
+            table.strategy(),
+            DeadlockStrategy::Timeout(std::time::Duration::from_millis(5))
+        );
+
+        assert!(db.set_deadlock_strategy("wait_forever", None).is_err());
+    }
+
+    #[test]
+    fn lock_introspection_test() {
+        let mut db = RDatabase::new();
+        let grades = db.create_table(String::from("Grades"), 2, 0).unwrap();
+        let key = (grades.get_table_id(), 3);
+
+        db.lock_manager.acquire(7, &key, LockType::Shared).unwrap();
+        db.lock_manager.acquire(8, &key, LockType::Shared).unwrap();
+
+        assert_eq!(
+            db.lock_holders(String::from("Grades"), 3),
+            vec![(7, String::from("Shared")), (8, String::from("Shared"))]
+        );
+        assert_eq!(db.dump_locks().len(), 2);
+
+        db.lock_manager.release_all(7, [&key]);
+        db.lock_manager.release_all(8, [&key]);
+        assert!(db.dump_locks().is_empty());
+    }
+
+    mod snapshot_tests {
+        use super::*;
+        use crate::container::{base_page_path, tail_page_path};
+        use crate::query::RQuery;
+
+        /// Start every test from a clean manifest and snapshot directory
+        fn fresh_manifest(name: &str) -> String {
+            let manifest = format!("./test-outputs/{}", name);
+            let _ = std::fs::remove_file(&manifest);
+            remove_old_snapshots(&manifest, u64::MAX);
+            manifest
+        }
+
+        fn write_scores(manifest: &str) {
+            let mut db = RDatabase::new();
+            db.open(manifest.to_string()).unwrap();
+
+            let mut q = RQuery::new(db.create_table(String::from("Scores"), 3, 0).unwrap());
+            q.insert(vec![1, 10, 100]);
+            q.insert(vec![2, 20, 200]);
+
+            db.close().unwrap();
+        }
+
+        #[test]
+        fn close_and_open_round_trip_test() {
+            let manifest = fresh_manifest("round-trip.db");
+            write_scores(&manifest);
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+
+            let mut q = RQuery::new(db.get_table(String::from("Scores")).unwrap());
+            let row = q.select(2, 0, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+            assert_eq!(row.columns, vec![Some(2), Some(20), Some(200)]);
+        }
+
+        #[test]
+        fn updated_table_round_trip_test() {
+            let manifest = fresh_manifest("updated.db");
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let mut q = RQuery::new(db.create_table(String::from("Scores"), 3, 0).unwrap());
+            for key in 1..=5 {
+                q.insert(vec![key, key * 10, key * 100]);
+            }
+            q.update(2, vec![None, Some(21), None]);
+            q.update(4, vec![None, None, Some(401)]);
+            q.update(4, vec![None, Some(41), None]);
+            db.close().unwrap();
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let mut q = RQuery::new(db.get_table(String::from("Scores")).unwrap());
+
+            let expected = [
+                (1, vec![Some(1), Some(10), Some(100)]),
+                (2, vec![Some(2), Some(21), Some(200)]),
+                (3, vec![Some(3), Some(30), Some(300)]),
+                (4, vec![Some(4), Some(41), Some(401)]),
+                (5, vec![Some(5), Some(50), Some(500)]),
+            ];
+            for (key, columns) in expected {
+                let row = q.select(key, 0, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+                assert_eq!(row.columns, columns);
+            }
+
+            // Older versions come from the tail pages too
+            let row = q.select_version(4, 0, vec![1, 1, 1], 1).unwrap()[0]
+                .clone()
+                .unwrap();
+            assert_eq!(row.columns, vec![Some(4), Some(40), Some(401)]);
+
+            // Updates after the reopen go on the loaded tail pages
+            assert!(q.update(2, vec![None, None, Some(202)]));
+            let row = q.select(2, 0, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+            assert_eq!(row.columns, vec![Some(2), Some(21), Some(202)]);
+        }
+
+        #[test]
+        fn close_replaces_old_snapshot_test() {
+            let manifest = fresh_manifest("replace.db");
+            write_scores(&manifest);
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            assert_eq!(db.generation, 1);
+            db.close().unwrap();
+
+            assert!(!Path::new(&snapshot_dir(&manifest, 1)).exists());
+            assert!(Path::new(&snapshot_dir(&manifest, 2)).exists());
+        }
+
+        #[test]
+        fn same_file_name_in_other_dir_test() {
+            create_dir_all("./test-outputs/first").unwrap();
+            create_dir_all("./test-outputs/second").unwrap();
+            let first = fresh_manifest("first/same.db");
+            let second = fresh_manifest("second/same.db");
+            write_scores(&first);
+            write_scores(&second);
+
+            // Closing the second database again must not touch the snapshot of the first
+            let mut db = RDatabase::new();
+            db.open(second.clone()).unwrap();
+            db.close().unwrap();
+
+            assert!(Path::new(&snapshot_dir(&first, 1)).exists());
+            assert!(Path::new(&snapshot_dir(&second, 2)).exists());
+            assert!(!Path::new(&snapshot_dir(&second, 1)).exists());
+
+            let mut db = RDatabase::new();
+            db.open(first).unwrap();
+            assert_eq!(db.list_tables(), vec!["Scores"]);
+        }
+
+        #[test]
+        fn open_ignores_unfinished_snapshot_test() {
+            let manifest = fresh_manifest("crashed.db");
+            write_scores(&manifest);
+
+            // Pretend a close crashed halfway through writing the next snapshot
+            let unfinished = table_dir(&snapshot_dir(&manifest, 2), 0);
+            create_dir_all(&unfinished).unwrap();
+            std::fs::write(base_page_path(&unfinished, 0), b"half a page").unwrap();
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            assert_eq!(db.tables.len(), 1);
+            assert!(!Path::new(&snapshot_dir(&manifest, 2)).exists());
+        }
+
+        #[test]
+        fn drop_table_applies_on_close_test() {
+            let manifest = fresh_manifest("drop-table.db");
+            write_scores(&manifest);
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let dir = table_dir(&snapshot_dir(&manifest, 1), 0);
+
+            db.drop_table(String::from("Scores")).unwrap();
+            assert!(db.drop_table(String::from("Scores")).is_err());
+
+            // Nothing on disk changes before the close
+            assert!(Path::new(&dir).exists());
+            let mut reopened = RDatabase::new();
+            reopened.open(manifest.clone()).unwrap();
+            assert_eq!(reopened.list_tables(), vec!["Scores"]);
+
+            db.close().unwrap();
+            assert!(!Path::new(&dir).exists());
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            assert!(db.tables.is_empty());
+            assert_eq!(db.next_table_id, 1);
+        }
+
+        #[test]
+        fn table_ids_survive_drop_and_reopen_test() {
+            let manifest = fresh_manifest("table-ids.db");
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            db.create_table(String::from("users"), 2, 0).unwrap();
+            let mut accounts =
+                RQuery::new(db.create_table(String::from("accounts"), 2, 0).unwrap());
+            accounts.insert(vec![1, 100]);
+
+            db.drop_table(String::from("users")).unwrap();
+            let mut bikes = RQuery::new(db.create_table(String::from("bikes"), 2, 0).unwrap());
+            bikes.insert(vec![1, 7]);
+            db.close().unwrap();
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            assert_eq!(db.get_table_by_id(1).unwrap().get_name(), "accounts");
+            assert_eq!(db.get_table_by_id(2).unwrap().get_name(), "bikes");
+            assert!(db.get_table_by_id(0).is_err());
+
+            let mut accounts = RQuery::new(db.get_table(String::from("accounts")).unwrap());
+            let mut bikes = RQuery::new(db.get_table(String::from("bikes")).unwrap());
+            assert_eq!(
+                accounts.select(1, 0, vec![1, 1]).unwrap()[0]
+                    .clone()
+                    .unwrap()
+                    .columns,
+                vec![Some(1), Some(100)]
+            );
+            assert_eq!(
+                bikes.select(1, 0, vec![1, 1]).unwrap()[0]
+                    .clone()
+                    .unwrap()
+                    .columns,
+                vec![Some(1), Some(7)]
+            );
+        }
+
+        #[test]
+        fn altered_table_round_trip_test() {
+            let manifest = fresh_manifest("altered.db");
+            write_scores(&manifest);
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let scores = db.get_table(String::from("Scores")).unwrap();
+            scores.drop_column(1).unwrap();
+            scores.add_column(7);
+            scores.reorder_columns(&[2, 0, 1]).unwrap();
+            db.rename_table(String::from("Scores"), String::from("Results"))
+                .unwrap();
+            db.close().unwrap();
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let results = db.get_table(String::from("Results")).unwrap();
+            assert_eq!(results.get_primary_key_column(), 1);
+
+            let mut q = RQuery::new(results);
+            let row = q.select(2, 1, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+            assert_eq!(row.columns, vec![Some(7), Some(2), Some(200)]);
+        }
+
+        #[test]
+        fn add_column_after_merge_round_trip_test() {
+            let manifest = fresh_manifest("merged-altered.db");
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let scores = db.create_table(String::from("Scores"), 3, 0).unwrap();
+            let mut q = RQuery::new(scores.clone());
+            for key in 1..=3 {
+                q.insert(vec![key, key * 10, key * 100]);
+            }
+            q.update(2, vec![None, Some(21), None]);
+            scores.table.write().unwrap().merge();
+            scores.add_column(7);
+            db.close().unwrap();
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            let mut q = RQuery::new(db.get_table(String::from("Scores")).unwrap());
+            for (key, columns) in [
+                (1, vec![Some(1), Some(10), Some(100), Some(7)]),
+                (2, vec![Some(2), Some(21), Some(200), Some(7)]),
+                (3, vec![Some(3), Some(30), Some(300), Some(7)]),
+            ] {
+                let row = q.select(key, 0, vec![1, 1, 1, 1]).unwrap()[0]
+                    .clone()
+                    .unwrap();
+                assert_eq!(row.columns, columns);
+            }
+        }
+
+        #[test]
+        fn describe_saved_table_test() {
+            let manifest = fresh_manifest("describe.db");
+            write_scores(&manifest);
+
+            let mut db = RDatabase::new();
+            db.open(manifest.clone()).unwrap();
+            db.create_table(String::from("Unsaved"), 2, 1).unwrap();
+            assert_eq!(db.list_tables(), vec!["Scores", "Unsaved"]);
+
+            let scores = db.describe_table(String::from("Scores")).unwrap();
+            assert_eq!(scores.num_records, 2);
+            assert!(scores.disk_size > 0);
+
+            let unsaved = db.describe_table(String::from("Unsaved")).unwrap();
+            assert_eq!(unsaved.primary_key_column, 1);
+            assert_eq!(unsaved.indexed_columns, vec![1]);
+            assert_eq!(unsaved.disk_size, 0);
+
+            assert!(db.describe_table(String::from("Missing")).is_err());
+        }
+
+        #[test]
+        fn open_with_missing_snapshot_file_fails_test() {
+            let manifest = fresh_manifest("missing.db");
+            write_scores(&manifest);
+
+            let page = tail_page_path(&table_dir(&snapshot_dir(&manifest, 1), 0), 0);
+            std::fs::remove_file(page).unwrap();
+
+            let mut db = RDatabase::new();
+            assert!(db.open(manifest).is_err());
+        }
+    }
 }
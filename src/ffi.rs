@@ -0,0 +1,695 @@
+//! A C API over `api`, for programs that embed RedoxQL without Python
+//!
+//! Databases, tables, transactions and select results are opaque handles. Every handle a
+//! function hands out has to be given back to its `_free` function. Functions that can fail
+//! return a `RedoxStatus`, and `redoxql_last_error` has the message of the last failure on the
+//! calling thread. The header is `include/redoxql.h`, regenerate it with
+//! `cargo build --features header`.
+//!
+//! # Safety
+//!
+//! Pointers have to be null or point to what the header says: handles from this library that
+//! were not freed yet, NUL terminated strings, and arrays of at least the given length. Passing
+//! null where a value is needed returns `REDOX_STATUS_NULL_POINTER`. A handle must not be used
+//! from two threads at the same time.
+
+use super::api::{Database, Error, Operation, QueryResult, Table, Transaction};
+use super::database::DatabaseError;
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// What a call of the C API did
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedoxStatus {
+    Ok = 0,
+    /// A pointer that has to point to something was null
+    NullPointer,
+    /// A string was not UTF-8
+    InvalidUtf8,
+    /// A file of the database could not be read or written
+    Io,
+    NoSuchTable,
+    TableExists,
+    DuplicateKey,
+    NoSuchRecord,
+    /// A column, projection or argument does not fit the table
+    InvalidArgument,
+    /// The transaction has not begun, already began, or already ended
+    TransactionState,
+    NoSuchSavepoint,
+    /// A query of an interactive transaction failed, check if the transaction is still active
+    QueryFailed,
+    /// The transaction was aborted and none of its writes were applied
+    Aborted,
+    /// A row or column past the end of a select result
+    OutOfRange,
+    /// The column was not projected, so it has no value
+    NullValue,
+    /// Something went wrong inside RedoxQL, the message says what
+    Panic,
+}
+
+pub struct RedoxDatabase {
+    db: Database,
+}
+
+pub struct RedoxTable {
+    table: Table,
+}
+
+pub struct RedoxTransaction {
+    transaction: Transaction,
+}
+
+/// The records a select found
+pub struct RedoxRows {
+    rows: Vec<Vec<Option<i64>>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: String) {
+    // A message can't hold a NUL byte in C, so cut it there
+    let message = message.split('\0').next().unwrap_or_default().to_string();
+    let message = CString::new(message).unwrap_or_default();
+
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+fn status_of(err: &Error) -> RedoxStatus {
+    match err {
+        Error::Database(
+            DatabaseError::File(_) | DatabaseError::Io { .. } | DatabaseError::MissingFile { .. },
+        ) => RedoxStatus::Io,
+        Error::Database(DatabaseError::NoSuchTable(_) | DatabaseError::NoSuchTableId(_)) => {
+            RedoxStatus::NoSuchTable
+        }
+        Error::Database(DatabaseError::TableExists(_)) => RedoxStatus::TableExists,
+        Error::Database(
+            DatabaseError::InvalidSchema { .. } | DatabaseError::UnknownDeadlockStrategy(_),
+        )
+        | Error::Schema(_)
+        | Error::Operation(_)
+        | Error::Sql(_)
+        | Error::WrongProjection { .. } => RedoxStatus::InvalidArgument,
+        Error::DuplicateKey(_) => RedoxStatus::DuplicateKey,
+        Error::NoSuchRecord(_) => RedoxStatus::NoSuchRecord,
+        Error::NotActive(_) | Error::AlreadyActive(_) => RedoxStatus::TransactionState,
+        Error::NoSuchSavepoint(_) => RedoxStatus::NoSuchSavepoint,
+        Error::QueryFailed { .. } => RedoxStatus::QueryFailed,
+        Error::Aborted(_) => RedoxStatus::Aborted,
+    }
+}
+
+// The status a failed call returns and the message redoxql_last_error gives for it
+struct Failure(RedoxStatus, String);
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        Failure(status_of(&err), err.to_string())
+    }
+}
+
+// Run `f`, remember why it failed and never let a panic cross into C
+fn guard(f: impl FnOnce() -> Result<(), Failure>) -> RedoxStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => RedoxStatus::Ok,
+        Ok(Err(Failure(status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("RedoxQL panicked"));
+            set_last_error(message);
+            RedoxStatus::Panic
+        }
+    }
+}
+
+unsafe fn reference<'a, T>(pointer: *const T, name: &str) -> Result<&'a T, Failure> {
+    pointer
+        .as_ref()
+        .ok_or_else(|| Failure(RedoxStatus::NullPointer, format!("{} can't be null", name)))
+}
+
+unsafe fn mutable<'a, T>(pointer: *mut T, name: &str) -> Result<&'a mut T, Failure> {
+    pointer
+        .as_mut()
+        .ok_or_else(|| Failure(RedoxStatus::NullPointer, format!("{} can't be null", name)))
+}
+
+unsafe fn string(pointer: *const c_char, name: &str) -> Result<String, Failure> {
+    if pointer.is_null() {
+        return Err(Failure(
+            RedoxStatus::NullPointer,
+            format!("{} can't be null", name),
+        ));
+    }
+
+    CStr::from_ptr(pointer)
+        .to_str()
+        .map(|s| s.to_string())
+        .map_err(|_| Failure(RedoxStatus::InvalidUtf8, format!("{} is not UTF-8", name)))
+}
+
+unsafe fn slice<'a, T>(pointer: *const T, len: usize, name: &str) -> Result<&'a [T], Failure> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    if pointer.is_null() {
+        return Err(Failure(
+            RedoxStatus::NullPointer,
+            format!("{} can't be null", name),
+        ));
+    }
+
+    Ok(std::slice::from_raw_parts(pointer, len))
+}
+
+// The columns of an update, `present[i]` says whether `values[i]` is set
+unsafe fn columns(
+    values: *const i64,
+    present: *const bool,
+    len: usize,
+) -> Result<Vec<Option<i64>>, Failure> {
+    let values = slice(values, len, "values")?;
+    let present = slice(present, len, "present")?;
+
+    Ok(values
+        .iter()
+        .zip(present)
+        .map(|(&v, &p)| p.then_some(v))
+        .collect())
+}
+
+// Hand a select result to C, it owns it until redoxql_rows_free
+unsafe fn put_rows(out: *mut *mut RedoxRows, rows: Vec<Vec<Option<i64>>>) -> Result<(), Failure> {
+    let out = mutable(out, "out")?;
+    *out = Box::into_raw(Box::new(RedoxRows { rows }));
+    Ok(())
+}
+
+/// The message of the last call on this thread that did not return `REDOX_STATUS_OK`
+///
+/// The string stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn redoxql_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// A database that only lives in memory, free it with `redoxql_database_free`
+#[no_mangle]
+pub extern "C" fn redoxql_database_new() -> *mut RedoxDatabase {
+    Box::into_raw(Box::new(RedoxDatabase {
+        db: Database::new(),
+    }))
+}
+
+/// Load the database whose manifest is at `path`, a missing manifest gives an empty database
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_database_open(
+    path: *const c_char,
+    out: *mut *mut RedoxDatabase,
+) -> RedoxStatus {
+    guard(|| {
+        let path = string(path, "path")?;
+        let out = mutable(out, "out")?;
+
+        let db = Database::open(&path)?;
+        *out = Box::into_raw(Box::new(RedoxDatabase { db }));
+        Ok(())
+    })
+}
+
+/// Save the database to the path it was opened with, the handle can still be used after
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_database_close(db: *mut RedoxDatabase) -> RedoxStatus {
+    guard(|| {
+        mutable(db, "db")?.db.close()?;
+        Ok(())
+    })
+}
+
+/// Free a database without saving it, tables taken from it stay usable until they are freed
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_database_free(db: *mut RedoxDatabase) {
+    if !db.is_null() {
+        drop(Box::from_raw(db));
+    }
+}
+
+/// Fails with TableExists if the database already has a table with this name, and with
+/// InvalidArgument if the primary key column is not one of the columns
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_create_table(
+    db: *mut RedoxDatabase,
+    name: *const c_char,
+    num_columns: usize,
+    primary_key_column: usize,
+    out: *mut *mut RedoxTable,
+) -> RedoxStatus {
+    guard(|| {
+        let db = mutable(db, "db")?;
+        let name = string(name, "name")?;
+        let out = mutable(out, "out")?;
+
+        let table = db.db.create_table(&name, num_columns, primary_key_column)?;
+        *out = Box::into_raw(Box::new(RedoxTable { table }));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_get_table(
+    db: *const RedoxDatabase,
+    name: *const c_char,
+    out: *mut *mut RedoxTable,
+) -> RedoxStatus {
+    guard(|| {
+        let db = reference(db, "db")?;
+        let name = string(name, "name")?;
+        let out = mutable(out, "out")?;
+
+        let table = db.db.table(&name)?;
+        *out = Box::into_raw(Box::new(RedoxTable { table }));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_drop_table(
+    db: *mut RedoxDatabase,
+    name: *const c_char,
+) -> RedoxStatus {
+    guard(|| {
+        let db = mutable(db, "db")?;
+        let name = string(name, "name")?;
+
+        db.db.drop_table(&name)?;
+        Ok(())
+    })
+}
+
+/// Free a table handle, the table itself stays in its database
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_table_free(table: *mut RedoxTable) {
+    if !table.is_null() {
+        drop(Box::from_raw(table));
+    }
+}
+
+/// The number of columns of the table, 0 if it is null
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_table_num_columns(table: *const RedoxTable) -> usize {
+    table.as_ref().map_or(0, |t| t.table.num_columns())
+}
+
+/// Insert a record, `values` has a value for every column
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_insert(
+    table: *const RedoxTable,
+    values: *const i64,
+    len: usize,
+) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+        let values = slice(values, len, "values")?;
+
+        table.table.query().insert(values)?;
+        Ok(())
+    })
+}
+
+/// Update a record, only the columns whose entry in `present` is true are set
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_update(
+    table: *const RedoxTable,
+    primary_key: i64,
+    values: *const i64,
+    present: *const bool,
+    len: usize,
+) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+        let columns = columns(values, present, len)?;
+
+        table.table.query().update(primary_key, &columns)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_delete(table: *const RedoxTable, primary_key: i64) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+
+        table.table.query().delete(primary_key)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_increment(
+    table: *const RedoxTable,
+    primary_key: i64,
+    column: usize,
+) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+
+        table.table.query().increment(primary_key, column)?;
+        Ok(())
+    })
+}
+
+/// Every record whose `column` is `key`, `projected` has a 1 for each column to return
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_select(
+    table: *const RedoxTable,
+    key: i64,
+    column: usize,
+    projected: *const i64,
+    len: usize,
+    out: *mut *mut RedoxRows,
+) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+        let projected = slice(projected, len, "projected")?;
+
+        let rows = table.table.query().select(key, column, projected)?;
+        put_rows(out, rows)
+    })
+}
+
+/// An older version of a record, -1 is the version before the newest one
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_select_version(
+    table: *const RedoxTable,
+    primary_key: i64,
+    projected: *const i64,
+    len: usize,
+    relative_version: i64,
+    out: *mut *mut RedoxRows,
+) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+        let projected = slice(projected, len, "projected")?;
+
+        let rows = table
+            .table
+            .query()
+            .select_version(primary_key, projected, relative_version)?;
+        put_rows(out, rows)
+    })
+}
+
+/// The sum of `column` over the records with a primary key from `start` to `end`, both included
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_sum(
+    table: *const RedoxTable,
+    start: i64,
+    end: i64,
+    column: usize,
+    out: *mut i64,
+) -> RedoxStatus {
+    redoxql_sum_version(table, start, end, column, 0, out)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_sum_version(
+    table: *const RedoxTable,
+    start: i64,
+    end: i64,
+    column: usize,
+    relative_version: i64,
+    out: *mut i64,
+) -> RedoxStatus {
+    guard(|| {
+        let table = reference(table, "table")?;
+        let out = mutable(out, "out")?;
+
+        *out = table
+            .table
+            .query()
+            .sum_version(start, end, column, relative_version)?;
+        Ok(())
+    })
+}
+
+/// How many records a select found, 0 if `rows` is null
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_rows_count(rows: *const RedoxRows) -> usize {
+    rows.as_ref().map_or(0, |r| r.rows.len())
+}
+
+/// One value of a select result, `REDOX_STATUS_NULL_VALUE` if the column was not projected
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_rows_value(
+    rows: *const RedoxRows,
+    row: usize,
+    column: usize,
+    out: *mut i64,
+) -> RedoxStatus {
+    guard(|| {
+        let rows = reference(rows, "rows")?;
+        let out = mutable(out, "out")?;
+
+        let Some(value) = rows.rows.get(row).and_then(|r| r.get(column)) else {
+            return Err(Failure(
+                RedoxStatus::OutOfRange,
+                format!("No column {} in row {}", column, row),
+            ));
+        };
+        let Some(value) = value else {
+            return Err(Failure(
+                RedoxStatus::NullValue,
+                format!("Column {} was not projected", column),
+            ));
+        };
+
+        *out = *value;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_rows_free(rows: *mut RedoxRows) {
+    if !rows.is_null() {
+        drop(Box::from_raw(rows));
+    }
+}
+
+/// A transaction that has begun, run queries in it with the `redoxql_transaction_` functions
+#[no_mangle]
+pub extern "C" fn redoxql_transaction_begin() -> *mut RedoxTransaction {
+    let mut transaction = Transaction::new();
+    transaction
+        .begin()
+        .expect("Should be able to begin a new transaction");
+
+    Box::into_raw(Box::new(RedoxTransaction { transaction }))
+}
+
+/// Apply the writes of the transaction and release its locks, Aborted if a write could not be
+/// applied, then none of them are
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_commit(t: *mut RedoxTransaction) -> RedoxStatus {
+    guard(|| {
+        mutable(t, "transaction")?.transaction.commit()?;
+        Ok(())
+    })
+}
+
+/// Drop the writes of the transaction and release its locks
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_abort(t: *mut RedoxTransaction) -> RedoxStatus {
+    guard(|| {
+        mutable(t, "transaction")?.transaction.abort()?;
+        Ok(())
+    })
+}
+
+/// False once the transaction committed or aborted, also after a failed query aborted it
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_is_active(t: *const RedoxTransaction) -> bool {
+    t.as_ref().is_some_and(|t| t.transaction.is_active())
+}
+
+/// Free a transaction, one that is still active is aborted first
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_free(t: *mut RedoxTransaction) {
+    if t.is_null() {
+        return;
+    }
+
+    // Dropping the transaction aborts it
+    drop(Box::from_raw(t));
+}
+
+// Run one query in the transaction, the result goes to whoever asked for it
+unsafe fn execute(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    op: impl FnOnce(&Table) -> Result<Operation, Failure>,
+) -> Result<QueryResult, Failure> {
+    let t = mutable(t, "transaction")?;
+    let table = reference(table, "table")?;
+
+    let op = op(&table.table)?;
+    Ok(t.transaction.execute(&table.table, op)?)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_insert(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    values: *const i64,
+    len: usize,
+) -> RedoxStatus {
+    guard(|| {
+        execute(t, table, |_| {
+            Ok(Operation::Insert(slice(values, len, "values")?.to_vec()))
+        })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_update(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    primary_key: i64,
+    values: *const i64,
+    present: *const bool,
+    len: usize,
+) -> RedoxStatus {
+    guard(|| {
+        execute(t, table, |_| {
+            Ok(Operation::Update {
+                primary_key,
+                columns: columns(values, present, len)?,
+            })
+        })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_delete(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    primary_key: i64,
+) -> RedoxStatus {
+    guard(|| {
+        execute(t, table, |_| Ok(Operation::Delete { primary_key }))?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_increment(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    primary_key: i64,
+    column: usize,
+) -> RedoxStatus {
+    guard(|| {
+        execute(t, table, |_| {
+            Ok(Operation::Increment {
+                primary_key,
+                column: column as i64,
+            })
+        })?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_select(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    key: i64,
+    column: usize,
+    projected: *const i64,
+    len: usize,
+    out: *mut *mut RedoxRows,
+) -> RedoxStatus {
+    guard(|| {
+        let result = execute(t, table, |_| {
+            Ok(Operation::Select {
+                key,
+                key_index: column as i64,
+                projected: slice(projected, len, "projected")?.to_vec(),
+            })
+        })?;
+
+        let rows = match result {
+            QueryResult::Records(records) => records.into_iter().map(|r| r.columns).collect(),
+            _ => Vec::new(),
+        };
+        put_rows(out, rows)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_sum(
+    t: *mut RedoxTransaction,
+    table: *const RedoxTable,
+    start: i64,
+    end: i64,
+    column: usize,
+    out: *mut i64,
+) -> RedoxStatus {
+    guard(|| {
+        let out = mutable(out, "out")?;
+        let result = execute(t, table, |_| {
+            Ok(Operation::Sum {
+                start,
+                end,
+                column: column as i64,
+            })
+        })?;
+
+        if let QueryResult::Sum(sum) = result {
+            *out = sum;
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_savepoint(
+    t: *mut RedoxTransaction,
+    name: *const c_char,
+) -> RedoxStatus {
+    guard(|| {
+        let t = mutable(t, "transaction")?;
+        let name = string(name, "name")?;
+
+        t.transaction.savepoint(&name)?;
+        Ok(())
+    })
+}
+
+/// Undo everything done since the savepoint, the savepoint itself stays
+#[no_mangle]
+pub unsafe extern "C" fn redoxql_transaction_rollback_to_savepoint(
+    t: *mut RedoxTransaction,
+    name: *const c_char,
+) -> RedoxStatus {
+    guard(|| {
+        let t = mutable(t, "transaction")?;
+        let name = string(name, "name")?;
+
+        t.transaction.rollback_to_savepoint(&name)?;
+        Ok(())
+    })
+}
@@ -1,28 +1,280 @@
+use super::checksum::crc32;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::fs::{read_to_string, File};
-use std::io::{BufReader, BufWriter, Write};
+use std::fmt;
+use std::fs::{read, read_to_string, rename, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Every binary file starts with these bytes so we can tell our files apart from anything else
+pub const MAGIC: [u8; 4] = *b"RDXQ";
+
+/// The version of the on-disk format. Bump this when the header or the encoding of anything we
+/// persist changes, and add a step to `migration` that upgrades the previous version.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// magic (4) + format version (2) + reserved (2) + payload length (8) + payload CRC-32 (4)
+pub const HEADER_SIZE: usize = 20;
+
+/// The reasons reading a persisted file can fail
+///
+/// Each variant carries the path of the file so the error tells you exactly which file on disk
+/// is bad instead of panicking somewhere inside bincode.
+#[derive(Debug)]
+pub enum FileError {
+    /// The file could not be opened or read
+    Io { path: String, message: String },
+    /// The file does not start with `MAGIC`, so it was not written by us
+    BadMagic { path: String },
+    /// The file was written with a format version this build does not understand
+    UnsupportedVersion { path: String, version: u16 },
+    /// The file is shorter than its header says it should be (e.g. a torn write)
+    Truncated {
+        path: String,
+        expected: u64,
+        found: u64,
+    },
+    /// The payload does not match the checksum stored in the header
+    ChecksumMismatch {
+        path: String,
+        expected: u32,
+        found: u32,
+    },
+    /// The payload passed the checksum but could not be decoded into the expected type
+    Deserialize { path: String, message: String },
+    /// The files of an older format version lost data, so they can't be upgraded
+    CannotMigrate { path: String, message: String },
+}
+
+impl FileError {
+    /// The path of the file that caused the error
+    pub fn path(&self) -> &str {
+        match self {
+            FileError::Io { path, .. }
+            | FileError::BadMagic { path }
+            | FileError::UnsupportedVersion { path, .. }
+            | FileError::Truncated { path, .. }
+            | FileError::ChecksumMismatch { path, .. }
+            | FileError::Deserialize { path, .. }
+            | FileError::CannotMigrate { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Io { path, message } => write!(f, "Could not read {}: {}", path, message),
+            FileError::BadMagic { path } => {
+                write!(f, "Corrupted file {}: not a RedoxQL data file", path)
+            }
+            FileError::UnsupportedVersion { path, version } => write!(
+                f,
+                "Unsupported file {}: format version {} (this build reads version {})",
+                path, version, FORMAT_VERSION
+            ),
+            FileError::Truncated {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Corrupted file {}: expected {} bytes of data but found {}",
+                path, expected, found
+            ),
+            FileError::ChecksumMismatch {
+                path,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Corrupted file {}: checksum mismatch (expected {:#010x}, found {:#010x})",
+                path, expected, found
+            ),
+            FileError::Deserialize { path, message } => {
+                write!(f, "Corrupted file {}: {}", path, message)
+            }
+            FileError::CannotMigrate { path, message } => {
+                write!(f, "Can't migrate {}: {}", path, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+/// Put the header in front of a payload
+pub fn frame_payload(payload: &[u8]) -> Vec<u8> {
+    frame_payload_with_version(FORMAT_VERSION, payload)
+}
+
+/// Put a header for a specific format version in front of a payload
+pub fn frame_payload_with_version(version: u16, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&version.to_le_bytes());
+    // Reserved for flags
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&crc32(payload).to_le_bytes());
+    bytes.extend_from_slice(payload);
+
+    bytes
+}
+
+/// Check the header of a file and return the payload if everything matches
+pub fn unframe_payload<'a>(path: &str, bytes: &'a [u8]) -> Result<&'a [u8], FileError> {
+    let (version, payload) = unframe_any_version(path, bytes)?;
+
+    if version != FORMAT_VERSION {
+        return Err(FileError::UnsupportedVersion {
+            path: path.to_string(),
+            version,
+        });
+    }
+
+    Ok(payload)
+}
+
+/// Check the header of a file written by this or any older format version
+///
+/// Only migrations should need this, everything else should go through `unframe_payload`.
+pub fn unframe_any_version<'a>(path: &str, bytes: &'a [u8]) -> Result<(u16, &'a [u8]), FileError> {
+    if bytes.len() < HEADER_SIZE {
+        // A file too short to even hold the header is only ours if it starts like one of ours
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(FileError::BadMagic {
+                path: path.to_string(),
+            });
+        }
+
+        return Err(FileError::Truncated {
+            path: path.to_string(),
+            expected: HEADER_SIZE as u64,
+            found: bytes.len() as u64,
+        });
+    }
+
+    if bytes[0..4] != MAGIC {
+        return Err(FileError::BadMagic {
+            path: path.to_string(),
+        });
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(FileError::UnsupportedVersion {
+            path: path.to_string(),
+            version,
+        });
+    }
+
+    let len = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+    let expected_crc = u32::from_le_bytes(bytes[16..20].try_into().unwrap());
+
+    let payload = &bytes[HEADER_SIZE..];
+    if payload.len() as u64 != len {
+        return Err(FileError::Truncated {
+            path: path.to_string(),
+            expected: len,
+            found: payload.len() as u64,
+        });
+    }
+
+    let found_crc = crc32(payload);
+    if found_crc != expected_crc {
+        return Err(FileError::ChecksumMismatch {
+            path: path.to_string(),
+            expected: expected_crc,
+            found: found_crc,
+        });
+    }
+
+    Ok((version, payload))
+}
+
+/// Counter so two writers in the same process never pick the same temp file
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Flush a directory entry to disk so a rename or new file inside it survives a crash
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    // Directories can only be opened like this on unix, elsewhere the rename is the best we can do
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+
+    #[cfg(not(unix))]
+    let _ = dir;
+
+    Ok(())
+}
+
+/// Write bytes to a file so that a crash leaves either the old file or the new file, never half
+///
+/// The bytes go to a temp file next to `path` first, get fsynced, and only then get renamed over
+/// `path`. The directory is synced last so the rename itself is durable.
+pub fn write_atomic(path: &str, bytes: &[u8]) -> io::Result<()> {
+    let target = Path::new(path);
+    let temp_path = format!(
+        "{}.tmp-{}-{}",
+        path,
+        process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        rename(&temp_path, target)
+    })();
+
+    if result.is_err() {
+        // Don't leave the half written temp file behind
+        let _ = std::fs::remove_file(&temp_path);
+        return result;
+    }
+
+    match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+pub fn read_bytes(path: &str) -> Result<Vec<u8>, FileError> {
+    read(path).map_err(|e| FileError::Io {
+        path: path.to_string(),
+        message: e.to_string(),
+    })
+}
 
 pub trait WriterStrategy<T: Serialize + for<'de> Deserialize<'de>> {
-    fn write_file(&self, path: &str, object: &T);
-    fn read_file(&self, path: &str) -> T;
+    fn write_file(&self, path: &str, object: &T) -> io::Result<()>;
+    fn read_file(&self, path: &str) -> Result<T, FileError>;
 }
 
+#[derive(Default)]
 pub struct BinaryFileWriter {}
 impl<T: Serialize + for<'de> Deserialize<'de>> WriterStrategy<T> for BinaryFileWriter {
-    /// Write a binary file
+    /// Write a binary file with a header holding the magic number, format version and CRC-32
     #[inline(always)]
-    fn write_file(&self, path: &str, object: &T) {
-        let obj_bytes: Vec<u8> = bincode::serialize(&object).expect("Should serialize.");
-        let mut file = BufWriter::new(File::create(path).expect("Should open file."));
-        file.write_all(&obj_bytes).expect("Should write.");
+    fn write_file(&self, path: &str, object: &T) -> io::Result<()> {
+        let obj_bytes: Vec<u8> = bincode::serialize(&object).map_err(io::Error::other)?;
+        write_atomic(path, &frame_payload(&obj_bytes))
     }
-    /// Read a binary file
+
+    /// Read a binary file, checking the header and checksum before decoding it
     #[inline(always)]
-    fn read_file(&self, path: &str) -> T {
-        let file = BufReader::new(File::open(path).expect("Should open file."));
-        let object: T = bincode::deserialize_from(file).expect("Should deserialize.");
-        return object;
+    fn read_file(&self, path: &str) -> Result<T, FileError> {
+        let bytes = read_bytes(path)?;
+        let payload = unframe_payload(path, &bytes)?;
+
+        bincode::deserialize(payload).map_err(|e| FileError::Deserialize {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
     }
 }
 
@@ -33,23 +285,28 @@ impl BinaryFileWriter {
     }
 }
 
+#[derive(Default)]
 pub struct JSONFileWriter {}
 impl<T: Serialize + for<'de> Deserialize<'de>> WriterStrategy<T> for JSONFileWriter {
     /// Write a JSON file
     #[inline(always)]
-    fn write_file(&self, path: &str, object: &T) {
-        let json = serde_json::to_string_pretty(&object).expect("Should serialize.");
-        let mut file = File::create(path).expect("Should open file.");
-        file.write_all(json.as_bytes()).expect("Should write.");
+    fn write_file(&self, path: &str, object: &T) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&object)?;
+        write_atomic(path, json.as_bytes())
     }
 
     /// Read a JSON file
     #[inline(always)]
-    fn read_file(&self, path: &str) -> T {
-        let json = read_to_string(path).expect("Should open file.");
-        let object: T = serde_json::from_str(&json).expect("Should deserialize.");
+    fn read_file(&self, path: &str) -> Result<T, FileError> {
+        let json = read_to_string(path).map_err(|e| FileError::Io {
+            path: path.to_string(),
+            message: e.to_string(),
+        })?;
 
-        return object;
+        serde_json::from_str(&json).map_err(|e| FileError::Deserialize {
+            path: path.to_string(),
+            message: e.to_string(),
+        })
     }
 }
 
@@ -77,13 +334,13 @@ pub struct Writer<T> {
 /// let mut writer = Writer::new(Box::new(json_writer));
 ///
 /// let data = vec![1, 2, 3];
-/// writer.write_file("./test-outputs/out.json", &data);
+/// writer.write_file("./test-outputs/out.json", &data).unwrap();
 ///
 /// let binary_writer = BinaryFileWriter::new();
 /// writer.set_strategy(Box::new(binary_writer));
 ///
 /// let data2 = vec![1, 2, 3];
-/// writer.write_file("./test-outputs/out.data", &data2);
+/// writer.write_file("./test-outputs/out.data", &data2).unwrap();
 /// ```
 impl<T: Serialize + for<'de> Deserialize<'de>> Writer<T> {
     pub fn new(strategy: Box<dyn WriterStrategy<T>>) -> Self {
@@ -94,11 +351,11 @@ impl<T: Serialize + for<'de> Deserialize<'de>> Writer<T> {
         self.strategy = strategy;
     }
 
-    pub fn write_file(&self, path: &str, object: &T) {
-        self.strategy.write_file(path, object);
+    pub fn write_file(&self, path: &str, object: &T) -> io::Result<()> {
+        self.strategy.write_file(path, object)
     }
 
-    pub fn read_file(&self, path: &str) -> T {
+    pub fn read_file(&self, path: &str) -> Result<T, FileError> {
         self.strategy.read_file(path)
     }
 }
@@ -118,19 +375,18 @@ impl<T: Serialize + for<'de> Deserialize<'de>> Writer<T> {
 ///
 /// let writer: Writer<PhysicalPage> = build_binary_writer();
 ///
-/// writer.write_file("./test-outputs/page.data", &page);
+/// writer.write_file("./test-outputs/page.data", &page).unwrap();
 ///
 /// // Read a data file
 ///
 /// let writer: Writer<PhysicalPage> = build_binary_writer();
 ///
-/// let page: PhysicalPage = writer.read_file("./test-outputs/page.data");
+/// let page: PhysicalPage = writer.read_file("./test-outputs/page.data").unwrap();
 /// ```
 pub fn build_binary_writer<T: Serialize + for<'de> Deserialize<'de>>() -> Writer<T> {
     let bin_writer = BinaryFileWriter::new();
-    let writer = Writer::new(Box::new(bin_writer));
 
-    return writer;
+    Writer::new(Box::new(bin_writer))
 }
 
 /// Build a Writer<T> with a json output format
@@ -148,19 +404,18 @@ pub fn build_binary_writer<T: Serialize + for<'de> Deserialize<'de>>() -> Writer
 ///
 /// let writer: Writer<PhysicalPage> = build_json_writer();
 ///
-/// writer.write_file("./test-outputs/page.json", &page);
+/// writer.write_file("./test-outputs/page.json", &page).unwrap();
 ///
 /// // Read a json file
 ///
 /// let writer: Writer<PhysicalPage> = build_json_writer();
 ///
-/// let page: PhysicalPage = writer.read_file("./test-outputs/page.json");
+/// let page: PhysicalPage = writer.read_file("./test-outputs/page.json").unwrap();
 /// ```
 pub fn build_json_writer<T: Serialize + for<'de> Deserialize<'de>>() -> Writer<T> {
     let json_writer = JSONFileWriter::new();
-    let writer = Writer::new(Box::new(json_writer));
 
-    return writer;
+    Writer::new(Box::new(json_writer))
 }
 
 #[cfg(test)]
@@ -170,16 +425,16 @@ mod tests {
 
     #[test]
     fn build_write_and_read_json_test() {
-        let mut page = PhysicalPage::new(0);
+        let page = PhysicalPage::new(0);
         page.write(101);
         page.write(202);
         page.write(303);
 
         let writer: Writer<PhysicalPage> = build_json_writer();
-        writer.write_file("./test-outputs/test-page.json", &page);
+        writer.write_file("./test-outputs/test-page.json", &page).unwrap();
 
         let writer: Writer<PhysicalPage> = build_json_writer();
-        let page: PhysicalPage = writer.read_file("./test-outputs/test-page.json");
+        let page: PhysicalPage = writer.read_file("./test-outputs/test-page.json").unwrap();
 
         assert_eq!(page.read(0), Some(101));
         assert_eq!(page.read(1), Some(202));
@@ -188,19 +443,118 @@ mod tests {
 
     #[test]
     fn build_write_and_read_bit_test() {
-        let mut page = PhysicalPage::new(0);
+        let page = PhysicalPage::new(0);
         page.write(401);
         page.write(402);
         page.write(403);
 
         let writer: Writer<PhysicalPage> = build_json_writer();
-        writer.write_file("./test-outputs/test-page.data", &page);
+        writer.write_file("./test-outputs/test-page.data", &page).unwrap();
 
         let writer: Writer<PhysicalPage> = build_json_writer();
-        let page: PhysicalPage = writer.read_file("./test-outputs/test-page.data");
+        let page: PhysicalPage = writer.read_file("./test-outputs/test-page.data").unwrap();
 
         assert_eq!(page.read(0), Some(401));
         assert_eq!(page.read(1), Some(402));
         assert_eq!(page.read(2), Some(403));
     }
+
+    mod corruption_tests {
+        use super::*;
+
+        fn write_page(path: &str) {
+            let page = PhysicalPage::new(0);
+            page.write(501);
+            page.write(502);
+            page.write(503);
+
+            let writer: Writer<PhysicalPage> = build_binary_writer();
+            writer.write_file(path, &page).unwrap();
+        }
+
+        fn read_page(path: &str) -> Result<PhysicalPage, FileError> {
+            let writer: Writer<PhysicalPage> = build_binary_writer();
+            writer.read_file(path)
+        }
+
+        #[test]
+        fn binary_round_trip_test() {
+            let path = "./test-outputs/round-trip-page.data";
+            write_page(path);
+
+            let page = read_page(path).unwrap();
+            assert_eq!(page.read(2), Some(503));
+        }
+
+        #[test]
+        fn flipped_byte_is_checksum_mismatch_test() {
+            let path = "./test-outputs/flipped-page.data";
+            write_page(path);
+
+            let mut bytes = read(path).unwrap();
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0xFF;
+            std::fs::write(path, &bytes).unwrap();
+
+            let err = read_page(path).unwrap_err();
+            assert!(matches!(err, FileError::ChecksumMismatch { .. }));
+            assert_eq!(err.path(), path);
+            assert!(err.to_string().contains(path));
+        }
+
+        #[test]
+        fn torn_write_is_truncated_test() {
+            let path = "./test-outputs/torn-page.data";
+            write_page(path);
+
+            let bytes = read(path).unwrap();
+            std::fs::write(path, &bytes[..bytes.len() - 3]).unwrap();
+
+            assert!(matches!(
+                read_page(path).unwrap_err(),
+                FileError::Truncated { .. }
+            ));
+
+            // Even the header can be cut off
+            std::fs::write(path, &bytes[..HEADER_SIZE - 1]).unwrap();
+            assert!(matches!(
+                read_page(path).unwrap_err(),
+                FileError::Truncated { .. }
+            ));
+        }
+
+        #[test]
+        fn garbage_is_bad_magic_test() {
+            let path = "./test-outputs/garbage-page.data";
+            std::fs::write(path, b"this is definitely not a page").unwrap();
+
+            assert!(matches!(
+                read_page(path).unwrap_err(),
+                FileError::BadMagic { .. }
+            ));
+        }
+
+        #[test]
+        fn newer_version_is_unsupported_test() {
+            let path = "./test-outputs/future-page.data";
+            write_page(path);
+
+            let mut bytes = read(path).unwrap();
+            bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+            std::fs::write(path, &bytes).unwrap();
+
+            assert!(matches!(
+                read_page(path).unwrap_err(),
+                FileError::UnsupportedVersion { .. }
+            ));
+        }
+
+        #[test]
+        fn missing_file_is_io_error_test() {
+            assert!(matches!(
+                read_page("./test-outputs/does-not-exist.data").unwrap_err(),
+                FileError::Io { .. }
+            ));
+        }
+    }
 }
@@ -1,21 +1,23 @@
-use super::filewriter::{build_binary_writer, Writer};
+use super::filewriter::{build_binary_writer, FileError, Writer};
 use super::table::RTable;
 use crate::container::NUM_RESERVED_COLUMNS;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::io;
 use std::sync::{Arc, RwLock, Weak};
 
 type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
 
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 #[derive(Clone, Default)]
 pub struct RIndexHandle {
     pub index: Arc<RwLock<RIndex>>,
 }
 
-#[pymethods]
+#[cfg_attr(feature = "python", pymethods)]
 impl RIndexHandle {
     pub fn create_index(&mut self, col_index: i64) {
         let mut index = self.index.write().unwrap();
@@ -57,19 +59,44 @@ pub struct RIndexMetadata {
     pub secondary_indices: RedoxQLHashMap<i64, BTreeMap<i64, Vec<i64>>>,
 }
 
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 #[derive(Clone, Default)]
 pub struct RIndex {
-    #[pyo3(get, set)]
     pub index: BTreeMap<i64, i64>,
 
-    #[pyo3(get, set)]
     pub secondary_indices: RedoxQLHashMap<i64, BTreeMap<i64, Vec<i64>>>,
     // Using Arc<RwLock<>> pattern which is safer than raw pointers
     // these fields are not python exposed
     pub owner: Option<Weak<RwLock<RTable>>>,
 }
 
+#[cfg(feature = "python")]
+#[pymethods]
+impl RIndex {
+    #[getter(index)]
+    fn py_index(&self) -> BTreeMap<i64, i64> {
+        self.index.clone()
+    }
+
+    #[setter(index)]
+    fn py_set_index(&mut self, index: BTreeMap<i64, i64>) {
+        self.index = index;
+    }
+
+    #[getter(secondary_indices)]
+    fn py_secondary_indices(&self) -> RedoxQLHashMap<i64, BTreeMap<i64, Vec<i64>>> {
+        self.secondary_indices.clone()
+    }
+
+    #[setter(secondary_indices)]
+    fn py_set_secondary_indices(
+        &mut self,
+        secondary_indices: RedoxQLHashMap<i64, BTreeMap<i64, Vec<i64>>>,
+    ) {
+        self.secondary_indices = secondary_indices;
+    }
+}
+
 impl RIndex {
     pub fn new() -> RIndex {
         RIndex {
@@ -105,7 +132,7 @@ impl RIndex {
                 }
                 // user columns start at offset 3
                 let val = record_data[(col_index + NUM_RESERVED_COLUMNS) as usize];
-                sec_index.entry(val).or_insert_with(Vec::new).push(rid);
+                sec_index.entry(val).or_default().push(rid);
             }
             // For each key in the secondary index, sort the vector so that tests compare in order.
             for vec in sec_index.values_mut() {
@@ -150,11 +177,11 @@ impl RIndex {
         }
     }
 
-    pub fn save_state(&self) {
+    pub fn save_state(&self, dir: &str) -> io::Result<()> {
         let index_meta = self.get_metadata();
 
         let writer: Writer<RIndexMetadata> = build_binary_writer();
-        writer.write_file("./redoxdata/index.data", &index_meta);
+        writer.write_file(&format!("{}/index.data", dir), &index_meta)
     }
 
     pub fn get_metadata(&self) -> RIndexMetadata {
@@ -164,15 +191,15 @@ impl RIndex {
         }
     }
 
-    pub fn load_state(table_ref: Weak<RwLock<RTable>>) -> RIndex {
+    pub fn load_state(dir: &str, table_ref: Weak<RwLock<RTable>>) -> Result<RIndex, FileError> {
         let writer: Writer<RIndexMetadata> = build_binary_writer();
-        let index_meta = writer.read_file("./redoxdata/index.data");
+        let index_meta = writer.read_file(&format!("{}/index.data", dir))?;
 
-        RIndex {
+        Ok(RIndex {
             index: index_meta.index,
             secondary_indices: index_meta.secondary_indices,
             owner: Some(table_ref),
-        }
+        })
     }
 }
 
@@ -195,6 +222,7 @@ mod tests {
 
     mod secondary_index_tests {
         use super::*;
+        use crate::lock_manager::LockManager;
         use crate::pagerange::PageRange;
         use crate::table::PageDirectory;
         use crate::table::RTable;
@@ -212,6 +240,8 @@ mod tests {
                 index: Arc::new(RwLock::new(RIndex::new())),
                 table_num: 0,
                 updates_since_merge: 0,
+                lock_manager: Arc::new(LockManager::default()),
+                versions: Default::default(),
             };
 
             // Insert three records:
@@ -241,7 +271,7 @@ mod tests {
 
             // Now drop the secondary index on column 1.
             index.drop_index_internal(1);
-            assert!(index.secondary_indices.get(&1).is_none());
+            assert!(!index.secondary_indices.contains_key(&1));
         }
 
         #[test]
@@ -257,6 +287,8 @@ mod tests {
                 index: Arc::new(RwLock::new(RIndex::new())),
                 table_num: 0,
                 updates_since_merge: 0,
+                lock_manager: Arc::new(LockManager::default()),
+                versions: Default::default(),
             };
 
             // Insert two records:
@@ -280,7 +312,7 @@ mod tests {
 
             // Drop the index.
             index.drop_index_internal(2);
-            assert!(index.secondary_indices.get(&2).is_none());
+            assert!(!index.secondary_indices.contains_key(&2));
         }
         #[test]
         fn test_set_owner() {
@@ -295,6 +327,8 @@ mod tests {
                 index: Arc::new(RwLock::new(RIndex::new())),
                 table_num: 0,
                 updates_since_merge: 0,
+                lock_manager: Arc::new(LockManager::default()),
+                versions: Default::default(),
             };
             let arc_table = Arc::new(RwLock::new(table));
 
@@ -1,42 +1,74 @@
+#[cfg(feature = "python")]
+use bulk::ImportSummary;
+#[cfg(feature = "python")]
 use database::RDatabase;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+#[cfg(feature = "python")]
 use query::RQuery;
+#[cfg(feature = "python")]
 use record::Record;
-use table::{RTable, RTableHandle};
+#[cfg(feature = "python")]
+use table::{RTable, RTableHandle, TableDescription};
+#[cfg(feature = "python")]
 use transaction::RTransaction;
+#[cfg(feature = "python")]
 use transaction_worker::RTransactionWorker;
+#[cfg(feature = "python")]
+use worker_pool::RWorkerPool;
 
+pub mod api;
 pub mod bufferpool;
+pub mod bulk;
+pub mod checksum;
+pub mod client;
+pub mod compression;
 pub mod container;
 pub mod database;
+// Every function shares the safety rules in the module docs
+#[allow(clippy::missing_safety_doc)]
+pub mod ffi;
 pub mod filewriter;
 pub mod index;
+pub mod lock_manager;
+pub mod migration;
+pub mod mvcc;
+pub mod operation;
 pub mod page;
 pub mod pagerange;
 pub mod query;
 pub mod record;
+pub mod repl;
+pub mod server;
+pub mod sql;
 pub mod system;
 pub mod table;
 pub mod transaction;
 pub mod transaction_worker;
 pub mod utils;
+pub mod worker_pool;
 
 /// Blazingly fast hello
+#[cfg(feature = "python")]
 #[pyfunction]
 fn hello_from_rust() -> PyResult<String> {
     Ok(String::from("Hello from Rust!"))
 }
 
 /// A Python module implemented in Rust.
+#[cfg(feature = "python")]
 #[pymodule]
 fn lstore(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<RDatabase>()?;
     m.add_class::<RTransaction>()?;
     m.add_class::<RTransactionWorker>()?;
+    m.add_class::<RWorkerPool>()?;
     m.add_class::<RQuery>()?;
     m.add_class::<RTable>()?;
     m.add_class::<Record>()?;
     m.add_class::<RTableHandle>()?;
+    m.add_class::<TableDescription>()?;
+    m.add_class::<ImportSummary>()?;
     m.add_function(wrap_pyfunction!(hello_from_rust, m)?)?;
     Ok(())
 }
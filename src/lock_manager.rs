@@ -0,0 +1,571 @@
+use super::database::LockType;
+use log::debug;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::fmt;
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
+type RedoxQLHashSet<K> = FxHashSet<K>;
+
+/// The longest wait-die and wound-wait wait for a lock before giving up
+///
+/// Neither of them can wait in a cycle, but a holder that stops asking for locks and never
+/// finishes would keep its waiters forever.
+pub const MAX_WAIT: Duration = Duration::from_secs(5);
+
+/// What gets locked: (table id, primary key)
+///
+/// Ids stay the same when a table is renamed, so a rename can't split the locks of a table.
+pub type LockKey = (i64, i64);
+
+/// What a transaction does when the lock it wants is held by someone else
+///
+/// The transaction id is the timestamp, so a smaller id means an older transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeadlockStrategy {
+    /// Abort right away
+    #[default]
+    NoWait,
+    /// Older transactions wait for younger ones, younger ones abort ("die"), see `MAX_WAIT`
+    WaitDie,
+    /// Older transactions abort ("wound") the younger holders, younger ones wait, see `MAX_WAIT`
+    WoundWait,
+    /// Wait, but abort if the lock is not granted in time
+    Timeout(Duration),
+}
+
+impl DeadlockStrategy {
+    /// Parse the names Python uses: "no_wait", "wait_die", "wound_wait" and "timeout"
+    pub fn from_name(name: &str, timeout_ms: Option<u64>) -> Option<DeadlockStrategy> {
+        match name {
+            "no_wait" => Some(DeadlockStrategy::NoWait),
+            "wait_die" => Some(DeadlockStrategy::WaitDie),
+            "wound_wait" => Some(DeadlockStrategy::WoundWait),
+            "timeout" => Some(DeadlockStrategy::Timeout(Duration::from_millis(
+                timeout_ms.unwrap_or(100),
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// Why a lock was not granted. In every case the transaction has to abort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockError {
+    /// Someone else holds the lock and the strategy is no-wait
+    Conflict,
+    /// A younger transaction asked for a lock an older one holds under wait-die
+    Died,
+    /// An older transaction needed a lock this one holds under wound-wait
+    Wounded,
+    /// The lock was not granted before the timeout, or `MAX_WAIT` for the other strategies
+    TimedOut,
+}
+
+/// Who holds a single lock
+#[derive(Debug, Default)]
+struct LockState {
+    shared: RedoxQLHashSet<i64>,
+    exclusive: Option<i64>,
+}
+
+impl LockState {
+    /// Every other transaction that holds this lock in a way that conflicts with `lock_type`
+    fn conflicts(&self, transaction_id: i64, lock_type: LockType) -> Vec<i64> {
+        let mut holders = Vec::new();
+
+        if let Some(writer) = self.exclusive {
+            if writer != transaction_id {
+                holders.push(writer);
+            }
+        }
+
+        if lock_type == LockType::Exclusive {
+            holders.extend(self.shared.iter().filter(|&&t| t != transaction_id));
+        }
+
+        holders
+    }
+
+    fn grant(&mut self, transaction_id: i64, lock_type: LockType) {
+        match lock_type {
+            LockType::Shared => {
+                self.shared.insert(transaction_id);
+            }
+            LockType::Exclusive => {
+                self.exclusive = Some(transaction_id);
+            }
+        }
+    }
+
+    fn is_free(&self) -> bool {
+        self.shared.is_empty() && self.exclusive.is_none()
+    }
+
+    /// Every holder with the strongest lock it has, sorted by transaction id
+    fn holders(&self) -> Vec<(i64, LockType)> {
+        let mut holders: Vec<(i64, LockType)> = self
+            .shared
+            .iter()
+            .filter(|&&t| Some(t) != self.exclusive)
+            .map(|&t| (t, LockType::Shared))
+            .chain(self.exclusive.map(|t| (t, LockType::Exclusive)))
+            .collect();
+
+        holders.sort_unstable_by_key(|&(t, _)| t);
+        holders
+    }
+}
+
+/// A shared lock on every key of a table from `start` to `end`, whether it was inserted or not
+#[derive(Debug, Clone, Copy)]
+struct RangeLock {
+    table_id: i64,
+    start: i64,
+    end: i64,
+    transaction_id: i64,
+}
+
+impl RangeLock {
+    fn covers(&self, key: &LockKey) -> bool {
+        self.table_id == key.0 && (self.start..=self.end).contains(&key.1)
+    }
+}
+
+#[derive(Debug, Default)]
+struct LockTable {
+    locks: RedoxQLHashMap<LockKey, LockState>,
+    /// Taken by sums, so nobody can insert or delete a key in a range that was summed
+    ranges: Vec<RangeLock>,
+    /// Transactions an older transaction has wounded, they abort the next time they ask for a lock,
+    /// run a query or commit
+    wounded: RedoxQLHashSet<i64>,
+}
+
+/// Every lock of one database
+///
+/// Locks are keyed by (table, primary key) and not by record, so a key can be locked before it
+/// is inserted or after it is deleted. That is what keeps two transactions from inserting the
+/// same key at once.
+///
+/// A transaction that can't get a lock waits on a condition variable until some lock is released,
+/// unless the deadlock strategy says it has to abort instead.
+#[derive(Debug, Default)]
+pub struct LockManager {
+    strategy: RwLock<DeadlockStrategy>,
+    table: Mutex<LockTable>,
+    released: Condvar,
+}
+
+impl LockManager {
+    pub fn new(strategy: DeadlockStrategy) -> Self {
+        LockManager {
+            strategy: RwLock::new(strategy),
+            ..Default::default()
+        }
+    }
+
+    pub fn strategy(&self) -> DeadlockStrategy {
+        *self.strategy.read().unwrap()
+    }
+
+    pub fn set_strategy(&self, strategy: DeadlockStrategy) {
+        *self.strategy.write().unwrap() = strategy;
+    }
+
+    /// Lock `key` for a transaction, waiting if the strategy allows it
+    ///
+    /// Asking again for a lock the transaction already holds is granted right away. Asking for an
+    /// exclusive lock while holding a shared one upgrades it once every other reader is gone.
+    pub fn acquire(
+        &self,
+        transaction_id: i64,
+        key: &LockKey,
+        lock_type: LockType,
+    ) -> Result<(), LockError> {
+        self.wait_for(transaction_id, key, |table| {
+            let mut holders = table
+                .locks
+                .get(key)
+                .map(|state| state.conflicts(transaction_id, lock_type))
+                .unwrap_or_default();
+
+            // Writing a key someone summed over could change their sum
+            if lock_type == LockType::Exclusive {
+                holders.extend(
+                    table
+                        .ranges
+                        .iter()
+                        .filter(|r| r.transaction_id != transaction_id && r.covers(key))
+                        .map(|r| r.transaction_id),
+                );
+            }
+
+            if holders.is_empty() {
+                table
+                    .locks
+                    .entry(*key)
+                    .or_default()
+                    .grant(transaction_id, lock_type);
+            }
+            holders
+        })
+    }
+
+    /// Lock every key of a table from `start` to `end` for reading, including the ones that
+    /// don't exist yet
+    ///
+    /// Nobody else can lock a key in the range exclusively until the range is released, so no
+    /// record can appear in or vanish from it.
+    pub fn acquire_range(
+        &self,
+        transaction_id: i64,
+        table_id: i64,
+        start: i64,
+        end: i64,
+    ) -> Result<(), LockError> {
+        let range = RangeLock {
+            table_id,
+            start,
+            end,
+            transaction_id,
+        };
+
+        self.wait_for(transaction_id, &(table_id, start..=end), |table| {
+            let holders: Vec<i64> = table
+                .locks
+                .iter()
+                .filter(|(key, _)| range.covers(key))
+                .filter_map(|(_, state)| state.exclusive)
+                .filter(|&writer| writer != transaction_id)
+                .collect();
+
+            if holders.is_empty() {
+                table.ranges.push(range);
+            }
+            holders
+        })
+    }
+
+    /// Whether an older transaction wounded this one, the wound is cleared either way
+    ///
+    /// A wounded transaction has to abort instead of running another query or committing, or
+    /// the older one could wait for it forever.
+    pub fn take_wound(&self, transaction_id: i64) -> bool {
+        self.table.lock().unwrap().wounded.remove(&transaction_id)
+    }
+
+    /// Give up every range a transaction locked and wake up anyone waiting for a lock
+    pub fn release_ranges(&self, transaction_id: i64) {
+        let mut table = self.table.lock().unwrap();
+        table.ranges.retain(|r| r.transaction_id != transaction_id);
+        self.released.notify_all();
+    }
+
+    // Call `try_grant` until it grants the lock, it returns who is in the way otherwise. Between
+    // tries this waits, dies or wounds as the strategy says.
+    fn wait_for(
+        &self,
+        transaction_id: i64,
+        what: &dyn fmt::Debug,
+        mut try_grant: impl FnMut(&mut LockTable) -> Vec<i64>,
+    ) -> Result<(), LockError> {
+        let strategy = self.strategy();
+        let deadline = Instant::now()
+            + match strategy {
+                DeadlockStrategy::Timeout(d) => d,
+                _ => MAX_WAIT,
+            };
+
+        let mut table = self.table.lock().unwrap();
+
+        loop {
+            if table.wounded.remove(&transaction_id) {
+                debug!("Transaction {} was wounded", transaction_id);
+                return Err(LockError::Wounded);
+            }
+
+            let holders = try_grant(&mut table);
+            if holders.is_empty() {
+                return Ok(());
+            }
+
+            match strategy {
+                DeadlockStrategy::NoWait => return Err(LockError::Conflict),
+                DeadlockStrategy::WaitDie => {
+                    // Only wait for younger transactions, so nobody ever waits in a cycle
+                    if holders.iter().any(|&h| h < transaction_id) {
+                        return Err(LockError::Died);
+                    }
+                }
+                DeadlockStrategy::WoundWait => {
+                    let younger: Vec<i64> = holders
+                        .iter()
+                        .copied()
+                        .filter(|&h| h > transaction_id)
+                        .collect();
+
+                    if !younger.is_empty() {
+                        debug!(
+                            "Transaction {} wounds {:?} for {:?}",
+                            transaction_id, younger, what
+                        );
+                        table.wounded.extend(younger);
+                        // Wake the wounded ones up in case they are waiting for a lock themselves
+                        self.released.notify_all();
+                    }
+                }
+                DeadlockStrategy::Timeout(_) => {}
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(LockError::TimedOut);
+            }
+            table = self.released.wait_timeout(table, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Give up a transaction's locks on these keys and wake up anyone waiting for a lock
+    pub fn release_all<'a>(
+        &self,
+        transaction_id: i64,
+        keys: impl IntoIterator<Item = &'a LockKey>,
+    ) {
+        let mut table = self.table.lock().unwrap();
+
+        for key in keys {
+            if let Some(state) = table.locks.get_mut(key) {
+                state.shared.remove(&transaction_id);
+                if state.exclusive == Some(transaction_id) {
+                    state.exclusive = None;
+                }
+
+                if state.is_free() {
+                    table.locks.remove(key);
+                }
+            }
+        }
+
+        // The transaction is done, a wound that came too late does not matter anymore
+        table.wounded.remove(&transaction_id);
+
+        self.released.notify_all();
+    }
+
+    /// Who holds the lock on `key` right now, for debugging
+    pub fn holders(&self, key: &LockKey) -> Vec<(i64, LockType)> {
+        let table = self.table.lock().unwrap();
+
+        table
+            .locks
+            .get(key)
+            .map(|state| state.holders())
+            .unwrap_or_default()
+    }
+
+    /// Every lock that is held right now as (key, transaction id, lock type), sorted by key
+    pub fn dump(&self) -> Vec<(LockKey, i64, LockType)> {
+        let table = self.table.lock().unwrap();
+
+        let mut locks: Vec<(LockKey, i64, LockType)> = table
+            .locks
+            .iter()
+            .flat_map(|(key, state)| {
+                state
+                    .holders()
+                    .into_iter()
+                    .map(move |(t, lock_type)| (*key, t, lock_type))
+            })
+            .collect();
+
+        locks.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        locks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn key(pk: i64) -> LockKey {
+        (0, pk)
+    }
+
+    #[test]
+    fn shared_locks_are_compatible_test() {
+        let lm = LockManager::new(DeadlockStrategy::NoWait);
+
+        assert_eq!(lm.acquire(1, &key(1), LockType::Shared), Ok(()));
+        assert_eq!(lm.acquire(2, &key(1), LockType::Shared), Ok(()));
+        assert_eq!(
+            lm.acquire(3, &key(1), LockType::Exclusive),
+            Err(LockError::Conflict)
+        );
+
+        lm.release_all(1, [&key(1)]);
+        lm.release_all(2, [&key(1)]);
+        assert_eq!(lm.acquire(3, &key(1), LockType::Exclusive), Ok(()));
+    }
+
+    #[test]
+    fn upgrade_shared_to_exclusive_test() {
+        let lm = Arc::new(LockManager::new(DeadlockStrategy::WaitDie));
+
+        // The only reader can upgrade right away
+        assert_eq!(lm.acquire(1, &key(1), LockType::Shared), Ok(()));
+        assert_eq!(lm.acquire(1, &key(1), LockType::Exclusive), Ok(()));
+        assert_eq!(lm.holders(&key(1)), vec![(1, LockType::Exclusive)]);
+        lm.release_all(1, [&key(1)]);
+        assert!(lm.holders(&key(1)).is_empty());
+
+        // With another reader, the upgrade waits until that reader is done
+        assert_eq!(lm.acquire(1, &key(2), LockType::Shared), Ok(()));
+        assert_eq!(lm.acquire(2, &key(2), LockType::Shared), Ok(()));
+
+        let upgrade = {
+            let lm = lm.clone();
+            thread::spawn(move || lm.acquire(1, &key(2), LockType::Exclusive))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            lm.holders(&key(2)),
+            vec![(1, LockType::Shared), (2, LockType::Shared)]
+        );
+
+        lm.release_all(2, [&key(2)]);
+        assert_eq!(upgrade.join().unwrap(), Ok(()));
+        assert_eq!(lm.holders(&key(2)), vec![(1, LockType::Exclusive)]);
+    }
+
+    #[test]
+    fn dump_test() {
+        let lm = LockManager::new(DeadlockStrategy::NoWait);
+
+        // Keys do not have to exist anywhere to be locked
+        assert_eq!(lm.acquire(3, &key(404), LockType::Exclusive), Ok(()));
+        assert_eq!(lm.acquire(1, &key(7), LockType::Shared), Ok(()));
+        assert_eq!(lm.acquire(2, &key(7), LockType::Shared), Ok(()));
+
+        assert_eq!(
+            lm.dump(),
+            vec![
+                (key(7), 1, LockType::Shared),
+                (key(7), 2, LockType::Shared),
+                (key(404), 3, LockType::Exclusive),
+            ]
+        );
+
+        lm.release_all(1, [&key(7)]);
+        lm.release_all(2, [&key(7)]);
+        lm.release_all(3, [&key(404)]);
+        assert!(lm.dump().is_empty());
+    }
+
+    #[test]
+    fn range_lock_test() {
+        let lm = LockManager::new(DeadlockStrategy::NoWait);
+
+        assert_eq!(lm.acquire(1, &key(50), LockType::Exclusive), Ok(()));
+        assert_eq!(lm.acquire_range(2, 0, 0, 100), Err(LockError::Conflict));
+        assert_eq!(lm.acquire_range(2, 0, 60, i64::MAX), Ok(()));
+        assert_eq!(lm.acquire_range(3, 0, 70, 80), Ok(()));
+
+        // Keys in a locked range can still be read, but not written, even if they don't exist
+        assert_eq!(lm.acquire(1, &key(75), LockType::Shared), Ok(()));
+        assert_eq!(
+            lm.acquire(1, &key(1000), LockType::Exclusive),
+            Err(LockError::Conflict)
+        );
+        assert_eq!(lm.acquire(2, &key(1000), LockType::Exclusive), Ok(()));
+        assert_eq!(lm.acquire(1, &(1, 75), LockType::Exclusive), Ok(()));
+
+        lm.release_ranges(2);
+        lm.release_all(2, [&key(1000)]);
+        assert_eq!(lm.acquire(1, &key(1000), LockType::Exclusive), Ok(()));
+        assert_eq!(
+            lm.acquire(1, &key(75), LockType::Exclusive),
+            Err(LockError::Conflict)
+        );
+    }
+
+    #[test]
+    fn wait_die_test() {
+        let lm = LockManager::new(DeadlockStrategy::WaitDie);
+
+        assert_eq!(lm.acquire(5, &key(1), LockType::Exclusive), Ok(()));
+
+        // Younger than the holder, so it dies
+        assert_eq!(
+            lm.acquire(9, &key(1), LockType::Exclusive),
+            Err(LockError::Died)
+        );
+
+        // Older than the holder, so it waits until the holder is done
+        let lm = Arc::new(lm);
+        let waiter = {
+            let lm = lm.clone();
+            thread::spawn(move || lm.acquire(2, &key(1), LockType::Exclusive))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        lm.release_all(5, [&key(1)]);
+
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn wound_wait_test() {
+        let lm = Arc::new(LockManager::new(DeadlockStrategy::WoundWait));
+
+        assert_eq!(lm.acquire(5, &key(1), LockType::Exclusive), Ok(()));
+
+        // Older than the holder, so it wounds the holder and waits for it to let go
+        let waiter = {
+            let lm = lm.clone();
+            thread::spawn(move || lm.acquire(2, &key(1), LockType::Exclusive))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+
+        // The holder finds out it was wounded the next time it asks for a lock
+        assert_eq!(
+            lm.acquire(5, &key(2), LockType::Shared),
+            Err(LockError::Wounded)
+        );
+        lm.release_all(5, [&key(1)]);
+
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+
+        // A holder that asks for no more locks finds out before it runs a query or commits
+        let waiter = {
+            let lm = lm.clone();
+            thread::spawn(move || lm.acquire(1, &key(1), LockType::Exclusive))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        assert!(lm.take_wound(2));
+        assert!(!lm.take_wound(2));
+        lm.release_all(2, [&key(1)]);
+
+        assert_eq!(waiter.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn timeout_test() {
+        let lm = LockManager::new(DeadlockStrategy::Timeout(Duration::from_millis(20)));
+
+        assert_eq!(lm.acquire(1, &key(1), LockType::Exclusive), Ok(()));
+
+        let start = Instant::now();
+        assert_eq!(
+            lm.acquire(2, &key(1), LockType::Shared),
+            Err(LockError::TimedOut)
+        );
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
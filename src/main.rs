@@ -0,0 +1,45 @@
+use redoxql::repl::{Repl, Reply};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::ExitCode;
+
+/// `redoxql [path]` opens the database saved at path, or an in-memory one, and reads commands from stdin
+fn main() -> ExitCode {
+    let path = std::env::args().nth(1);
+
+    let mut repl = match Repl::open(path) {
+        Ok(repl) => repl,
+        Err(e) => {
+            eprintln!("Could not open the database: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let interactive = io::stdin().is_terminal();
+    if interactive {
+        println!("redoxql, type help for the commands");
+    }
+
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("redoxql> ");
+            let _ = io::stdout().flush();
+        }
+
+        // Save the database on end of input, like exit does
+        let Some(Ok(line)) = lines.next() else {
+            if let Err(e) = repl.close() {
+                eprintln!("Could not save the database: {}", e);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        };
+
+        match repl.execute(&line) {
+            Ok(Reply::Print(text)) if text.is_empty() => {}
+            Ok(Reply::Print(text)) => println!("{}", text),
+            Ok(Reply::Exit) => return ExitCode::SUCCESS,
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use super::container::{base_page_path, tail_page_path, ReservedColumns};
+use super::database::{
+    list_files, remove_old_snapshots, snapshot_dir, snapshots_dir, table_dir, RDatabaseMetadata,
+};
+use super::filewriter::{
+    frame_payload, read_bytes, sync_dir, unframe_any_version, write_atomic, FileError,
+    FORMAT_VERSION, MAGIC,
+};
+use super::page::PhysicalPage;
+use super::record::{RecordAddressMetadata, RecordMetadata};
+use super::table::{PageDirectoryMetadata, RTableMetadata};
+use log::info;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{create_dir_all, read_dir, remove_dir_all, remove_file};
+use std::io;
+use std::path::Path;
+
+type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
+
+// History of the on-disk format
+//
+// 0 - Every file is raw bincode without a header, all in one flat ./redoxdata directory, and the
+//     database file at the user's path is the bare database metadata
+// 1 - Every file has a header with a checksum, tables live in snapshot directories named after
+//     their ids next to the database file, which is a manifest naming the current snapshot. Pages are
+//     compressed, each with the codec that makes it the smallest.
+//
+// Version 0 wrote base and tail pages with the same index to the same file and read every record
+// from the base pages. Once a record was updated, the tail pages replaced the base pages on disk
+// and the base records were lost, so only version 0 databases without updates can be migrated.
+// The current version names the pages apart and marks the tail records in the page directory.
+// The table, page range and index files have not changed since version 0.
+
+/// The database metadata as version 0 wrote it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DatabaseMetadataV0 {
+    pub tables: Vec<RTableMetadata>,
+    pub tables_hashmap: RedoxQLHashMap<String, usize>,
+    pub db_filepath: Option<String>,
+}
+
+/// A page as version 0 wrote it, without compression
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PhysicalPageV0 {
+    pub data: Vec<i64>,
+    pub num_records: i64,
+    pub column_index: i64,
+}
+
+/// A record as version 0 wrote it into the page directory, it had no tail flag
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RecordMetadataV0 {
+    pub rid: i64,
+    pub addresses: Vec<RecordAddressMetadata>,
+}
+
+/// The page directory as version 0 wrote it
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PageDirectoryMetadataV0 {
+    pub directory: RedoxQLHashMap<i64, RecordMetadataV0>,
+}
+
+/// Where version 0 kept the files of every database
+pub const LEGACY_DATA_DIR: &str = "./redoxdata";
+
+/// The files every table wrote into the flat directory in version 0
+const LEGACY_FILES: [&str; 5] = [
+    "pagerange.data",
+    "page_directory.data",
+    "index.data",
+    "base_container.data",
+    "tail_container.data",
+];
+
+fn io_error(path: &str) -> impl Fn(io::Error) -> FileError + '_ {
+    move |e| FileError::Io {
+        path: path.to_string(),
+        message: e.to_string(),
+    }
+}
+
+fn decode<'a, T: Deserialize<'a>>(path: &str, bytes: &'a [u8]) -> Result<T, FileError> {
+    bincode::deserialize(bytes).map_err(|e| FileError::Deserialize {
+        path: path.to_string(),
+        message: e.to_string(),
+    })
+}
+
+fn encode<T: Serialize>(object: &T) -> Vec<u8> {
+    bincode::serialize(object).expect("Should serialize.")
+}
+
+/// Make an empty snapshot directory, throwing away whatever an earlier crash left there
+fn fresh_dir(dir: &str) -> Result<(), FileError> {
+    if Path::new(dir).exists() {
+        remove_dir_all(dir).map_err(io_error(dir))?;
+    }
+
+    create_dir_all(dir).map_err(io_error(dir))
+}
+
+/// Flush the new snapshot and every directory in it so it is all on disk before the manifest
+fn sync_snapshot(manifest_path: &str, snapshot: &str, files: &[String]) -> Result<(), FileError> {
+    let dirs: BTreeSet<String> = files
+        .iter()
+        .filter_map(|f| Path::new(f).parent())
+        .map(|p| format!("{}/{}", snapshot, p.to_string_lossy()))
+        .collect();
+
+    for dir in dirs {
+        sync_dir(Path::new(&dir)).map_err(io_error(&dir))?;
+    }
+
+    sync_dir(Path::new(snapshot)).map_err(io_error(snapshot))?;
+    let snapshots = snapshots_dir(manifest_path);
+    sync_dir(Path::new(&snapshots)).map_err(io_error(&snapshots))
+}
+
+/// Find out which format version the database with this manifest was written in
+pub fn detect_version(manifest_path: &str) -> Result<u16, FileError> {
+    let bytes = read_bytes(manifest_path)?;
+
+    // Version 0 had no header at all
+    if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+        return Ok(0);
+    }
+
+    let (version, _) = unframe_any_version(manifest_path, &bytes)?;
+
+    Ok(version)
+}
+
+/// Upgrade the database with this manifest to the current format
+///
+/// The migration writes a complete new snapshot and only then swaps the manifest, so a crash
+/// during it leaves the database in the version it was in before.
+pub fn upgrade(manifest_path: &str) -> Result<(), FileError> {
+    if detect_version(manifest_path)? == 0 {
+        info!(
+            "Migrating {} from format version 0 to {}",
+            manifest_path, FORMAT_VERSION
+        );
+
+        migrate_v0(manifest_path, LEGACY_DATA_DIR)?;
+    }
+
+    Ok(())
+}
+
+/// Refuse version 0 files in which tail pages replaced the base pages
+///
+/// Every record reads its RID from the RID page at its own offset. When that is some other RID, a
+/// tail page was written over the base page and the values of the base records are gone.
+fn check_base_records(legacy_dir: &str) -> Result<(), FileError> {
+    let directory_path = format!("{}/page_directory.data", legacy_dir);
+    let rid_page_path = format!("{}/{}-page.data", legacy_dir, ReservedColumns::RID as usize);
+    if !Path::new(&directory_path).exists() {
+        return Ok(());
+    }
+
+    let directory: PageDirectoryMetadataV0 =
+        decode(&directory_path, &read_bytes(&directory_path)?)?;
+    let rids = if Path::new(&rid_page_path).exists() {
+        let page: PhysicalPageV0 = decode(&rid_page_path, &read_bytes(&rid_page_path)?)?;
+        page.data
+    } else {
+        Vec::new()
+    };
+
+    for (rid, record) in &directory.directory {
+        let offset = record.addresses[ReservedColumns::RID as usize].offset;
+        if rids.get(offset as usize) != Some(rid) {
+            return Err(FileError::CannotMigrate {
+                path: rid_page_path,
+                message: String::from(
+                    "records were updated, version 0 wrote the tail pages over the base pages",
+                ),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Give every table an id, version 0 gave a table the number of tables when it was created, so
+/// a table created after a drop could get the number of a table that still exists. Those get a
+/// new id after the highest one. Returns the ids and the id the next table gets.
+fn table_ids(tables: &[RTableMetadata]) -> (Vec<i64>, i64) {
+    let mut next_table_id = tables.iter().map(|t| t.table_num + 1).max().unwrap_or(0);
+    let mut used = BTreeSet::new();
+
+    let ids = tables
+        .iter()
+        .map(|table| {
+            if table.table_num >= 0 && used.insert(table.table_num) {
+                table.table_num
+            } else {
+                next_table_id += 1;
+                next_table_id - 1
+            }
+        })
+        .collect();
+
+    (ids, next_table_id)
+}
+
+/// Move the flat, headerless files of version 0 into a snapshot of the current version
+///
+/// `legacy_dir` is where the version 0 files are, which is always ./redoxdata outside of tests.
+pub fn migrate_v0(manifest_path: &str, legacy_dir: &str) -> Result<(), FileError> {
+    let bytes = read_bytes(manifest_path)?;
+    let old: DatabaseMetadataV0 = decode(manifest_path, &bytes)?;
+
+    // The page files are numbered, so find out which ones are there
+    let mut legacy_files: Vec<String> = LEGACY_FILES.iter().map(|f| f.to_string()).collect();
+    for entry in read_dir(legacy_dir)
+        .map_err(io_error(legacy_dir))?
+        .flatten()
+    {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with("-page.data") || name.ends_with("-table.data") {
+            legacy_files.push(name);
+        }
+    }
+
+    check_base_records(legacy_dir)?;
+
+    let (ids, next_table_id) = table_ids(&old.tables);
+    let mut tables = old.tables;
+
+    let generation = 1;
+    let snapshot = snapshot_dir(manifest_path, generation);
+    fresh_dir(&snapshot)?;
+
+    for (table, &id) in tables.iter_mut().zip(&ids) {
+        let dir = table_dir(&snapshot, id);
+        create_dir_all(&dir).map_err(io_error(&dir))?;
+
+        for name in &legacy_files {
+            // Every table wrote over the same files in version 0, only the table files were kept
+            // apart. The manifest has a copy of each table file, so that one is written from it
+            // and each table gets a copy of the rest.
+            if name.ends_with("-table.data") {
+                continue;
+            }
+
+            let from = format!("{}/{}", legacy_dir, name);
+            if !Path::new(&from).exists() {
+                continue;
+            }
+
+            let payload = read_bytes(&from)?;
+
+            if let Some(index) = name.strip_suffix("-page.data") {
+                let Ok(index) = index.parse::<usize>() else {
+                    continue;
+                };
+
+                let page: PhysicalPageV0 = decode(&from, &payload)?;
+                let page = PhysicalPage::from_values(page.column_index, page.data);
+
+                // Without updates the tail pages were empty and version 0 did not write them, so
+                // this is the base page and the table starts over with an empty tail page
+                let to = base_page_path(&dir, index);
+                write_atomic(&to, &frame_payload(&encode(&page.get_metadata())))
+                    .map_err(io_error(&to))?;
+
+                let tail = PhysicalPage::new(page.column_index());
+                let to = tail_page_path(&dir, index);
+                write_atomic(&to, &frame_payload(&encode(&tail.get_metadata())))
+                    .map_err(io_error(&to))?;
+
+                continue;
+            }
+
+            let payload = if name == "page_directory.data" {
+                // There were no tail records, so every record is a base record
+                let old: PageDirectoryMetadataV0 = decode(&from, &payload)?;
+                let directory = old
+                    .directory
+                    .into_iter()
+                    .map(|(rid, record)| {
+                        let record = RecordMetadata {
+                            rid: record.rid,
+                            tail: false,
+                            addresses: record.addresses,
+                        };
+                        (rid, record)
+                    })
+                    .collect();
+
+                encode(&PageDirectoryMetadata { directory })
+            } else {
+                payload
+            };
+
+            let to = format!("{}/{}", dir, name);
+            write_atomic(&to, &frame_payload(&payload)).map_err(io_error(&to))?;
+        }
+
+        table.table_num = id;
+        let to = format!("{}/{}-table.data", dir, id);
+        write_atomic(&to, &frame_payload(&encode(table))).map_err(io_error(&to))?;
+    }
+
+    let mut files = Vec::new();
+    list_files(Path::new(&snapshot), Path::new(&snapshot), &mut files)
+        .map_err(io_error(&snapshot))?;
+    files.sort();
+    sync_snapshot(manifest_path, &snapshot, &files)?;
+
+    let manifest = RDatabaseMetadata {
+        format_version: FORMAT_VERSION,
+        tables,
+        tables_hashmap: old.tables_hashmap,
+        db_filepath: old.db_filepath,
+        generation,
+        files,
+        next_table_id,
+    };
+    write_atomic(manifest_path, &frame_payload(&encode(&manifest)))
+        .map_err(io_error(manifest_path))?;
+
+    // The snapshot has everything now, so the flat files are not needed anymore
+    for name in &legacy_files {
+        let _ = remove_file(format!("{}/{}", legacy_dir, name));
+    }
+
+    remove_old_snapshots(manifest_path, generation);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RDatabase;
+    use crate::filewriter::{build_binary_writer, Writer};
+    use crate::page::PhysicalPageMetadata;
+    use crate::query::RQuery;
+
+    fn reset(manifest: &str) {
+        let _ = remove_file(manifest);
+        remove_old_snapshots(manifest, u64::MAX);
+    }
+
+    fn write_grades(manifest: &str) {
+        write_grades_with(manifest, |_| {});
+    }
+
+    /// Insert the grades, then run `change` on them before closing
+    fn write_grades_with(manifest: &str, change: impl FnOnce(&mut RQuery)) {
+        reset(manifest);
+
+        let mut db = RDatabase::new();
+        db.open(manifest.to_string()).unwrap();
+
+        let mut q = RQuery::new(db.create_table(String::from("Grades"), 3, 0).unwrap());
+        q.insert(vec![1, 90, 80]);
+        q.insert(vec![2, 70, 60]);
+        q.insert(vec![3, 50, 40]);
+        change(&mut q);
+
+        db.close().unwrap();
+    }
+
+    fn write_legacy_grades(manifest: &str, legacy_dir: &str) {
+        write_legacy_grades_with(manifest, legacy_dir, |_| {});
+    }
+
+    /// Turn a current database into the flat, headerless layout of version 0
+    fn write_legacy_grades_with(
+        manifest: &str,
+        legacy_dir: &str,
+        change: impl FnOnce(&mut RQuery),
+    ) {
+        write_grades_with(manifest, change);
+
+        let _ = remove_dir_all(legacy_dir);
+        create_dir_all(legacy_dir).unwrap();
+
+        let writer: Writer<RDatabaseMetadata> = build_binary_writer();
+        let meta = writer.read_file(manifest).unwrap();
+        let snapshot = snapshot_dir(manifest, meta.generation);
+
+        for file in &meta.files {
+            let path = format!("{}/{}", snapshot, file);
+            let bytes = read_bytes(&path).unwrap();
+            let (_, payload) = unframe_any_version(&path, &bytes).unwrap();
+
+            let name = Path::new(file).file_name().unwrap().to_string_lossy();
+
+            // Pages were not compressed back then. Version 0 did not write empty tail pages and
+            // wrote the others over the base page with the same index, which comes first here.
+            let page_name = name.strip_prefix("base-").or(name.strip_prefix("tail-"));
+            let (name, payload) = if let Some(name) = page_name {
+                let page_meta: PhysicalPageMetadata = decode(&path, payload).unwrap();
+                let page = page_meta.load_state(&path).unwrap();
+                if page.num_records() == 0 {
+                    continue;
+                }
+
+                let page = encode(&PhysicalPageV0 {
+                    data: page.values().collect(),
+                    num_records: page.num_records(),
+                    column_index: page.column_index(),
+                });
+                (name.to_string(), page)
+            } else if name == "page_directory.data" {
+                let pd: PageDirectoryMetadata = decode(&path, payload).unwrap();
+                let directory = pd
+                    .directory
+                    .into_iter()
+                    .map(|(rid, r)| {
+                        let record = RecordMetadataV0 {
+                            rid: r.rid,
+                            addresses: r.addresses,
+                        };
+                        (rid, record)
+                    })
+                    .collect();
+                (
+                    name.to_string(),
+                    encode(&PageDirectoryMetadataV0 { directory }),
+                )
+            } else {
+                (name.to_string(), payload.to_vec())
+            };
+
+            std::fs::write(format!("{}/{}", legacy_dir, name), payload).unwrap();
+        }
+
+        let old = DatabaseMetadataV0 {
+            tables: meta.tables,
+            tables_hashmap: meta.tables_hashmap,
+            db_filepath: meta.db_filepath,
+        };
+        std::fs::write(manifest, encode(&old)).unwrap();
+        remove_old_snapshots(manifest, u64::MAX);
+    }
+
+    #[test]
+    fn current_database_is_not_migrated_test() {
+        let manifest = "./test-outputs/current-version.db";
+        write_grades(manifest);
+
+        assert_eq!(detect_version(manifest).unwrap(), FORMAT_VERSION);
+
+        upgrade(manifest).unwrap();
+        assert!(Path::new(&snapshot_dir(manifest, 1)).exists());
+    }
+
+    #[test]
+    fn legacy_database_is_upgraded_test() {
+        let manifest = "./test-outputs/legacy-version.db";
+        let legacy_dir = "./test-outputs/legacy-redoxdata";
+        write_legacy_grades(manifest, legacy_dir);
+
+        assert_eq!(detect_version(manifest).unwrap(), 0);
+
+        migrate_v0(manifest, legacy_dir).unwrap();
+        assert_eq!(detect_version(manifest).unwrap(), FORMAT_VERSION);
+        assert!(!Path::new(&format!("{}/0-page.data", legacy_dir)).exists());
+
+        let mut db = RDatabase::new();
+        db.open(manifest.to_string()).unwrap();
+
+        let mut q = RQuery::new(db.get_table(String::from("Grades")).unwrap());
+        let row = q.select(2, 0, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+        assert_eq!(row.columns, vec![Some(2), Some(70), Some(60)]);
+    }
+
+    #[test]
+    fn legacy_database_with_deletes_is_upgraded_test() {
+        let manifest = "./test-outputs/legacy-deleted.db";
+        let legacy_dir = "./test-outputs/legacy-deleted-redoxdata";
+        write_legacy_grades_with(manifest, legacy_dir, |q| assert!(q.delete(2)));
+
+        migrate_v0(manifest, legacy_dir).unwrap();
+
+        let mut db = RDatabase::new();
+        db.open(manifest.to_string()).unwrap();
+
+        let mut q = RQuery::new(db.get_table(String::from("Grades")).unwrap());
+        assert!(q.select(2, 0, vec![1, 1, 1]).unwrap().is_empty());
+        let row = q.select(3, 0, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+        assert_eq!(row.columns, vec![Some(3), Some(50), Some(40)]);
+
+        // Updates after the migration go on the new, empty tail pages
+        assert!(q.update(3, vec![None, Some(55), None]));
+        let row = q.select(3, 0, vec![1, 1, 1]).unwrap()[0].clone().unwrap();
+        assert_eq!(row.columns, vec![Some(3), Some(55), Some(40)]);
+    }
+
+    #[test]
+    fn legacy_database_with_updates_is_refused_test() {
+        let manifest = "./test-outputs/legacy-updated.db";
+        let legacy_dir = "./test-outputs/legacy-updated-redoxdata";
+        write_legacy_grades_with(manifest, legacy_dir, |q| {
+            q.update(1, vec![None, Some(95), None]);
+            q.delete(3);
+        });
+
+        let err = migrate_v0(manifest, legacy_dir).unwrap_err();
+        assert!(matches!(err, FileError::CannotMigrate { .. }));
+        assert!(err.path().ends_with("0-page.data"));
+
+        // The database is still in version 0 and nothing was deleted
+        assert_eq!(detect_version(manifest).unwrap(), 0);
+        assert!(!Path::new(&snapshot_dir(manifest, 1)).exists());
+        assert!(Path::new(&format!("{}/page_directory.data", legacy_dir)).exists());
+    }
+
+    #[test]
+    fn corrupt_legacy_page_is_an_error_test() {
+        let manifest = "./test-outputs/corrupt-legacy-page.db";
+        let legacy_dir = "./test-outputs/corrupt-legacy-redoxdata";
+        write_legacy_grades(manifest, legacy_dir);
+
+        std::fs::write(format!("{}/0-page.data", legacy_dir), b"no").unwrap();
+
+        let err = migrate_v0(manifest, legacy_dir).unwrap_err();
+        assert!(matches!(err, FileError::Deserialize { .. }));
+        assert!(err.path().ends_with("0-page.data"));
+
+        // The database is still in version 0 and nothing was deleted
+        assert_eq!(detect_version(manifest).unwrap(), 0);
+        assert!(Path::new(&format!("{}/1-page.data", legacy_dir)).exists());
+    }
+
+    #[test]
+    fn reused_table_numbers_get_new_ids_test() {
+        let manifest = "./test-outputs/reused-table-num.db";
+        let legacy_dir = "./test-outputs/reused-table-num-redoxdata";
+        write_legacy_grades(manifest, legacy_dir);
+
+        // Version 0 could give a table created after a drop the number of a table that still
+        // exists, so give a second table the number of the first
+        let mut old: DatabaseMetadataV0 = decode(manifest, &read_bytes(manifest).unwrap()).unwrap();
+        let mut scores: RTableMetadata = decode(manifest, &encode(&old.tables[0])).unwrap();
+        scores.name = String::from("Scores");
+        old.tables.insert(0, scores);
+        old.tables_hashmap = RedoxQLHashMap::default();
+        old.tables_hashmap.insert(String::from("Scores"), 0);
+        old.tables_hashmap.insert(String::from("Grades"), 1);
+        std::fs::write(manifest, encode(&old)).unwrap();
+
+        migrate_v0(manifest, legacy_dir).unwrap();
+
+        let mut db = RDatabase::new();
+        db.open(manifest.to_string()).unwrap();
+
+        let scores = db.get_table(String::from("Scores")).unwrap();
+        let grades = db.get_table(String::from("Grades")).unwrap();
+        assert_eq!(scores.get_table_id(), 0);
+        assert_eq!(grades.get_table_id(), 1);
+        assert_eq!(
+            RQuery::new(grades).select(2, 0, vec![1, 1, 1]).unwrap()[0]
+                .clone()
+                .unwrap()
+                .columns,
+            vec![Some(2), Some(70), Some(60)]
+        );
+
+        // The next table continues after the highest id
+        assert_eq!(
+            db.create_table(String::from("Bikes"), 2, 0)
+                .unwrap()
+                .get_table_id(),
+            2
+        );
+    }
+
+    #[test]
+    fn newer_database_is_rejected_test() {
+        let manifest = "./test-outputs/newer-version.db";
+        write_grades(manifest);
+
+        let mut bytes = read_bytes(manifest).unwrap();
+        bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(manifest, &bytes).unwrap();
+
+        assert!(matches!(
+            upgrade(manifest).unwrap_err(),
+            FileError::UnsupportedVersion { .. }
+        ));
+    }
+}
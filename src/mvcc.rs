@@ -0,0 +1,452 @@
+use super::record::Record;
+use rustc_hash::FxHashMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
+
+/// The last commit timestamp that is completely published
+static COMMITTED: AtomicU64 = AtomicU64::new(0);
+
+/// Only one transaction publishes its writes at a time
+static COMMIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// How many snapshots in use started at each timestamp
+static LIVE_SNAPSHOTS: Mutex<BTreeMap<u64, usize>> = Mutex::new(BTreeMap::new());
+
+/// How many snapshots are in use, so writes don't have to lock LIVE_SNAPSHOTS to find out
+static NUM_LIVE_SNAPSHOTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Writers for writes made outside of a transaction, negative so they never clash with a
+/// transaction id
+static NEXT_AUTOCOMMIT_WRITER: AtomicI64 = AtomicI64::new(-1);
+
+/// The writer of writes made outside of a transaction while no snapshot is in use
+///
+/// Nobody can tell its writes from older history, so they are not stamped and not committed.
+pub const UNSTAMPED_WRITER: i64 = 0;
+
+/// The timestamp a new snapshot starts at. Everything committed at or before it is visible.
+pub fn snapshot_ts() -> u64 {
+    COMMITTED.load(Ordering::SeqCst)
+}
+
+/// Give the next commit timestamp to `publish` and make it visible once `publish` is done
+///
+/// Snapshots only start at a timestamp after everything with that timestamp was stamped, so
+/// nobody ever sees half of a commit.
+pub fn commit<F: FnOnce(u64)>(publish: F) -> u64 {
+    let _guard = COMMIT_LOCK.lock().unwrap();
+
+    let ts = COMMITTED.load(Ordering::SeqCst) + 1;
+    publish(ts);
+    COMMITTED.store(ts, Ordering::SeqCst);
+
+    ts
+}
+
+/// The start of the oldest snapshot in use, or of the next one if there is none
+///
+/// Every snapshot sees everything committed at or before it, so older history can be pruned.
+pub fn oldest_snapshot_ts() -> u64 {
+    let live = LIVE_SNAPSHOTS.lock().unwrap();
+    live.keys().next().copied().unwrap_or_else(snapshot_ts)
+}
+
+/// A writer id for writes made outside of a transaction, they are published right after
+///
+/// Ask with the table locked for writing. Without a snapshot in use this is `UNSTAMPED_WRITER`:
+/// a snapshot that starts later can only read the table once the write is done, so seeing it
+/// right away is what it would see after the commit too.
+pub fn autocommit_writer() -> i64 {
+    if NUM_LIVE_SNAPSHOTS.load(Ordering::SeqCst) == 0 {
+        return UNSTAMPED_WRITER;
+    }
+
+    NEXT_AUTOCOMMIT_WRITER.fetch_sub(1, Ordering::Relaxed)
+}
+
+// Count a snapshot that starts at `start_ts`, with LIVE_SNAPSHOTS locked
+fn register(live: &mut BTreeMap<u64, usize>, start_ts: u64) {
+    *live.entry(start_ts).or_default() += 1;
+    NUM_LIVE_SNAPSHOTS.fetch_add(1, Ordering::SeqCst);
+}
+
+/// What a transaction sees: everything committed up to `start_ts` and its own writes
+///
+/// The history it may still read is kept until it is dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    pub start_ts: u64,
+    pub transaction_id: i64,
+    // Whether it counts in LIVE_SNAPSHOTS
+    registered: bool,
+}
+
+impl Snapshot {
+    pub fn new(transaction_id: i64) -> Self {
+        // Start under the lock, so pruning never misses a snapshot that is starting
+        let mut live = LIVE_SNAPSHOTS.lock().unwrap();
+        let start_ts = snapshot_ts();
+        register(&mut live, start_ts);
+
+        Snapshot {
+            start_ts,
+            transaction_id,
+            registered: true,
+        }
+    }
+
+    /// A snapshot at any timestamp, it does not hold back pruning
+    #[cfg(test)]
+    pub fn at(start_ts: u64, transaction_id: i64) -> Self {
+        Snapshot {
+            start_ts,
+            transaction_id,
+            registered: false,
+        }
+    }
+}
+
+impl Clone for Snapshot {
+    fn clone(&self) -> Self {
+        if self.registered {
+            register(&mut LIVE_SNAPSHOTS.lock().unwrap(), self.start_ts);
+        }
+
+        Snapshot {
+            start_ts: self.start_ts,
+            transaction_id: self.transaction_id,
+            registered: self.registered,
+        }
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if !self.registered {
+            return;
+        }
+
+        let mut live = LIVE_SNAPSHOTS.lock().unwrap();
+        if let Some(count) = live.get_mut(&self.start_ts) {
+            NUM_LIVE_SNAPSHOTS.fetch_sub(1, Ordering::SeqCst);
+            *count -= 1;
+            if *count == 0 {
+                live.remove(&self.start_ts);
+            }
+        }
+    }
+}
+
+/// Who wrote a record version and whether it is visible yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionStamp {
+    /// Written by a writer that has not committed yet
+    Pending(i64),
+    /// Visible to snapshots that started at or after this timestamp
+    Committed(u64),
+}
+
+impl VersionStamp {
+    fn is_visible(&self, snapshot: &Snapshot) -> bool {
+        match self {
+            VersionStamp::Pending(writer) => *writer == snapshot.transaction_id,
+            VersionStamp::Committed(ts) => *ts <= snapshot.start_ts,
+        }
+    }
+}
+
+/// A base record that lost its primary key to an update or a delete
+#[derive(Debug, Clone)]
+struct FormerKey {
+    base: Record,
+    /// When it lost the key
+    stamp: VersionStamp,
+}
+
+/// The writes of one writer, from when it writes until nobody can see older history
+#[derive(Debug, Clone, Default)]
+struct Writes {
+    /// RIDs of the record versions
+    versions: Vec<i64>,
+    /// (primary key, base RID) of the records that lost their key
+    former_keys: Vec<(i64, i64)>,
+}
+
+/// The version stamps of the records in one table
+///
+/// Records without a stamp are visible to everyone. Those are records loaded from disk and
+/// records whose commit every snapshot in use can see, their stamps are pruned. Stamps are not
+/// persisted, everything on disk was committed before the database was opened.
+///
+/// Deleted and re-keyed base records stay reachable by their old primary key until every
+/// snapshot sees the change.
+#[derive(Debug, Clone, Default)]
+pub struct VersionMap {
+    stamps: RedoxQLHashMap<i64, VersionStamp>,
+    /// The writes of each writer that has not committed yet, so commit doesn't have to search
+    pending: RedoxQLHashMap<i64, Writes>,
+    /// Committed writes that some snapshot might not see yet, oldest commit first
+    committed: VecDeque<(u64, Writes)>,
+    /// The base records that had each primary key before a change some snapshot might not see
+    former_keys: RedoxQLHashMap<i64, Vec<FormerKey>>,
+}
+
+impl VersionMap {
+    /// Remember that a writer wrote this record
+    pub fn record_write(&mut self, rid: i64, writer: i64) {
+        if writer == UNSTAMPED_WRITER {
+            return;
+        }
+
+        self.stamps.insert(rid, VersionStamp::Pending(writer));
+        self.pending.entry(writer).or_default().versions.push(rid);
+    }
+
+    /// Remember that the base record had this primary key before the writer changed or deleted
+    /// it, so older snapshots still find it by the key
+    pub fn record_former_key(&mut self, primary_key: i64, base: Record, writer: i64) {
+        if writer == UNSTAMPED_WRITER {
+            return;
+        }
+
+        let base_rid = base.rid;
+        self.former_keys
+            .entry(primary_key)
+            .or_default()
+            .push(FormerKey {
+                base,
+                stamp: VersionStamp::Pending(writer),
+            });
+        self.pending
+            .entry(writer)
+            .or_default()
+            .former_keys
+            .push((primary_key, base_rid));
+    }
+
+    /// Make everything the writer wrote visible at `commit_ts`
+    pub fn publish(&mut self, writer: i64, commit_ts: u64) {
+        if let Some(writes) = self.pending.remove(&writer) {
+            let stamp = VersionStamp::Committed(commit_ts);
+
+            for rid in &writes.versions {
+                self.stamps.insert(*rid, stamp);
+            }
+            for (primary_key, base_rid) in &writes.former_keys {
+                if let Some(former) = self.former_key_mut(*primary_key, *base_rid, writer) {
+                    former.stamp = stamp;
+                }
+            }
+
+            self.committed.push_back((commit_ts, writes));
+        }
+    }
+
+    /// Forget the history of commits at or before `oldest_ts`, every snapshot that starts at or
+    /// after it sees them
+    pub fn prune(&mut self, oldest_ts: u64) {
+        while self
+            .committed
+            .front()
+            .is_some_and(|(commit_ts, _)| *commit_ts <= oldest_ts)
+        {
+            let (commit_ts, writes) = self.committed.pop_front().unwrap();
+
+            for rid in writes.versions {
+                self.stamps.remove(&rid);
+            }
+            for (primary_key, base_rid) in writes.former_keys {
+                self.remove_former_key(primary_key, base_rid, VersionStamp::Committed(commit_ts));
+            }
+        }
+    }
+
+    /// Drop everything the writer recorded, its writes were taken back before it committed
+    pub fn forget(&mut self, writer: i64) {
+        let Some(writes) = self.pending.remove(&writer) else {
+            return;
+        };
+
+        for rid in writes.versions {
+            self.stamps.remove(&rid);
+        }
+        for (primary_key, base_rid) in writes.former_keys {
+            self.remove_former_key(primary_key, base_rid, VersionStamp::Pending(writer));
+        }
+    }
+
+    fn remove_former_key(&mut self, primary_key: i64, base_rid: i64, stamp: VersionStamp) {
+        let Some(former) = self.former_keys.get_mut(&primary_key) else {
+            return;
+        };
+
+        former.retain(|f| f.base.rid != base_rid || f.stamp != stamp);
+        if former.is_empty() {
+            self.former_keys.remove(&primary_key);
+        }
+    }
+
+    fn former_key_mut(
+        &mut self,
+        primary_key: i64,
+        base_rid: i64,
+        writer: i64,
+    ) -> Option<&mut FormerKey> {
+        self.former_keys
+            .get_mut(&primary_key)?
+            .iter_mut()
+            .find(|f| f.base.rid == base_rid && f.stamp == VersionStamp::Pending(writer))
+    }
+
+    /// Change the base records kept for older snapshots like the ones in the page directory,
+    /// when the columns of the table change
+    pub fn remap_former_records<F: FnMut(&mut Record)>(&mut self, mut remap: F) {
+        for former in self.former_keys.values_mut().flatten() {
+            remap(&mut former.base);
+        }
+    }
+
+    /// Whether some writer wrote to this table and has not committed yet
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub fn stamp(&self, rid: i64) -> Option<VersionStamp> {
+        self.stamps.get(&rid).copied()
+    }
+
+    pub fn is_visible(&self, rid: i64, snapshot: &Snapshot) -> bool {
+        self.stamps
+            .get(&rid)
+            .is_none_or(|stamp| stamp.is_visible(snapshot))
+    }
+
+    /// The base records that had this primary key before a change the snapshot can't see
+    pub fn former_records(&self, primary_key: i64, snapshot: &Snapshot) -> Vec<Record> {
+        self.former_keys
+            .get(&primary_key)
+            .into_iter()
+            .flatten()
+            .filter(|f| !f.stamp.is_visible(snapshot))
+            .map(|f| f.base.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_visibility_test() {
+        let mut versions = VersionMap::default();
+        let reader = Snapshot::at(10, 1);
+
+        // Unstamped records are visible to everyone
+        assert!(versions.is_visible(100, &reader));
+
+        // Pending writes are only visible to the writer
+        versions.record_write(101, 2);
+        assert!(!versions.is_visible(101, &reader));
+        assert!(versions.is_visible(101, &Snapshot::at(10, 2)));
+        assert!(versions.has_pending());
+
+        // Committed after the snapshot started
+        versions.publish(2, 11);
+        assert!(!versions.is_visible(101, &reader));
+        assert!(versions.is_visible(101, &Snapshot::at(11, 1)));
+        assert!(!versions.has_pending());
+
+        // Writes that were taken back leave nothing behind
+        versions.record_write(102, 3);
+        versions.record_former_key(1, Record::default(), 3);
+        versions.forget(3);
+        assert_eq!(versions.stamp(102), None);
+        assert!(versions.former_records(1, &reader).is_empty());
+        assert!(!versions.has_pending());
+    }
+
+    #[test]
+    fn former_keys_test() {
+        let mut versions = VersionMap::default();
+        let base = Record {
+            rid: 7,
+            ..Default::default()
+        };
+
+        versions.record_former_key(1, base, 2);
+        assert_eq!(versions.former_records(1, &Snapshot::at(10, 1)).len(), 1);
+        assert!(versions.former_records(1, &Snapshot::at(10, 2)).is_empty());
+        assert!(versions.former_records(2, &Snapshot::at(10, 1)).is_empty());
+
+        // Snapshots that started before the commit still find the record by its old key
+        versions.publish(2, 11);
+        assert_eq!(versions.former_records(1, &Snapshot::at(10, 1))[0].rid, 7);
+        assert!(versions.former_records(1, &Snapshot::at(11, 1)).is_empty());
+    }
+
+    #[test]
+    fn prune_test() {
+        let mut versions = VersionMap::default();
+        let base = Record {
+            rid: 7,
+            ..Default::default()
+        };
+
+        versions.record_write(101, 2);
+        versions.record_former_key(1, base, 2);
+        versions.publish(2, 11);
+        versions.record_write(102, 3);
+        versions.publish(3, 12);
+
+        // The oldest snapshot started before the first commit, nothing can go
+        versions.prune(10);
+        assert_eq!(versions.stamp(101), Some(VersionStamp::Committed(11)));
+        assert_eq!(versions.former_records(1, &Snapshot::at(10, 1)).len(), 1);
+
+        versions.prune(11);
+        assert_eq!(versions.stamp(101), None);
+        assert!(versions.former_records(1, &Snapshot::at(10, 1)).is_empty());
+        assert_eq!(versions.stamp(102), Some(VersionStamp::Committed(12)));
+    }
+
+    #[test]
+    fn live_snapshots_hold_back_pruning_test() {
+        let snapshot = Snapshot::new(1);
+        let copy = snapshot.clone();
+        commit(|_| {});
+
+        assert!(oldest_snapshot_ts() <= snapshot.start_ts);
+
+        drop(snapshot);
+        assert!(oldest_snapshot_ts() <= copy.start_ts);
+    }
+
+    #[test]
+    fn unstamped_writes_test() {
+        let mut versions = VersionMap::default();
+
+        // Writes nobody could see the history of leave nothing to commit or prune
+        versions.record_write(101, UNSTAMPED_WRITER);
+        versions.record_former_key(1, Record::default(), UNSTAMPED_WRITER);
+        assert_eq!(versions.stamp(101), None);
+        assert!(versions.former_records(1, &Snapshot::at(0, 1)).is_empty());
+        assert!(!versions.has_pending());
+
+        // With a snapshot in use, writes outside of a transaction are stamped again
+        let _snapshot = Snapshot::new(1);
+        assert_ne!(autocommit_writer(), UNSTAMPED_WRITER);
+    }
+
+    #[test]
+    fn commit_advances_snapshot_test() {
+        let mut seen = 0;
+        let ts = commit(|ts| seen = ts);
+
+        assert_eq!(seen, ts);
+        assert!(snapshot_ts() >= ts);
+    }
+}
@@ -0,0 +1,323 @@
+use super::database::LockType;
+use std::fmt;
+
+/// One query a transaction can run, with its arguments
+///
+/// These mirror the methods of `RQuery`. Build them directly from Rust, or parse the name of a
+/// Python query method and its flat argument list with `Operation::parse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// A value for every column
+    Insert(Vec<i64>),
+    /// The new value of every column, None keeps the old one
+    Update {
+        primary_key: i64,
+        columns: Vec<Option<i64>>,
+    },
+    Delete {
+        primary_key: i64,
+    },
+    Increment {
+        primary_key: i64,
+        column: i64,
+    },
+    Sum {
+        start: i64,
+        end: i64,
+        column: i64,
+    },
+    SumVersion {
+        start: i64,
+        end: i64,
+        column: i64,
+        relative_version: i64,
+    },
+    /// `projected` has a 1 for every column to return and a 0 for the others
+    Select {
+        key: i64,
+        key_index: i64,
+        projected: Vec<i64>,
+    },
+    SelectVersion {
+        key: i64,
+        key_index: i64,
+        projected: Vec<i64>,
+        relative_version: i64,
+    },
+}
+
+/// Why a query name and its arguments don't make an `Operation`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationError {
+    /// Not the name of a query method
+    UnknownOperation(String),
+    /// The operation takes a different number of arguments
+    WrongArgumentCount {
+        operation: String,
+        expected: usize,
+        found: usize,
+    },
+    /// An argument that has to have a value was None
+    MissingArgument { operation: String, position: usize },
+    /// Transactions lock records by primary key, so they can only select on that column
+    NotPrimaryKey {
+        operation: String,
+        key_index: i64,
+        primary_key_column: usize,
+    },
+}
+
+impl fmt::Display for OperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationError::UnknownOperation(name) => write!(f, "Unknown operation {}", name),
+            OperationError::WrongArgumentCount {
+                operation,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} arguments but was given {}",
+                operation, expected, found
+            ),
+            OperationError::MissingArgument {
+                operation,
+                position,
+            } => write!(f, "Argument {} of {} can't be None", position, operation),
+            OperationError::NotPrimaryKey {
+                operation,
+                key_index,
+                primary_key_column,
+            } => write!(
+                f,
+                "{} in a transaction has to search the primary key column {}, not column {}",
+                operation, primary_key_column, key_index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OperationError {}
+
+impl Operation {
+    /// Build an operation from the name of a Query method and its flattened arguments
+    ///
+    /// The projected columns of select and select_version are part of the flat list, so the
+    /// number of arguments depends on the number of columns of the table.
+    pub fn parse(
+        name: &str,
+        args: &[Option<i64>],
+        num_columns: usize,
+    ) -> Result<Operation, OperationError> {
+        let expected = match name {
+            "insert" => num_columns,
+            "update" => 1 + num_columns,
+            "delete" => 1,
+            "increment" => 2,
+            "sum" => 3,
+            "sum_version" => 4,
+            "select" => 2 + num_columns,
+            "select_version" => 3 + num_columns,
+            _ => return Err(OperationError::UnknownOperation(name.to_string())),
+        };
+
+        let short_update = name == "update" && (1..expected).contains(&args.len());
+        if args.len() != expected && !short_update {
+            return Err(OperationError::WrongArgumentCount {
+                operation: name.to_string(),
+                expected,
+                found: args.len(),
+            });
+        }
+
+        let arg = |position: usize| {
+            args[position].ok_or_else(|| OperationError::MissingArgument {
+                operation: name.to_string(),
+                position,
+            })
+        };
+        // Columns that are not given are not projected
+        let projected = |range: std::ops::Range<usize>| -> Vec<i64> {
+            args[range].iter().map(|c| c.unwrap_or(0)).collect()
+        };
+
+        Ok(match name {
+            "insert" => Operation::Insert((0..num_columns).map(arg).collect::<Result<_, _>>()?),
+            "update" => Operation::Update {
+                primary_key: arg(0)?,
+                columns: (1..expected)
+                    .map(|position| args.get(position).copied().flatten())
+                    .collect(),
+            },
+            "delete" => Operation::Delete {
+                primary_key: arg(0)?,
+            },
+            "increment" => Operation::Increment {
+                primary_key: arg(0)?,
+                column: arg(1)?,
+            },
+            "sum" => Operation::Sum {
+                start: arg(0)?,
+                end: arg(1)?,
+                column: arg(2)?,
+            },
+            "sum_version" => Operation::SumVersion {
+                start: arg(0)?,
+                end: arg(1)?,
+                column: arg(2)?,
+                relative_version: arg(3)?,
+            },
+            "select" => Operation::Select {
+                key: arg(0)?,
+                key_index: arg(1)?,
+                projected: projected(2..expected),
+            },
+            "select_version" => Operation::SelectVersion {
+                key: arg(0)?,
+                key_index: arg(1)?,
+                projected: projected(2..expected - 1),
+                relative_version: arg(expected - 1)?,
+            },
+            _ => unreachable!(),
+        })
+    }
+
+    /// The name of the Query method this operation runs
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Insert(_) => "insert",
+            Operation::Update { .. } => "update",
+            Operation::Delete { .. } => "delete",
+            Operation::Increment { .. } => "increment",
+            Operation::Sum { .. } => "sum",
+            Operation::SumVersion { .. } => "sum_version",
+            Operation::Select { .. } => "select",
+            Operation::SelectVersion { .. } => "select_version",
+        }
+    }
+
+    /// Whether a transaction can run the operation on a table with this primary key column
+    ///
+    /// Records are locked by their primary key, so selects have to search that column.
+    pub fn check_in_transaction(&self, primary_key_column: usize) -> Result<(), OperationError> {
+        match self {
+            Operation::Select { key_index, .. } | Operation::SelectVersion { key_index, .. }
+                if *key_index != primary_key_column as i64 =>
+            {
+                Err(OperationError::NotPrimaryKey {
+                    operation: self.name().to_string(),
+                    key_index: *key_index,
+                    primary_key_column,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Operation::Sum { .. }
+                | Operation::SumVersion { .. }
+                | Operation::Select { .. }
+                | Operation::SelectVersion { .. }
+        )
+    }
+
+    /// Reads share their records, writes need them for themselves
+    pub fn lock_type(&self) -> LockType {
+        if self.is_read_only() {
+            LockType::Shared
+        } else {
+            LockType::Exclusive
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_operation_test() {
+        assert_eq!(
+            Operation::parse("update", &[Some(1), None, Some(5), None], 3),
+            Ok(Operation::Update {
+                primary_key: 1,
+                columns: vec![None, Some(5), None]
+            })
+        );
+        assert_eq!(
+            Operation::parse(
+                "select_version",
+                &[Some(1), Some(0), Some(1), None, Some(1), Some(-2)],
+                3
+            ),
+            Ok(Operation::SelectVersion {
+                key: 1,
+                key_index: 0,
+                projected: vec![1, 0, 1],
+                relative_version: -2
+            })
+        );
+        assert_eq!(
+            Operation::parse("update", &[Some(1), Some(5)], 3),
+            Ok(Operation::Update {
+                primary_key: 1,
+                columns: vec![Some(5), None, None]
+            })
+        );
+        assert_eq!(
+            Operation::parse("sum", &[Some(0), Some(10), Some(2)], 3)
+                .unwrap()
+                .lock_type(),
+            LockType::Shared
+        );
+    }
+
+    #[test]
+    fn parse_operation_errors_test() {
+        assert_eq!(
+            Operation::parse("upsert", &[Some(1)], 3),
+            Err(OperationError::UnknownOperation(String::from("upsert")))
+        );
+        assert_eq!(
+            Operation::parse("update", &[Some(1), Some(2), Some(3), Some(4), Some(5)], 3),
+            Err(OperationError::WrongArgumentCount {
+                operation: String::from("update"),
+                expected: 4,
+                found: 5
+            })
+        );
+        assert_eq!(
+            Operation::parse("insert", &[Some(1), None, Some(3)], 3),
+            Err(OperationError::MissingArgument {
+                operation: String::from("insert"),
+                position: 1
+            })
+        );
+    }
+
+    #[test]
+    fn check_in_transaction_test() {
+        let select = |key_index| Operation::Select {
+            key: 1,
+            key_index,
+            projected: vec![1, 1, 1],
+        };
+
+        assert_eq!(select(0).check_in_transaction(0), Ok(()));
+        assert_eq!(
+            select(1).check_in_transaction(0),
+            Err(OperationError::NotPrimaryKey {
+                operation: String::from("select"),
+                key_index: 1,
+                primary_key_column: 0
+            })
+        );
+        assert_eq!(
+            Operation::Delete { primary_key: 1 }.check_in_transaction(2),
+            Ok(())
+        );
+    }
+}
@@ -1,53 +1,239 @@
-use super::filewriter::{build_binary_writer, Writer};
+use super::compression::{compress, Codec};
+use super::filewriter::{build_binary_writer, FileError, Writer};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
 static MAX_SIZE_RECORD: i64 = i64::MAX;
 
-#[pyclass]
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Bucket `b` of a page holds `1 << (b + FIRST_BUCKET_BITS)` values
+const FIRST_BUCKET_BITS: u32 = 10;
+const NUM_BUCKETS: usize = (usize::BITS - FIRST_BUCKET_BITS) as usize;
+
+/// A shared reference to a page.
+///
+/// Pages need no lock around them: reads never wait, see `PhysicalPage`.
+pub type PageHandle = Arc<PhysicalPage>;
+
+/// The values of one column, only ever appended to
+///
+/// Values live in buckets that double in size and never move once they are allocated, so a
+/// value that was appended stays where it is. Reading is an atomic load, which lets any number of
+/// threads read a page while another appends to it. Values below `num_records` are sealed: only
+/// the schema encoding and indirection columns are overwritten in place, and those writes are
+/// atomic stores, so a reader sees either the old or the new value and never waits for the write.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Deserialize, Serialize)]
+#[serde(from = "StoredPage", into = "StoredPage")]
 pub struct PhysicalPage {
-    pub data: Vec<i64>,
-    pub num_records: i64,
-    pub column_index: i64,
+    buckets: [OnceLock<Box<[AtomicI64]>>; NUM_BUCKETS],
+    /// Values below this are written and can be read
+    len: AtomicUsize,
+    /// Appends to the same page take turns, the rows of a container are appended in order anyway
+    append: Mutex<()>,
+    column_index: AtomicI64,
+}
+
+/// Where the value at `index` lives: (bucket, offset in the bucket)
+fn locate(index: usize) -> (usize, usize) {
+    let position = index + (1 << FIRST_BUCKET_BITS);
+    let bucket = (usize::BITS - 1 - position.leading_zeros() - FIRST_BUCKET_BITS) as usize;
+
+    (
+        bucket,
+        position - (1 << (bucket as u32 + FIRST_BUCKET_BITS)),
+    )
 }
 
 impl PhysicalPage {
     pub fn new(column_index: i64) -> Self {
         PhysicalPage {
-            data: Vec::<i64>::new(),
-            num_records: 0,
-            column_index,
+            buckets: std::array::from_fn(|_| OnceLock::new()),
+            len: AtomicUsize::new(0),
+            append: Mutex::new(()),
+            column_index: AtomicI64::new(column_index),
         }
     }
 
+    /// A page that holds `values`, in order
+    pub fn from_values(column_index: i64, values: Vec<i64>) -> Self {
+        let page = PhysicalPage::new(column_index);
+        page.extend(values);
+        page
+    }
+
+    /// Wrap the page in a `PageHandle` so it can be shared between records and threads
+    pub fn into_handle(self) -> PageHandle {
+        Arc::new(self)
+    }
+
     pub fn has_capacity(&self) -> bool {
-        return self.num_records < MAX_SIZE_RECORD;
+        self.num_records() < MAX_SIZE_RECORD
+    }
+
+    pub fn num_records(&self) -> i64 {
+        self.len.load(Ordering::Acquire) as i64
+    }
+
+    /// Where the page goes when it is loaded again
+    pub fn column_index(&self) -> i64 {
+        self.column_index.load(Ordering::Relaxed)
+    }
+
+    pub fn set_column_index(&self, column_index: i64) {
+        self.column_index.store(column_index, Ordering::Relaxed);
+    }
+
+    /// Append a value, returns its offset
+    pub fn write(&self, value: i64) -> i64 {
+        self.extend([value])
     }
 
-    pub fn write(&mut self, value: i64) {
-        if self.has_capacity() {
-            self.data.push(value);
-            self.num_records += 1;
+    /// Append every value, returns the offset of the last one
+    pub fn extend(&self, values: impl IntoIterator<Item = i64>) -> i64 {
+        let _append = self.append.lock().unwrap();
+        let mut len = self.len.load(Ordering::Relaxed);
+
+        for value in values {
+            if !self.has_capacity() {
+                break;
+            }
+
+            let (bucket, offset) = locate(len);
+            let slots = self.buckets[bucket].get_or_init(|| {
+                (0..1usize << (bucket as u32 + FIRST_BUCKET_BITS))
+                    .map(|_| AtomicI64::new(0))
+                    .collect()
+            });
+            slots[offset].store(value, Ordering::Relaxed);
+
+            // Readers only look below `len`, so the value is in place before they can see it
+            len += 1;
+            self.len.store(len, Ordering::Release);
         }
+
+        len as i64 - 1
     }
 
-    pub fn overwrite(&mut self, index: usize, value: i64) {
-        self.data[index] = value;
+    /// Replace a value that was already written
+    pub fn overwrite(&self, index: usize, value: i64) {
+        self.slot(index)
+            .expect("Can only overwrite a written value")
+            .store(value, Ordering::Release);
     }
 
     pub fn read(&self, index: usize) -> Option<i64> {
-        Some(self.data[index])
+        Some(self.slot(index)?.load(Ordering::Acquire))
+    }
+
+    fn slot(&self, index: usize) -> Option<&AtomicI64> {
+        if index >= self.len.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let (bucket, offset) = locate(index);
+        Some(&self.buckets[bucket].get()?[offset])
+    }
+
+    /// Every value, oldest first
+    pub fn values(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len.load(Ordering::Acquire)).filter_map(|index| self.read(index))
+    }
+
+    pub fn save_state(&self, path: &str) -> io::Result<()> {
+        let writer: Writer<PhysicalPageMetadata> = build_binary_writer();
+        writer.write_file(path, &self.get_metadata())
+    }
+
+    pub fn load_state(path: &str) -> Result<PhysicalPage, FileError> {
+        let writer: Writer<PhysicalPageMetadata> = build_binary_writer();
+        let page_meta = writer.read_file(path)?;
+
+        page_meta.load_state(path)
+    }
+
+    /// Compress the page with whichever codec suits its values best
+    pub fn get_metadata(&self) -> PhysicalPageMetadata {
+        let values: Vec<i64> = self.values().collect();
+        let (codec, data) = compress(&values);
+
+        PhysicalPageMetadata {
+            column_index: self.column_index(),
+            num_records: values.len() as i64,
+            codec,
+            num_values: values.len() as u64,
+            data,
+        }
     }
+}
+
+/// A copy of the page's values at the time of the clone
+impl Clone for PhysicalPage {
+    fn clone(&self) -> Self {
+        PhysicalPage::from_values(self.column_index(), self.values().collect())
+    }
+}
 
-    pub fn save_state(&self, id: i64) {
-        let writer: Writer<PhysicalPage> = build_binary_writer();
-        writer.write_file(&format!("./redoxdata/{}-page.data", id), self);
+impl fmt::Debug for PhysicalPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhysicalPage")
+            .field("data", &self.values().collect::<Vec<i64>>())
+            .field("column_index", &self.column_index())
+            .finish()
     }
+}
+
+/// How a page is serialized when it is written as a whole
+#[derive(Deserialize, Serialize)]
+struct StoredPage {
+    data: Vec<i64>,
+    num_records: i64,
+    column_index: i64,
+}
 
-    pub fn load_state(id: i64) -> PhysicalPage {
-        let writer: Writer<PhysicalPage> = build_binary_writer();
-        writer.read_file(&format!("./redoxdata/{}-page.data", id))
+impl From<StoredPage> for PhysicalPage {
+    fn from(page: StoredPage) -> Self {
+        PhysicalPage::from_values(page.column_index, page.data)
+    }
+}
+
+impl From<PhysicalPage> for StoredPage {
+    fn from(page: PhysicalPage) -> Self {
+        StoredPage {
+            data: page.values().collect(),
+            num_records: page.num_records(),
+            column_index: page.column_index(),
+        }
+    }
+}
+
+/// A page the way it is stored on disk
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PhysicalPageMetadata {
+    pub column_index: i64,
+    pub num_records: i64,
+    /// The codec `data` was compressed with
+    pub codec: Codec,
+    pub num_values: u64,
+    pub data: Vec<u8>,
+}
+
+impl PhysicalPageMetadata {
+    /// Decompress the page, `path` is only used to say which file was bad
+    pub fn load_state(&self, path: &str) -> Result<PhysicalPage, FileError> {
+        let data = self
+            .codec
+            .decode(&self.data, self.num_values as usize)
+            .ok_or_else(|| FileError::Deserialize {
+                path: path.to_string(),
+                message: format!("could not decode {:?} compressed page", self.codec),
+            })?;
+
+        Ok(PhysicalPage::from_values(self.column_index, data))
     }
 }
 
@@ -57,7 +243,7 @@ mod tests {
 
     #[test]
     fn write_page_test() {
-        let mut phys_page = PhysicalPage::new(0);
+        let phys_page = PhysicalPage::new(0);
 
         phys_page.write(10);
         assert_eq!(phys_page.read(0).unwrap(), 10);
@@ -67,8 +253,8 @@ mod tests {
     fn save_load_test() {
         // Scope so that page_one and page_two get unallocated and leave scope
         {
-            let mut page_one = PhysicalPage::new(0);
-            let mut page_two = PhysicalPage::new(1);
+            let page_one = PhysicalPage::new(0);
+            let page_two = PhysicalPage::new(1);
 
             // Write to page_one
             page_one.write(100);
@@ -81,13 +267,13 @@ mod tests {
             page_two.write(333);
 
             // Save page_one and page_two
-            page_one.save_state(1);
-            page_two.save_state(2);
+            page_one.save_state("./test-outputs/1-page.data").unwrap();
+            page_two.save_state("./test-outputs/2-page.data").unwrap();
         }
 
         // Load page_one and page_two
-        let mut page_one = PhysicalPage::load_state(1);
-        let mut page_two = PhysicalPage::load_state(2);
+        let page_one = PhysicalPage::load_state("./test-outputs/1-page.data").unwrap();
+        let page_two = PhysicalPage::load_state("./test-outputs/2-page.data").unwrap();
 
         // Write to both pages once more
         page_one.write(400);
@@ -107,11 +293,75 @@ mod tests {
 
     #[test]
     fn many_writes_page_test() {
-        let mut phys_page = PhysicalPage::new(0);
+        let phys_page = PhysicalPage::new(0);
 
         for x in 0..1000 {
             phys_page.write(x * 10);
             assert_eq!(phys_page.read(x as usize).unwrap(), x * 10);
         }
     }
+
+    #[test]
+    fn concurrent_append_and_read_test() {
+        use std::thread;
+
+        // Enough values to fill a few buckets
+        let page = PhysicalPage::new(0).into_handle();
+        page.write(0);
+
+        let writer = {
+            let page = page.clone();
+            thread::spawn(move || {
+                for x in 1..10_000 {
+                    page.write(x);
+                    page.overwrite(0, x);
+                }
+            })
+        };
+
+        // Readers never wait and only see values that were completely written
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let page = page.clone();
+                thread::spawn(move || {
+                    while page.num_records() < 10_000 {
+                        let last = page.num_records() - 1;
+                        if last > 0 {
+                            assert_eq!(page.read(last as usize), Some(last));
+                            assert_eq!(page.read(last as usize / 2 + 1), Some(last / 2 + 1));
+                        }
+                        assert!(page.read(0).unwrap() < 10_000);
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(page.read(0), Some(9_999));
+        assert!(page.values().skip(1).eq(1..10_000));
+        assert_eq!(page.read(10_000), None);
+    }
+
+    #[test]
+    fn compressed_save_load_test() {
+        let page = PhysicalPage::new(3);
+        for x in 0..1000 {
+            page.write(x);
+        }
+
+        let page_meta = page.get_metadata();
+        assert_eq!(page_meta.codec, Codec::DeltaVarint);
+        assert!(page_meta.data.len() < 1000 * 8);
+
+        page.save_state("./test-outputs/30-page.data").unwrap();
+        let loaded = PhysicalPage::load_state("./test-outputs/30-page.data").unwrap();
+
+        assert!(loaded.values().eq(page.values()));
+        assert_eq!(loaded.num_records(), 1000);
+        assert_eq!(loaded.column_index(), 3);
+    }
 }
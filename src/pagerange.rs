@@ -1,14 +1,16 @@
 use super::container::{
     BaseContainer, BaseContainerMetadata, TailContainer, TailContainerMetadata,
 };
-use super::filewriter::{build_binary_writer, Writer};
+use super::filewriter::{build_binary_writer, FileError, Writer};
+use super::page::PageHandle;
 use super::record::Record;
-use crate::record::{RecordAddress, RecordLock};
+use crate::record::RecordAddress;
 use crate::table::PageDirectory;
 use log::info;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
-use std::sync::{Arc, Mutex, RwLock};
+use std::io;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 #[derive(Clone, Default, Deserialize, Serialize, Debug)]
@@ -42,10 +44,29 @@ impl PageRange {
         self.base_container.insert_record(new_rid, values)
     }
 
+    /// Write many records of `(rid, values)`
+    pub fn write_many(&mut self, records: Vec<(i64, Vec<i64>)>) -> Vec<Record> {
+        self.base_container.insert_records(records)
+    }
+
     pub fn read(&self, record: Record) -> Option<Vec<i64>> {
         Some(self.base_container.read_record(record))
     }
 
+    /// Add a column to both containers, returns the new base and tail pages
+    pub fn add_column(&mut self, default: i64) -> (PageHandle, PageHandle) {
+        (
+            self.base_container.add_column(default),
+            self.tail_container.add_column(default),
+        )
+    }
+
+    /// Reorder or drop the columns of both containers
+    pub fn arrange_columns(&mut self, order: &[usize]) {
+        self.base_container.arrange_columns(order);
+        self.tail_container.arrange_columns(order);
+    }
+
     pub fn merge(&mut self, page_directory: Arc<Mutex<PageDirectory>>) {
         // println!("Merge: Pre-starting merge operation in a separate thread");
 
@@ -63,10 +84,9 @@ impl PageRange {
             // println!("Thread: Locking tail_container.rid_page()");
             let tail_rid_page = tail_container.rid_page();
             let tail_rid_data = {
-                let rid_guard = tail_rid_page.lock().unwrap();
-                // println!("Thread: Acquired tail_container.rid_page lock, data length: {}", rid_guard.data.len());
-                let data = rid_guard.data.clone();
-                data
+                // println!("Thread: Read tail_container.rid_page, data length: {}", tail_rid_page.num_records());
+
+                tail_rid_page.values().collect::<Vec<i64>>()
             };
 
             if tail_rid_data.is_empty() {
@@ -87,9 +107,8 @@ impl PageRange {
                 // Check if we've seen all rids using new_base's RID page.
                 {
                     let new_base_rid_page = new_base.rid_page();
-                    let rid_guard = new_base_rid_page.lock().unwrap();
-                    // println!("Thread: new_base.rid_page num_records: {}", rid_guard.num_records);
-                    if seen_rids.len() >= rid_guard.num_records as usize {
+                    // println!("Thread: new_base.rid_page num_records: {}", new_base_rid_page.num_records());
+                    if seen_rids.len() >= new_base_rid_page.num_records() as usize {
                         // println!("Thread: Seen all rids, breaking loop");
                         break;
                     }
@@ -107,8 +126,9 @@ impl PageRange {
                 let base_rid = {
                     // println!("Thread: Locking base_rid_address.page for base_rid");
                     let base_rid_page = base_rid_address.page;
-                    let page_guard = base_rid_page.lock().unwrap();
-                    let brid = page_guard.data[base_rid_address.offset as usize];
+                    let brid = base_rid_page
+                        .read(base_rid_address.offset as usize)
+                        .unwrap();
                     // println!("Thread: Retrieved base_rid: {}", brid);
                     brid
                 };
@@ -120,29 +140,22 @@ impl PageRange {
                     {
                         // println!("Thread: Locking new_base.schema_encoding_page");
                         let schema_page = new_base.schema_encoding_page();
-                        let mut schema_guard = schema_page.lock().unwrap();
-                        schema_guard.data[offset] = 0;
+                        schema_page.overwrite(offset, 0);
                         // println!("Thread: Updated schema_encoding_page at offset {}", offset);
                     }
                     {
                         // println!("Thread: Locking new_base.indirection_page");
                         let indirection_page = new_base.indirection_page();
-                        let mut indir_guard = indirection_page.lock().unwrap();
-                        indir_guard.data[offset] = base_rid;
+                        indirection_page.overwrite(offset, base_rid);
                         // println!("Thread: Updated indirection_page at offset {} with base_rid {}", offset, base_rid);
                     }
 
                     // println!("Thread: Creating new record for base_rid: {}", base_rid);
-                    let new_record = Record {
-                        rid: base_rid,
-                        addresses: Arc::new(Mutex::new(Vec::new())),
-                        lock: Arc::new(RwLock::new(RecordLock::default())),
-                    };
+                    let mut new_addresses: Vec<RecordAddress> = Vec::new();
 
                     {
                         let new_rid_page = new_base.rid_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: new_rid_page,
                             offset: offset as i64,
                         });
@@ -150,8 +163,7 @@ impl PageRange {
                     }
                     {
                         let schema_page = new_base.schema_encoding_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: schema_page,
                             offset: offset as i64,
                         });
@@ -159,8 +171,7 @@ impl PageRange {
                     }
                     {
                         let indirection_page = new_base.indirection_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: indirection_page,
                             offset: offset as i64,
                         });
@@ -168,8 +179,7 @@ impl PageRange {
                     }
                     {
                         let base_rid_page = new_base.base_rid_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: base_rid_page,
                             offset: offset as i64,
                         });
@@ -180,22 +190,21 @@ impl PageRange {
                         // println!("Thread: Processing column {} for tail_record with base_rid: {}", i, base_rid);
                         let tail_col_page = tail_record.columns()[i].page.clone();
                         let col_value = {
-                            let col_guard = tail_col_page.lock().unwrap();
-                            let val = col_guard.data[tail_record.columns()[i].offset as usize];
+                            let val = tail_col_page
+                                .read(tail_record.columns()[i].offset as usize)
+                                .unwrap();
                             // println!("Thread: Got column value {} for column {}", val, i);
                             val
                         };
 
                         {
                             let new_col_page = new_base.column_page(i as i64);
-                            let mut new_col_guard = new_col_page.lock().unwrap();
-                            new_col_guard.data[offset] = col_value;
+                            new_col_page.overwrite(offset, col_value);
                             // println!("Thread: Updated new_base column {} at offset {} with value {}", i, offset, col_value);
                         }
                         {
                             let new_col_page = new_base.column_page(i as i64);
-                            let mut addr_guard = new_record.addresses.lock().unwrap();
-                            addr_guard.push(RecordAddress {
+                            new_addresses.push(RecordAddress {
                                 page: new_col_page,
                                 offset: offset as i64,
                             });
@@ -203,6 +212,11 @@ impl PageRange {
                         }
                     }
 
+                    let new_record = Record {
+                        rid: base_rid,
+                        addresses: Arc::new(new_addresses),
+                    };
+
                     new_records.push(new_record);
                     // println!("Thread: New record for base_rid {} added", base_rid);
                     seen_rids.insert(base_rid);
@@ -228,8 +242,9 @@ impl PageRange {
             let current_indir_val = {
                 // println!("Main: Locking current_record.indirection().page for record {}", record.rid);
                 let indirection_page = current_record.indirection().page.clone();
-                let indir_guard = indirection_page.lock().unwrap();
-                let val = indir_guard.data[current_record.indirection().offset as usize];
+                let val = indirection_page
+                    .read(current_record.indirection().offset as usize)
+                    .unwrap();
                 // println!("Main: Current indirection value for record {} is {}", record.rid, val);
                 val
             };
@@ -237,8 +252,8 @@ impl PageRange {
             if current_indir_val > self.base_container.tail_page_sequence {
                 // println!("Main: Updating record {} indirection with value {}", record.rid, current_indir_val);
                 let record_indirection_page = record.indirection().page.clone();
-                let mut rec_indir_guard = record_indirection_page.lock().unwrap();
-                rec_indir_guard.data[record.indirection().offset as usize] = current_indir_val;
+                record_indirection_page
+                    .overwrite(record.indirection().offset as usize, current_indir_val);
             }
             let rid = record.rid;
             pd_guard.directory.insert(rid, record);
@@ -268,10 +283,9 @@ impl PageRange {
             // println!("Thread: Locking tail_container.rid_page()");
             let tail_rid_page = tail_container.rid_page();
             let tail_rid_data = {
-                let rid_guard = tail_rid_page.lock().unwrap();
-                // println!("Thread: Acquired tail_container.rid_page lock, data length: {}", rid_guard.data.len());
-                let data = rid_guard.data.clone();
-                data
+                // println!("Thread: Read tail_container.rid_page, data length: {}", tail_rid_page.num_records());
+
+                tail_rid_page.values().collect::<Vec<i64>>()
             };
 
             if tail_rid_data.is_empty() {
@@ -297,9 +311,8 @@ impl PageRange {
                 // Check if we've seen all rids using new_base's RID page.
                 {
                     let new_base_rid_page = new_base.rid_page();
-                    let rid_guard = new_base_rid_page.lock().unwrap();
-                    // println!("Thread: new_base.rid_page num_records: {}", rid_guard.num_records);
-                    if seen_rids.len() >= rid_guard.num_records as usize {
+                    // println!("Thread: new_base.rid_page num_records: {}", new_base_rid_page.num_records());
+                    if seen_rids.len() >= new_base_rid_page.num_records() as usize {
                         // println!("Thread: Seen all rids, breaking loop");
                         break;
                     }
@@ -317,8 +330,9 @@ impl PageRange {
                 let base_rid = {
                     // println!("Thread: Locking base_rid_address.page for base_rid");
                     let base_rid_page = base_rid_address.page;
-                    let page_guard = base_rid_page.lock().unwrap();
-                    let brid = page_guard.data[base_rid_address.offset as usize];
+                    let brid = base_rid_page
+                        .read(base_rid_address.offset as usize)
+                        .unwrap();
                     // println!("Thread: Retrieved base_rid: {}", brid);
                     brid
                 };
@@ -330,29 +344,22 @@ impl PageRange {
                     {
                         // println!("Thread: Locking new_base.schema_encoding_page");
                         let schema_page = new_base.schema_encoding_page();
-                        let mut schema_guard = schema_page.lock().unwrap();
-                        schema_guard.data[offset] = 0;
+                        schema_page.overwrite(offset, 0);
                         // println!("Thread: Updated schema_encoding_page at offset {}", offset);
                     }
                     {
                         // println!("Thread: Locking new_base.indirection_page");
                         let indirection_page = new_base.indirection_page();
-                        let mut indir_guard = indirection_page.lock().unwrap();
-                        indir_guard.data[offset] = base_rid;
+                        indirection_page.overwrite(offset, base_rid);
                         // println!("Thread: Updated indirection_page at offset {} with base_rid {}", offset, base_rid);
                     }
 
                     // println!("Thread: Creating new record for base_rid: {}", base_rid);
-                    let new_record = Record {
-                        rid: base_rid,
-                        addresses: Arc::new(Mutex::new(Vec::new())),
-                        lock: Arc::new(RwLock::new(RecordLock::default())),
-                    };
+                    let mut new_addresses: Vec<RecordAddress> = Vec::new();
 
                     {
                         let new_rid_page = new_base.rid_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: new_rid_page,
                             offset: offset as i64,
                         });
@@ -360,8 +367,7 @@ impl PageRange {
                     }
                     {
                         let schema_page = new_base.schema_encoding_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: schema_page,
                             offset: offset as i64,
                         });
@@ -369,8 +375,7 @@ impl PageRange {
                     }
                     {
                         let indirection_page = new_base.indirection_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: indirection_page,
                             offset: offset as i64,
                         });
@@ -378,8 +383,7 @@ impl PageRange {
                     }
                     {
                         let base_rid_page = new_base.base_rid_page();
-                        let mut addr_guard = new_record.addresses.lock().unwrap();
-                        addr_guard.push(RecordAddress {
+                        new_addresses.push(RecordAddress {
                             page: base_rid_page,
                             offset: offset as i64,
                         });
@@ -390,22 +394,21 @@ impl PageRange {
                         // println!("Thread: Processing column {} for tail_record with base_rid: {}", i, base_rid);
                         let tail_col_page = &tail_record.columns()[i].page;
                         let col_value = {
-                            let col_guard = tail_col_page.lock().unwrap();
-                            let val = col_guard.data[tail_record.columns()[i].offset as usize];
+                            let val = tail_col_page
+                                .read(tail_record.columns()[i].offset as usize)
+                                .unwrap();
                             // println!("Thread: Got column value {} for column {}", val, i);
                             val
                         };
 
                         {
                             let new_col_page = new_base.column_page(i as i64);
-                            let mut new_col_guard = new_col_page.lock().unwrap();
-                            new_col_guard.data[offset] = col_value;
+                            new_col_page.overwrite(offset, col_value);
                             // println!("Thread: Updated new_base column {} at offset {} with value {}", i, offset, col_value);
                         }
                         {
                             let new_col_page = new_base.column_page(i as i64);
-                            let mut addr_guard = new_record.addresses.lock().unwrap();
-                            addr_guard.push(RecordAddress {
+                            new_addresses.push(RecordAddress {
                                 page: new_col_page,
                                 offset: offset as i64,
                             });
@@ -413,6 +416,11 @@ impl PageRange {
                         }
                     }
 
+                    let new_record = Record {
+                        rid: base_rid,
+                        addresses: Arc::new(new_addresses),
+                    };
+
                     new_records.push(new_record);
                     // println!("Thread: New record for base_rid {} added", base_rid);
                     seen_rids.insert(base_rid);
@@ -448,8 +456,9 @@ impl PageRange {
             let current_indir_val = {
                 // println!("Main: Locking current_record.indirection().page for record {}", record.rid);
                 let indirection_page = current_record.indirection().page;
-                let indir_guard = indirection_page.lock().unwrap();
-                let val = indir_guard.data[current_record.indirection().offset as usize];
+                let val = indirection_page
+                    .read(current_record.indirection().offset as usize)
+                    .unwrap();
                 // println!("Main: Current indirection value for record {} is {}", record.rid, val);
                 val
             };
@@ -457,8 +466,8 @@ impl PageRange {
             if current_indir_val > self.base_container.tail_page_sequence {
                 // println!("Main: Updating record {} indirection with value {}", record.rid, current_indir_val);
                 let record_indirection_page = record.indirection().page;
-                let mut rec_indir_guard = record_indirection_page.lock().unwrap();
-                rec_indir_guard.data[record.indirection().offset as usize] = current_indir_val;
+                record_indirection_page
+                    .overwrite(record.indirection().offset as usize, current_indir_val);
             }
             let rid = record.rid;
             pd_guard.directory.insert(rid, record);
@@ -468,25 +477,25 @@ impl PageRange {
         info!("Merge finished!");
     }
 
-    pub fn save_state(&self) {
+    pub fn save_state(&self, dir: &str) -> io::Result<()> {
         // Save the state of the two containers
-        self.base_container.save_state();
-        self.tail_container.save_state();
+        self.base_container.save_state(dir)?;
+        self.tail_container.save_state(dir)?;
 
         let pr_meta = self.get_metadata();
 
         let writer: Writer<PageRangeMetadata> = build_binary_writer();
-        writer.write_file("./redoxdata/pagerange.data", &pr_meta);
+        writer.write_file(&format!("{}/pagerange.data", dir), &pr_meta)
     }
 
-    pub fn load_state() -> PageRange {
+    pub fn load_state(dir: &str) -> Result<PageRange, FileError> {
         let writer: Writer<PageRangeMetadata> = build_binary_writer();
-        let pr_meta: PageRangeMetadata = writer.read_file("./redoxdata/pagerange.data");
+        let pr_meta: PageRangeMetadata = writer.read_file(&format!("{}/pagerange.data", dir))?;
 
-        PageRange {
-            base_container: pr_meta.base_container.load_state(),
-            tail_container: pr_meta.tail_container.load_state(),
-        }
+        Ok(PageRange {
+            base_container: pr_meta.base_container.load_state(dir)?,
+            tail_container: pr_meta.tail_container.load_state(dir)?,
+        })
     }
 
     pub fn get_metadata(&self) -> PageRangeMetadata {
@@ -1,16 +1,24 @@
 use super::container::{ReservedColumns, NUM_RESERVED_COLUMNS};
+use super::mvcc::{self, Snapshot};
 use super::record::{RReturnRecord, Record};
-use super::table::RTableHandle;
+use super::table::{RTable, RTableHandle};
 use super::utils::{decode_string_from_ints, encode_str_to_ints};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::iter::zip;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct RQuery {
     // pub table: RTable,
     pub handle: RTableHandle,
     merging: AtomicBool,
+    /// Who the writes belong to, they stay invisible to snapshots until the writer commits.
+    /// Outside of a transaction every write picks a new one with the table locked.
+    writer: i64,
+    /// Whether `writer` is a transaction, otherwise each write commits right away
+    in_transaction: bool,
 }
 
 /// Use the projected vector to decide which columns to set to None
@@ -30,12 +38,86 @@ fn filter_projected(column_values: Vec<i64>, projected: Vec<i64>) -> Vec<Option<
         });
     }
 
-    return out;
+    out
+}
+
+// The tail records `update_many` has planned but not appended yet
+#[derive(Default)]
+struct TailBatch {
+    // Reserved columns and values of each tail record
+    tails: Vec<([i64; 4], Vec<i64>)>,
+    // For each updated primary key, its base record and the RID of its newest tail record
+    newest: HashMap<i64, (Record, i64)>,
+}
+
+impl TailBatch {
+    // Plan the tail record of an update that keeps the primary key, false if there is no record
+    fn add(&mut self, table: &mut RTable, primary_key: i64, columns: Vec<Option<i64>>) -> bool {
+        let rid_column = ReservedColumns::RID as usize;
+        let schema_encoding_column = ReservedColumns::SchemaEncoding as usize;
+
+        let (base, previous_rid, base_rid, mut values) =
+            if let Some((base, previous_rid)) = self.newest.get(&primary_key) {
+                // The record was already updated in this batch, so its newest version is planned
+                let previous = self
+                    .tails
+                    .iter_mut()
+                    .rev()
+                    .find(|(reserved, _)| reserved[rid_column] == *previous_rid)
+                    .unwrap();
+                previous.0[schema_encoding_column] = 1;
+
+                let base_rid = previous.0[ReservedColumns::BaseRID as usize];
+                (base.clone(), *previous_rid, base_rid, previous.1.clone())
+            } else {
+                let Some(&rid) = table.index.read().unwrap().get(primary_key) else {
+                    return false;
+                };
+                let Some(base) = table.page_directory.directory.get(&rid).cloned() else {
+                    return false;
+                };
+                let Some(row) = table.page_range.read(base.clone()) else {
+                    return false;
+                };
+
+                let base_rid = row[rid_column];
+                let base_indirection = row[ReservedColumns::Indirection as usize];
+
+                // Mark the version that is about to be superseded as updated, like `update`
+                let (address, mut values) = if base_rid == base_indirection {
+                    (&base.addresses[schema_encoding_column], row)
+                } else {
+                    let Some(tail) = table.page_directory.directory.get(&base_indirection) else {
+                        return false;
+                    };
+                    let Some(row) = table.page_range.read(tail.clone()) else {
+                        return false;
+                    };
+                    (&tail.addresses[schema_encoding_column], row)
+                };
+                address.page.overwrite(address.offset as usize, 1);
+
+                values.drain(0..NUM_RESERVED_COLUMNS as usize);
+                (base.clone(), base_indirection, base_rid, values)
+            };
+
+        for (value, column) in values.iter_mut().zip(&columns) {
+            if let Some(column) = column {
+                *value = *column;
+            }
+        }
+
+        let rid = table.num_records;
+        table.num_records += 1;
+
+        self.tails.push(([rid, 0, previous_rid, base_rid], values));
+        self.newest.insert(primary_key, (base, rid));
+
+        true
+    }
 }
 
-#[pymethods]
 impl RQuery {
-    #[new]
     pub fn new(handle: RTableHandle) -> Self {
         let binding = handle.table.clone();
         let mut t = binding.write().unwrap();
@@ -48,12 +130,221 @@ impl RQuery {
         RQuery {
             handle,
             merging: AtomicBool::new(false),
+            writer: mvcc::UNSTAMPED_WRITER,
+            in_transaction: false,
         }
     }
 
-    pub fn delete(&mut self, primary_key: i64) {
-        let mut table = self.handle.table.write().unwrap();
-        table.delete(primary_key);
+    // The writer of the write that is about to start, call it with the table locked
+    fn next_writer(&self) -> i64 {
+        if self.in_transaction {
+            self.writer
+        } else {
+            mvcc::autocommit_writer()
+        }
+    }
+
+    // Commit the writes made outside of a transaction, the table must not be locked
+    fn autocommit(&self) {
+        if !self.in_transaction {
+            self.handle.publish(self.writer);
+        }
+    }
+
+    // Merge first if the table had many updates, like every update does
+    fn count_updates(&self, table: &mut RTable, updates: i64) {
+        if table.num_records > 0
+            && table.updates_since_merge > 100000
+            && !self.merging.load(Ordering::Relaxed)
+        {
+            self.merging.store(true, Ordering::Relaxed);
+
+            table.merge();
+            table.updates_since_merge = 0;
+
+            self.merging.store(false, Ordering::Relaxed);
+        }
+
+        table.updates_since_merge += updates;
+    }
+
+    // The body of `update`, for callers that hold the write lock of the table
+    fn update_locked(
+        &self,
+        table: &mut RTable,
+        primary_key: i64,
+        columns: Vec<Option<i64>>,
+    ) -> bool {
+        // This functin expects an expact number of columns as table has
+        if columns.len() != table.num_columns {
+            return false;
+        }
+
+        let mut new_columns: Vec<i64>;
+
+        // Check if the record found by primary_key exists
+        let index = table.index.read().unwrap();
+        let Some(rid) = index.get(primary_key) else {
+            return false;
+        };
+
+        // do not allow primary key to be changed to an existing primary key
+        if let Some(new_primary_key) = columns[table.primary_key_column] {
+            if primary_key != new_primary_key && index.get(new_primary_key).is_some() {
+                return false;
+            }
+        }
+
+        // Get record by RID
+        let record = match table.page_directory.directory.get(rid).cloned() {
+            Some(r) => r,
+            None => return false,
+        };
+        drop(index);
+
+        let Some(result) = table.page_range.read(record.clone()) else {
+            return false;
+        };
+
+        let indirection_column = ReservedColumns::Indirection as usize;
+
+        // Get values from record for the 4 internal columns
+        let base_rid = result[ReservedColumns::RID as usize];
+        let base_schema_encoding = result[ReservedColumns::SchemaEncoding as usize];
+        let base_indirection_column = result[ReservedColumns::Indirection as usize];
+
+        // base record addresses
+        let addrs_base = &record.addresses;
+
+        if base_rid == base_indirection_column {
+            // first update
+            if base_schema_encoding == 0 {
+                let base_schema_encoding = &addrs_base[ReservedColumns::SchemaEncoding as usize];
+                base_schema_encoding
+                    .page
+                    .overwrite(base_schema_encoding.offset as usize, 1);
+            }
+
+            new_columns = result;
+        } else {
+            // second and subsequent updates
+            let Some(existing_tail_record) =
+                table.page_directory.directory.get(&base_indirection_column)
+            else {
+                return false;
+            };
+
+            {
+                let _tail_cont = &table.page_range.tail_container;
+                // update schema encoding of the tail to be 1 (since record has changed)
+                let addrs_existing = &existing_tail_record.addresses;
+                let schema_encoding = &addrs_existing[ReservedColumns::SchemaEncoding as usize];
+                schema_encoding
+                    .page
+                    .overwrite(schema_encoding.offset as usize, 1);
+            }
+
+            let Some(result) = table.page_range.read(existing_tail_record.clone()) else {
+                return false;
+            };
+
+            new_columns = result;
+        }
+        // drop(base_cont);
+
+        // Extract the new primary key (if provided)
+        let mut new_primary_key = primary_key;
+        if let Some(pk) = columns[table.primary_key_column] {
+            new_primary_key = pk;
+        }
+
+        // drop first 4 columns (rid, schema_encoding, indirection, base_rid)
+        new_columns.drain(0..NUM_RESERVED_COLUMNS as usize);
+
+        // overwrite columns values onto new_columns values (that unwrap successfully)
+        for i in 0..new_columns.len() {
+            if let Some(value) = columns[i] {
+                new_columns[i] = value;
+            }
+        }
+
+        let new_rid = table.num_records;
+
+        let new_rec = table.page_range.tail_container.insert_record(
+            new_rid,
+            base_indirection_column,
+            base_rid,
+            new_columns,
+        );
+
+        // update the page directory with the new record
+        table.page_directory.directory.insert(new_rid, new_rec);
+        table.versions.record_write(new_rid, self.writer);
+
+        // update the index with the new primary key, it keeps pointing at the base record
+        if new_primary_key != primary_key {
+            let mut index = table.index.write().unwrap();
+            index.index.remove(&primary_key);
+            index.index.insert(new_primary_key, base_rid);
+            drop(index);
+
+            // Older snapshots still find the record by the old key
+            table
+                .versions
+                .record_former_key(primary_key, record.clone(), self.writer);
+        }
+
+        // update the indirection column of the base record
+        let indirection = &addrs_base[indirection_column];
+        indirection
+            .page
+            .overwrite(indirection.offset as usize, new_rid);
+
+        table.num_records += 1;
+
+        true
+    }
+
+    // Append the tail records of an `update_many` batch and point their base records at them
+    fn append_tails(&self, table: &mut RTable, batch: &mut TailBatch) {
+        if batch.tails.is_empty() {
+            return;
+        }
+
+        let records = table
+            .page_range
+            .tail_container
+            .insert_records(std::mem::take(&mut batch.tails));
+
+        for record in records {
+            table.versions.record_write(record.rid, self.writer);
+            table.page_directory.directory.insert(record.rid, record);
+        }
+
+        let indirection_column = ReservedColumns::Indirection as usize;
+        for (_, (base, newest_rid)) in batch.newest.drain() {
+            let address = &base.addresses[indirection_column];
+            address.page.overwrite(address.offset as usize, newest_rid);
+        }
+    }
+}
+
+#[cfg_attr(feature = "python", pymethods)]
+impl RQuery {
+    #[cfg(feature = "python")]
+    #[new]
+    fn py_new(handle: RTableHandle) -> Self {
+        RQuery::new(handle)
+    }
+
+    pub fn delete(&mut self, primary_key: i64) -> bool {
+        let deleted = {
+            let mut table = self.handle.table.write().unwrap();
+            self.writer = self.next_writer();
+            table.delete(primary_key, self.writer)
+        };
+        self.autocommit();
+        deleted
     }
 
     pub fn insert(&mut self, values: Vec<i64>) -> bool {
@@ -77,10 +368,8 @@ impl RQuery {
         let v = self.select(index, 0, vec![1; width as usize]);
         let mut vals = vec![];
 
-        for x in &v.unwrap()[0].as_mut().unwrap().columns {
-            if let Some(d) = x {
-                vals.push(*d);
-            }
+        for d in v.unwrap()[0].as_mut().unwrap().columns.iter().flatten() {
+            vals.push(*d);
         }
 
         decode_string_from_ints(vals)
@@ -88,15 +377,21 @@ impl RQuery {
 
     fn internal_insert(&mut self, values: Vec<i64>) -> Option<Record> {
         let mut table = self.handle.table.write().unwrap();
+        self.writer = self.next_writer();
         // check if primary key already exists
         {
             let index = table.index.read().unwrap();
-            if index.get(values[table.primary_key_column]) != None {
+            if index.get(values[table.primary_key_column]).is_some() {
                 return None;
             }
         }
 
-        Some(table.write(values))
+        let rec = table.write(values);
+        table.versions.record_write(rec.rid, self.writer);
+        drop(table);
+
+        self.autocommit();
+        Some(rec)
     }
 
     pub fn select(
@@ -109,20 +404,17 @@ impl RQuery {
 
         let mut out = vec![];
 
-        match ret {
-            Some(rows) => {
-                for row in rows {
-                    let mut a = row;
-                    // Remove the first 4 columns that are used only internally
-                    a.drain(0..4);
-                    // Return the columns encased in the RReturnRecord struct
-                    out.push(Some(RReturnRecord { columns: a }))
-                }
+        if let Some(rows) = ret {
+            for row in rows {
+                let mut a = row;
+                // Remove the first 4 columns that are used only internally
+                a.drain(0..4);
+                // Return the columns encased in the RReturnRecord struct
+                out.push(Some(RReturnRecord { columns: a }))
             }
-            None => {}
         }
 
-        return Some(out);
+        Some(out)
     }
 
     /// Formerly just `select` does a select on the database
@@ -138,11 +430,9 @@ impl RQuery {
 
         // Case 1: Searching on the primary key column
         if search_key_index == table.primary_key_column as i64 {
-            if let Some(ret) = table.read(search_key) {
-                return Some(vec![filter_projected(ret, projected_columns_index)]);
-            } else {
-                return None;
-            }
+            table
+                .read(search_key)
+                .map(|ret| vec![filter_projected(ret, projected_columns_index)])
         }
         // Case 2: Searching on a non-primary column
         else {
@@ -159,9 +449,9 @@ impl RQuery {
                             ));
                         }
                     }
-                    return Some(results);
+                    Some(results)
                 } else {
-                    return Some(vec![]); // No records match
+                    Some(vec![]) // No records match
                 }
             }
             // Otherwise, do a full scan
@@ -179,7 +469,7 @@ impl RQuery {
                         }
                     }
                 }
-                return Some(results);
+                Some(results)
             }
         }
     }
@@ -193,9 +483,7 @@ impl RQuery {
         relative_version: i64,
     ) -> Option<Vec<Option<i64>>> {
         let table = self.handle.table.read().unwrap();
-        let Some(ret) = table.read_relative(primary_key, relative_version) else {
-            return None;
-        };
+        let ret = table.read_relative(primary_key, relative_version)?;
 
         Some(filter_projected(ret, projected_columns_index))
     }
@@ -224,153 +512,87 @@ impl RQuery {
 
     pub fn update(&mut self, primary_key: i64, columns: Vec<Option<i64>>) -> bool {
         let mut table = self.handle.table.write().unwrap();
+        self.writer = self.next_writer();
+        self.count_updates(&mut table, 1);
+        let updated = self.update_locked(&mut table, primary_key, columns);
+        drop(table);
 
-        {
-            if table.num_records > 0
-                && table.updates_since_merge > 100000
-                && !self.merging.load(Ordering::Relaxed)
-            {
-                self.merging.store(true, Ordering::Relaxed);
-
-                table.merge();
-                table.updates_since_merge = 0;
-
-                self.merging.store(false, Ordering::Relaxed);
-            }
-
-            table.updates_since_merge += 1;
-        }
+        self.autocommit();
+        updated
+    }
 
-        // This functin expects an expact number of columns as table has
-        if columns.len() != table.num_columns {
-            return false;
-        }
+    /// Insert every row under one lock of the table, returns which rows went in
+    ///
+    /// A row fails like in `insert` when its primary key is taken, which includes by an earlier
+    /// row of the same call, and when it does not have a value for every column.
+    pub fn insert_many(&mut self, rows: Vec<Vec<i64>>) -> Vec<bool> {
+        let mut table = self.handle.table.write().unwrap();
+        self.writer = self.next_writer();
+        let primary_key_column = table.primary_key_column;
+        let num_columns = table.num_columns;
 
-        let mut new_columns: Vec<i64>;
+        let mut inserted = Vec::with_capacity(rows.len());
+        let mut accepted = Vec::new();
+        {
+            let index = table.index.read().unwrap();
+            let mut keys = HashSet::new();
 
-        // Check if the record found by primary_key exists
-        let index = table.index.read().unwrap();
-        let Some(rid) = index.get(primary_key) else {
-            return false;
-        };
+            for row in rows {
+                let ok = row.len() == num_columns
+                    && index.get(row[primary_key_column]).is_none()
+                    && keys.insert(row[primary_key_column]);
 
-        // do not allow primary key to be changed to an existing primary key
-        if let Some(new_primary_key) = columns[table.primary_key_column as usize] {
-            if primary_key != new_primary_key && index.get(new_primary_key) != None {
-                return false;
+                inserted.push(ok);
+                if ok {
+                    accepted.push(row);
+                }
             }
         }
 
-        // Get record by RID
-        let record = match table.page_directory.directory.get(&rid).cloned() {
-            Some(r) => r,
-            None => return false,
-        };
-        drop(index);
+        let records = table.write_many(accepted);
+        for record in &records {
+            table.versions.record_write(record.rid, self.writer);
+        }
+        drop(table);
 
-        let Some(result) = table.page_range.read(record.clone()) else {
-            return false;
-        };
+        self.autocommit();
+        inserted
+    }
 
-        let indirection_column = ReservedColumns::Indirection as usize;
+    /// Apply every `(primary_key, columns)` update under one lock of the table, returns which
+    /// ones were applied
+    ///
+    /// An update fails like in `update`. The tail records are appended together, except for
+    /// updates that change a primary key, which are applied one by one in their turn.
+    pub fn update_many(&mut self, updates: Vec<(i64, Vec<Option<i64>>)>) -> Vec<bool> {
+        let mut table = self.handle.table.write().unwrap();
+        self.writer = self.next_writer();
+        self.count_updates(&mut table, updates.len() as i64);
 
-        // Get values from record for the 4 internal columns
-        let base_rid = result[ReservedColumns::RID as usize];
-        let base_schema_encoding = result[ReservedColumns::SchemaEncoding as usize];
-        let base_indirection_column = result[ReservedColumns::Indirection as usize];
+        let mut updated = Vec::with_capacity(updates.len());
+        let mut batch = TailBatch::default();
 
-        // base record addresses
-        let addrs_base = record.addresses.lock().unwrap();
-
-        if base_rid == base_indirection_column {
-            // first update
-            if base_schema_encoding == 0 {
-                let mut base_schema_encoding = addrs_base[ReservedColumns::SchemaEncoding as usize]
-                    .page
-                    .lock()
-                    .unwrap();
-                base_schema_encoding.overwrite(
-                    addrs_base[ReservedColumns::SchemaEncoding as usize].offset as usize,
-                    1,
-                );
+        for (primary_key, columns) in updates {
+            if columns.len() != table.num_columns {
+                updated.push(false);
+                continue;
             }
 
-            new_columns = result;
-        } else {
-            // second and subsequent updates
-            let Some(existing_tail_record) =
-                table.page_directory.directory.get(&base_indirection_column)
-            else {
-                return false;
-            };
-
-            {
-                let _tail_cont = &table.page_range.tail_container;
-                // update schema encoding of the tail to be 1 (since record has changed)
-                let addrs_existing = existing_tail_record.addresses.lock().unwrap();
-                let mut schema_encoding = addrs_existing[ReservedColumns::SchemaEncoding as usize]
-                    .page
-                    .lock()
-                    .unwrap();
-                schema_encoding.overwrite(
-                    addrs_existing[ReservedColumns::SchemaEncoding as usize].offset as usize,
-                    1,
-                );
+            if columns[table.primary_key_column].is_some_and(|key| key != primary_key) {
+                // Later updates have to find the record under its new key
+                self.append_tails(&mut table, &mut batch);
+                updated.push(self.update_locked(&mut table, primary_key, columns));
+                continue;
             }
 
-            let Some(result) = table.page_range.read(existing_tail_record.clone()) else {
-                return false;
-            };
-
-            new_columns = result;
-        }
-        // drop(base_cont);
-
-        // Extract the new primary key (if provided)
-        let mut new_primary_key = primary_key;
-        if let Some(pk) = columns[table.primary_key_column] {
-            new_primary_key = pk;
+            updated.push(batch.add(&mut table, primary_key, columns));
         }
 
-        // drop first 4 columns (rid, schema_encoding, indirection, base_rid)
-        new_columns.drain(0..NUM_RESERVED_COLUMNS as usize);
+        self.append_tails(&mut table, &mut batch);
+        drop(table);
 
-        // overwrite columns values onto new_columns values (that unwrap successfully)
-        for i in 0..new_columns.len() {
-            if let Some(value) = columns[i] {
-                new_columns[i] = value;
-            }
-        }
-
-        let new_rid = table.num_records;
-
-        let new_rec = table.page_range.tail_container.insert_record(
-            new_rid,
-            base_indirection_column,
-            base_rid,
-            new_columns,
-        );
-
-        // update the page directory with the new record
-        table.page_directory.directory.insert(new_rid, new_rec);
-
-        // update the index with the new primary key
-        if new_primary_key != primary_key {
-            let mut index = table.index.write().unwrap();
-            index.index.remove(&primary_key);
-            index.index.insert(new_primary_key, new_rid);
-        }
-
-        // update the indirection column of the base record
-        let mut indirection_page = addrs_base[indirection_column as usize].page.lock().unwrap();
-        indirection_page.overwrite(
-            addrs_base[indirection_column as usize].offset as usize,
-            new_rid,
-        );
-
-        table.num_records += 1;
-
-        return true;
+        self.autocommit();
+        updated
     }
 
     pub fn sum(&mut self, start_primary_key: i64, end_primary_key: i64, col_index: i64) -> i64 {
@@ -378,7 +600,7 @@ impl RQuery {
         table.sum(start_primary_key, end_primary_key, col_index)
     }
 
-    fn sum_version(
+    pub fn sum_version(
         &mut self,
         start_primary_key: i64,
         end_primary_key: i64,
@@ -413,7 +635,53 @@ impl RQuery {
             return self.update(primary_key, to_update);
         }
 
-        return false;
+        false
+    }
+}
+
+impl RQuery {
+    /// A query whose writes belong to a transaction
+    pub fn for_transaction(handle: RTableHandle, transaction_id: i64) -> Self {
+        let mut query = RQuery::new(handle);
+        query.writer = transaction_id;
+        query.in_transaction = true;
+        query
+    }
+
+    /// `select_version` on the primary key, reading only versions the snapshot can see
+    pub fn select_at(
+        &self,
+        primary_key: i64,
+        projected_columns_index: Vec<i64>,
+        relative_version: i64,
+        snapshot: &Snapshot,
+    ) -> Option<Vec<Option<RReturnRecord>>> {
+        let table = self.handle.table.read().unwrap();
+        let ret = table.read_relative_at(primary_key, relative_version, snapshot)?;
+
+        let mut columns = filter_projected(ret, projected_columns_index);
+        columns.drain(0..NUM_RESERVED_COLUMNS as usize);
+
+        Some(vec![Some(RReturnRecord { columns })])
+    }
+
+    /// `sum_version`, reading only versions the snapshot can see, None if the sum overflows
+    pub fn sum_at(
+        &self,
+        start_primary_key: i64,
+        end_primary_key: i64,
+        col_index: i64,
+        relative_version: i64,
+        snapshot: &Snapshot,
+    ) -> Option<i64> {
+        let table = self.handle.table.read().unwrap();
+        table.sum_at(
+            start_primary_key,
+            end_primary_key,
+            col_index,
+            relative_version,
+            snapshot,
+        )
     }
 }
 
@@ -425,7 +693,7 @@ mod tests {
     #[test]
     fn test_internal_insert_and_read_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         q.internal_insert(vec![1, 2, 3]);
@@ -449,7 +717,7 @@ mod tests {
     #[test]
     fn increment_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Counts"), 3, 0);
+        let table_ref = db.create_table(String::from("Counts"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         q.internal_insert(vec![1, 2, 3]); // Insert [Primary Key: 1, Col1: 2, Col2: 3]
@@ -508,7 +776,7 @@ mod tests {
     #[test]
     fn increment_test() {
         let mut db = RDatabase::new();
-        let t = db.create_table(String::from("Counts"), 3, 0);
+        let t = db.create_table(String::from("Counts"), 3, 0).unwrap();
         let mut q = RQuery::new(t);
 
         q.internal_insert(vec![1, 2, 3]); // Insert [Primary Key: 1, Col1: 2, Col2: 3]
@@ -533,7 +801,7 @@ mod tests {
     #[test]
     fn test_update_read_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         q.insert(vec![1, 2, 3]);
@@ -575,7 +843,7 @@ mod tests {
     // #[should_panic(expected = "Primary key cannot be changed")]
     // fn test_update_primary_key_should_panic() {
     //     let mut db = RDatabase::new();
-    //     let t = db.create_table(String::from("Grades"), 3, 0);
+    //     let t = db.create_table(String::from("Grades"), 3, 0).unwrap();
     //     let mut q = RQuery::new(t);
 
     //     q.internal_insert(vec![1, 2, 3]);
@@ -587,7 +855,7 @@ mod tests {
     #[test]
     fn test_multiple_updates() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         q.internal_insert(vec![1, 2, 3]);
@@ -614,7 +882,7 @@ mod tests {
     #[test]
     fn test_delete_and_select() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         q.internal_insert(vec![1, 2, 3]);
@@ -626,7 +894,7 @@ mod tests {
     #[test]
     fn test_select_version() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         // Insert initial record
@@ -698,7 +966,7 @@ mod tests {
     #[test]
     fn test_internal_insert_existing_primary_key() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Grades".to_string(), 3, 0);
+        let table_ref = db.create_table("Grades".to_string(), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         q.internal_insert(vec![1, 2, 3]);
@@ -726,7 +994,7 @@ mod tests {
     #[test]
     fn merge_one_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref.clone());
 
         // Insert initial record
@@ -757,7 +1025,7 @@ mod tests {
     #[test]
     fn merge_two_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Grades2"), 3, 0);
+        let table_ref = db.create_table(String::from("Grades2"), 3, 0).unwrap();
         let mut q = RQuery::new(table_ref.clone());
 
         // Insert initial record
@@ -789,7 +1057,7 @@ mod tests {
         let width = 10;
 
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Names"), width, 0);
+        let table_ref = db.create_table(String::from("Names"), width, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         let name = "Jake";
@@ -797,10 +1065,8 @@ mod tests {
 
         let v = q.select(0, 0, vec![1; width as usize]);
         let mut vals = vec![];
-        for x in &v.unwrap()[0].as_mut().unwrap().columns {
-            if let Some(d) = x {
-                vals.push(*d);
-            }
+        for d in v.unwrap()[0].as_mut().unwrap().columns.iter().flatten() {
+            vals.push(*d);
         }
 
         assert_eq!(decode_string_from_ints(vals.clone()), "Jake");
@@ -812,7 +1078,7 @@ mod tests {
         let width = 10;
 
         let mut db = RDatabase::new();
-        let table_ref = db.create_table(String::from("Names"), width, 0);
+        let table_ref = db.create_table(String::from("Names"), width, 0).unwrap();
         let mut q = RQuery::new(table_ref);
 
         let name = "Jake";
@@ -825,12 +1091,104 @@ mod tests {
         assert_ne!(str, "JakeR");
     }
 
+    #[test]
+    fn insert_many_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table(String::from("Grades"), 3, 0).unwrap();
+        let mut q = RQuery::new(table_ref);
+
+        q.insert(vec![1, 2, 3]);
+
+        // A taken key, a key repeated in the batch and a short row fail
+        let inserted = q.insert_many(vec![
+            vec![1, 0, 0],
+            vec![2, 4, 6],
+            vec![3, 6, 9],
+            vec![2, 0, 0],
+            vec![4, 8],
+        ]);
+        assert_eq!(inserted, vec![false, true, true, false, false]);
+
+        let vals = q.internal_select_version(2, 0, vec![1, 1, 1], 0);
+        assert_eq!(
+            vals.unwrap(),
+            vec![
+                Some(1),
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(2),
+                Some(4),
+                Some(6)
+            ]
+        );
+        assert_eq!(q.sum(0, 10, 1), 12);
+
+        // Records after the batch get the next RIDs
+        assert!(q.insert(vec![5, 10, 15]));
+        assert_eq!(
+            q.internal_select_version(5, 0, vec![1, 0, 0], 0).unwrap()[0],
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn update_many_test() {
+        let mut db = RDatabase::new();
+        let one_by_one = RQuery::new(db.create_table(String::from("One"), 3, 0).unwrap());
+        let batched = RQuery::new(db.create_table(String::from("Many"), 3, 0).unwrap());
+
+        let updates = vec![
+            (1, vec![None, Some(10), None]),
+            (2, vec![None, None, Some(20)]),
+            (1, vec![None, None, Some(11)]),
+            (9, vec![None, Some(0), None]),
+            (2, vec![None, Some(5)]),
+            (1, vec![Some(1), Some(12), None]),
+            (3, vec![None, Some(30), Some(31)]),
+            // Updates after a primary key change find the record under its new key
+            (1, vec![Some(7), None, None]),
+            (1, vec![None, Some(0), None]),
+            (7, vec![None, None, Some(8)]),
+            (2, vec![Some(7), None, None]),
+        ];
+
+        let mut results = Vec::new();
+        for mut q in [one_by_one, batched] {
+            q.insert_many(vec![vec![1, 2, 3], vec![2, 4, 6], vec![3, 6, 9]]);
+            q.update(3, vec![None, Some(7), None]);
+
+            let updated = if results.is_empty() {
+                updates
+                    .iter()
+                    .map(|(key, columns)| q.update(*key, columns.clone()))
+                    .collect()
+            } else {
+                q.update_many(updates.clone())
+            };
+            assert_eq!(
+                updated,
+                vec![true, true, true, false, false, true, true, true, false, true, false]
+            );
+
+            // Every version of every record, with its reserved columns
+            let versions: Vec<_> = [1, 2, 3, 7]
+                .into_iter()
+                .flat_map(|key| (0..4).map(move |version| (key, version)))
+                .map(|(key, version)| q.internal_select_version(key, 0, vec![1, 1, 1], version))
+                .collect();
+            results.push(versions);
+        }
+
+        assert_eq!(results[0], results[1]);
+    }
+
     /* Seems like M2 test wants us to delete the record if primary key is changed
 
     #[test]
     fn test_update_existing_primary_key() {
         let mut db = RDatabase::new();
-        let t = db.create_table(String::from("Grades"), 3, 0);
+        let t = db.create_table(String::from("Grades"), 3, 0).unwrap();
         let mut q = RQuery::new(t);
 
         q.internal_insert(vec![1, 2, 3]);
@@ -1,21 +1,22 @@
-use super::page::PhysicalPage;
+use super::page::PageHandle;
 use crate::container::{ReservedColumns, NUM_RESERVED_COLUMNS};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::Arc;
 
 type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
 
 /// This is the Python object that we return in `select` and `select_version`
 /// Making this in Rust improved speed by 30%
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all))]
 #[derive(Debug, Clone)]
 pub struct RReturnRecord {
-    #[pyo3(get)]
     pub columns: Vec<Option<i64>>,
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl RReturnRecord {
     fn __str__(&self) -> String {
@@ -28,9 +29,9 @@ impl RReturnRecord {
 }
 
 #[derive(Debug, Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct RecordAddress {
-    pub page: Arc<Mutex<PhysicalPage>>,
+    pub page: PageHandle,
     pub offset: i64,
 }
 
@@ -53,7 +54,7 @@ pub struct RecordAddressMetadata {
 }
 
 impl RecordAddressMetadata {
-    pub fn load_state(&self, phys_page_ref: Arc<Mutex<PhysicalPage>>) -> RecordAddress {
+    pub fn load_state(&self, phys_page_ref: PageHandle) -> RecordAddress {
         RecordAddress {
             page: phys_page_ref,
             offset: self.offset,
@@ -65,128 +66,103 @@ impl RecordAddressMetadata {
 pub struct RecordMetadata {
     pub rid: i64,
 
+    /// Whether the record is in the tail container, its addresses point into those pages then
+    pub tail: bool,
+
     pub addresses: Vec<RecordAddressMetadata>,
 }
 
 impl RecordMetadata {
     pub fn load_state(
         &self,
-        base_pages: &RedoxQLHashMap<i64, Arc<Mutex<PhysicalPage>>>,
-        _tail_pages: &RedoxQLHashMap<i64, Arc<Mutex<PhysicalPage>>>,
+        base_pages: &RedoxQLHashMap<i64, PageHandle>,
+        tail_pages: &RedoxQLHashMap<i64, PageHandle>,
     ) -> Record {
+        let pages = if self.tail { tail_pages } else { base_pages };
         let mut rec_addrs = Vec::new();
 
         // Create the RecordAddresses from the metadata
         // This eventually gets propagated through load_state
         // calls all the way to PageDirectory
-        let mut index = 0;
-        for rec_addr in &self.addresses {
-            let p = base_pages.get(&index).expect("Should be a page here.");
+        for (index, rec_addr) in self.addresses.iter().enumerate() {
+            let p = pages.get(&(index as i64)).expect("Should be a page here.");
             rec_addrs.push(rec_addr.load_state(p.clone()));
-            index += 1;
         }
 
         Record {
             rid: self.rid,
-            addresses: Arc::new(Mutex::new(rec_addrs)),
-            lock: Arc::new(RwLock::new(RecordLock::default())),
+            addresses: Arc::new(rec_addrs),
         }
     }
 }
 
 #[derive(Debug, Clone, Default)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct Record {
     /// Each Record has a RID and we can retrieve the Record via RTable.page_directory
-    #[pyo3(get)]
     pub rid: i64,
     /// The Record keeps a Vector of the RecordAddress, which allow us to actually call
     /// RecordAddress.page.read() to get the value stored at the page using the offset
-    pub addresses: Arc<Mutex<Vec<RecordAddress>>>,
-
-    pub lock: Arc<RwLock<RecordLock>>,
-}
-
-#[derive(Debug, Clone, Default)]
-pub struct RecordLock {
-    pub reader_count: i64,
-    pub writer_count: i64,
+    ///
+    /// The addresses never change once a record is written, so they are shared without a lock
+    pub addresses: Arc<Vec<RecordAddress>>,
 }
 
 impl Record {
-    pub fn get_metadata(&self) -> RecordMetadata {
+    /// `tail` says whether the record was written to the tail container
+    pub fn get_metadata(&self, tail: bool) -> RecordMetadata {
         let mut rm = RecordMetadata {
             rid: self.rid,
+            tail,
             addresses: Vec::new(),
         };
 
-        let m = self.addresses.lock().unwrap();
-        let addrs = m.iter();
-
-        for addr in addrs {
+        for addr in self.addresses.iter() {
             // Get the metadata for each RecordAddress
             rm.addresses.push(addr.get_metadata());
         }
 
-        return rm;
+        rm
     }
 
-    pub fn attempt_obtain_read(&self) -> bool {
-        let mut a = self.lock.write().unwrap();
-
-        if a.writer_count != 0 {
-            return false;
-        }
-
-        a.reader_count += 1;
-
-        true
+    pub fn rid(&self) -> i64 {
+        self.rid
     }
 
-    pub fn attempt_obtain_write(&self) -> bool {
-        let mut a = self.lock.write().unwrap();
-
-        if a.writer_count != 0 || a.reader_count != 0 {
-            return false;
-        }
-
-        a.writer_count += 1;
-
-        true
+    pub fn schema_encoding(&self) -> RecordAddress {
+        self.addresses[ReservedColumns::SchemaEncoding as usize].clone()
     }
 
-    pub fn release_read_lock(&self) {
-        let mut a = self.lock.write().unwrap();
-        a.reader_count -= 1;
-
-        if a.reader_count < 0 {
-            panic!("write_count less than 0, rid {}", self.rid)
-        }
+    pub fn indirection(&self) -> RecordAddress {
+        self.addresses[ReservedColumns::Indirection as usize].clone()
     }
 
-    pub fn release_write_lock(&self) {
-        let mut a = self.lock.write().unwrap();
-        a.writer_count -= 1;
+    pub fn base_rid(&self) -> RecordAddress {
+        self.addresses[ReservedColumns::BaseRID as usize].clone()
+    }
 
-        if a.writer_count < 0 {
-            panic!("write_count less than 0, rid {}", self.rid)
-        }
+    pub fn columns(&self) -> Vec<RecordAddress> {
+        self.addresses[(NUM_RESERVED_COLUMNS as usize)..].to_vec()
     }
 }
 
+#[cfg(feature = "python")]
 #[pymethods]
 impl Record {
+    #[getter(rid)]
+    fn py_rid(&self) -> i64 {
+        self.rid
+    }
+
     fn __str__(&self) -> String {
         // Print the Addresses from RecordAddress
-        let addresses = self.addresses.lock().unwrap();
         let mut addrs = Vec::<String>::new();
-        let addr_vec: Vec<RecordAddress> = addresses.clone();
 
-        for addr in addr_vec {
-            let page = addr.page;
+        for addr in self.addresses.iter() {
+            let page = &addr.page;
             addrs.push(format!(
                 "0x{:?} + {}",
-                &page as *const Arc<Mutex<PhysicalPage>> as usize, addr.offset
+                page as *const PageHandle as usize, addr.offset
             ));
         }
 
@@ -196,29 +172,4 @@ impl Record {
     fn __repr__(&self) -> String {
         self.__str__()
     }
-
-    pub fn rid(&self) -> i64 {
-        self.rid
-    }
-
-    pub fn schema_encoding(&self) -> RecordAddress {
-        let addresses = self.addresses.lock().unwrap();
-        addresses[ReservedColumns::SchemaEncoding as usize].clone()
-    }
-
-    pub fn indirection(&self) -> RecordAddress {
-        let addresses = self.addresses.lock().unwrap();
-        addresses[ReservedColumns::Indirection as usize].clone()
-    }
-
-    pub fn base_rid(&self) -> RecordAddress {
-        let addresses = self.addresses.lock().unwrap();
-        addresses[ReservedColumns::BaseRID as usize].clone()
-    }
-
-    pub fn columns(&self) -> Vec<RecordAddress> {
-        let addresses = self.addresses.lock().unwrap();
-
-        addresses[(NUM_RESERVED_COLUMNS as usize)..].to_vec()
-    }
 }
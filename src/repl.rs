@@ -0,0 +1,434 @@
+use super::container::ReservedColumns;
+use super::container::NUM_RESERVED_COLUMNS;
+use super::database::{DatabaseError, RDatabase};
+use super::query::RQuery;
+use super::table::RTableHandle;
+use std::fmt;
+
+/// How many versions `versions` shows when it is not told
+const DEFAULT_VERSIONS: i64 = 10;
+
+const HELP: &str = "\
+Commands:
+  tables                                 List the tables
+  create <table> <columns> <key column>  Create a table
+  drop <table>                           Drop a table and delete its files
+  insert <table> <value>...              Insert a record, one value per column
+  select <table> <key> [column]          Records whose column (the key column by default) is key
+  update <table> <key> <value|_>...      Update a record, _ keeps the old value of a column
+  delete <table> <key>                   Delete a record
+  sum <table> <start> <end> <column>     Sum a column over a range of keys
+  versions <table> <key> [count]         The newest versions of a record, newest first
+  merge <table>                          Merge the tail records into the base records
+  checkpoint                             Save the database without closing it
+  stats <table>                          Record counts, indexes and size on disk of a table
+  help                                   Show this help
+  exit                                   Save the database and quit";
+
+/// Why a command could not run
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownCommand(String),
+    /// The command got the wrong arguments, holds how to use it
+    Usage(&'static str),
+    NotANumber(String),
+    Database(DatabaseError),
+    /// The query ran but did nothing, e.g. an insert of a key that exists
+    Failed(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(name) => {
+                write!(f, "Unknown command {}, try help", name)
+            }
+            CommandError::Usage(usage) => write!(f, "Usage: {}", usage),
+            CommandError::NotANumber(arg) => write!(f, "{} is not a number", arg),
+            CommandError::Database(err) => write!(f, "{}", err),
+            CommandError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<DatabaseError> for CommandError {
+    fn from(err: DatabaseError) -> Self {
+        CommandError::Database(err)
+    }
+}
+
+/// What the REPL should do after a command
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reply {
+    Print(String),
+    Exit,
+}
+
+/// The commands of the `redoxql` binary, run on one database
+pub struct Repl {
+    db: RDatabase,
+}
+
+fn number(arg: &str) -> Result<i64, CommandError> {
+    arg.parse()
+        .map_err(|_| CommandError::NotANumber(arg.to_string()))
+}
+
+fn format_row(row: &[Option<i64>]) -> String {
+    row.iter()
+        .map(|v| v.map_or(String::from("_"), |v| v.to_string()))
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+impl Repl {
+    /// Open the database at `path`, or one that only lives in memory without a path
+    pub fn open(path: Option<String>) -> Result<Repl, DatabaseError> {
+        let mut db = RDatabase::new();
+
+        if let Some(path) = path {
+            db.open(path)?;
+        }
+
+        Ok(Repl { db })
+    }
+
+    /// Save the database, like `checkpoint`
+    pub fn close(&mut self) -> Result<(), DatabaseError> {
+        self.db.close()
+    }
+
+    /// Run one line of input
+    pub fn execute(&mut self, line: &str) -> Result<Reply, CommandError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        let Some((&command, args)) = words.split_first() else {
+            return Ok(Reply::Print(String::new()));
+        };
+
+        let text = match command {
+            "help" => String::from(HELP),
+            "exit" | "quit" => {
+                self.close()?;
+                return Ok(Reply::Exit);
+            }
+            "tables" => self.db.list_tables().join("\n"),
+            "create" => self.create(args)?,
+            "drop" => {
+                let [name] = args else {
+                    return Err(CommandError::Usage("drop <table>"));
+                };
+                self.db.drop_table(name.to_string())?;
+                format!("Dropped {}", name)
+            }
+            "insert" => self.insert(args)?,
+            "select" => self.select(args)?,
+            "update" => self.update(args)?,
+            "delete" => self.delete(args)?,
+            "sum" => self.sum(args)?,
+            "versions" => self.versions(args)?,
+            "merge" => self.merge(args)?,
+            "checkpoint" => {
+                self.close()?;
+                String::from("Saved")
+            }
+            "stats" => self.stats(args)?,
+            _ => return Err(CommandError::UnknownCommand(command.to_string())),
+        };
+
+        Ok(Reply::Print(text))
+    }
+
+    fn table(&self, name: &str) -> Result<RTableHandle, CommandError> {
+        Ok(self.db.get_table(name.to_string())?)
+    }
+
+    fn create(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let [name, num_columns, primary_key_column] = args else {
+            return Err(CommandError::Usage("create <table> <columns> <key column>"));
+        };
+
+        self.db.create_table(
+            name.to_string(),
+            number(num_columns)?,
+            number(primary_key_column)?,
+        )?;
+        Ok(format!("Created {}", name))
+    }
+
+    fn insert(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let Some((name, values)) = args.split_first() else {
+            return Err(CommandError::Usage("insert <table> <value>..."));
+        };
+        let table = self.table(name)?;
+
+        let values = values
+            .iter()
+            .map(|v| number(v))
+            .collect::<Result<Vec<i64>, _>>()?;
+        if values.len() != table.get_num_columns() {
+            return Err(CommandError::Failed(format!(
+                "{} has {} columns",
+                name,
+                table.get_num_columns()
+            )));
+        }
+
+        let key = values[table.get_primary_key_column()];
+        if !RQuery::new(table).insert(values) {
+            return Err(CommandError::Failed(format!(
+                "A record with key {} already exists",
+                key
+            )));
+        }
+
+        Ok(String::from("Inserted 1 record"))
+    }
+
+    fn select(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let usage = CommandError::Usage("select <table> <key> [column]");
+        let (name, key, column) = match args {
+            [name, key] => (name, number(key)?, None),
+            [name, key, column] => (name, number(key)?, Some(number(column)?)),
+            _ => return Err(usage),
+        };
+        let table = self.table(name)?;
+
+        let column = column.unwrap_or(table.get_primary_key_column() as i64);
+        if !(0..table.get_num_columns() as i64).contains(&column) {
+            return Err(CommandError::Failed(format!(
+                "{} has no column {}",
+                name, column
+            )));
+        }
+
+        let projected = vec![1; table.get_num_columns()];
+        let records = RQuery::new(table)
+            .select(key, column, projected)
+            .unwrap_or_default();
+
+        let rows: Vec<String> = records
+            .into_iter()
+            .flatten()
+            .map(|r| format_row(&r.columns))
+            .collect();
+        if rows.is_empty() {
+            return Ok(String::from("No records"));
+        }
+
+        Ok(rows.join("\n"))
+    }
+
+    fn update(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let [name, key, values @ ..] = args else {
+            return Err(CommandError::Usage("update <table> <key> <value|_>..."));
+        };
+        let table = self.table(name)?;
+        let key = number(key)?;
+
+        let columns = values
+            .iter()
+            .map(|v| {
+                if *v == "_" {
+                    Ok(None)
+                } else {
+                    number(v).map(Some)
+                }
+            })
+            .collect::<Result<Vec<Option<i64>>, _>>()?;
+        if columns.len() != table.get_num_columns() {
+            return Err(CommandError::Failed(format!(
+                "{} has {} columns",
+                name,
+                table.get_num_columns()
+            )));
+        }
+
+        if table.read(key).is_none() {
+            return Err(CommandError::Failed(format!("No record with key {}", key)));
+        }
+        if !RQuery::new(table).update(key, columns) {
+            return Err(CommandError::Failed(format!(
+                "Could not update {}, the new key is taken",
+                key
+            )));
+        }
+
+        Ok(String::from("Updated 1 record"))
+    }
+
+    fn delete(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let [name, key] = args else {
+            return Err(CommandError::Usage("delete <table> <key>"));
+        };
+        let table = self.table(name)?;
+        let key = number(key)?;
+
+        if table.read(key).is_none() {
+            return Err(CommandError::Failed(format!("No record with key {}", key)));
+        }
+        RQuery::new(table).delete(key);
+
+        Ok(String::from("Deleted 1 record"))
+    }
+
+    fn sum(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let [name, start, end, column] = args else {
+            return Err(CommandError::Usage("sum <table> <start> <end> <column>"));
+        };
+        let table = self.table(name)?;
+        let column = number(column)?;
+
+        if !(0..table.get_num_columns() as i64).contains(&column) {
+            return Err(CommandError::Failed(format!(
+                "{} has no column {}",
+                name, column
+            )));
+        }
+
+        let sum = RQuery::new(table).sum(number(start)?, number(end)?, column);
+        Ok(sum.to_string())
+    }
+
+    fn versions(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let (name, key, count) = match args {
+            [name, key] => (name, number(key)?, DEFAULT_VERSIONS),
+            [name, key, count] => (name, number(key)?, number(count)?),
+            _ => return Err(CommandError::Usage("versions <table> <key> [count]")),
+        };
+        let table = self.table(name)?;
+        let table = table.table.read().unwrap();
+
+        let Some(base) = table.read_base(key) else {
+            return Err(CommandError::Failed(format!("No record with key {}", key)));
+        };
+        let base_rid = base[ReservedColumns::RID as usize];
+
+        let mut lines = Vec::new();
+        for version in 0..count.max(1) {
+            let Some(row) = table.read_relative(key, -version) else {
+                break;
+            };
+
+            let columns: Vec<Option<i64>> = row[NUM_RESERVED_COLUMNS as usize..]
+                .iter()
+                .map(|&v| Some(v))
+                .collect();
+            lines.push(format!("{:>4}: {}", -version, format_row(&columns)));
+
+            // Older versions than the base record all read as the base record
+            if row[ReservedColumns::RID as usize] == base_rid {
+                break;
+            }
+        }
+
+        Ok(lines.join("\n"))
+    }
+
+    fn merge(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let [name] = args else {
+            return Err(CommandError::Usage("merge <table>"));
+        };
+        let table = self.table(name)?;
+
+        let mut t = table.table.write().unwrap();
+        t.merge();
+        t.updates_since_merge = 0;
+
+        Ok(format!("Merged {}", name))
+    }
+
+    fn stats(&mut self, args: &[&str]) -> Result<String, CommandError> {
+        let [name] = args else {
+            return Err(CommandError::Usage("stats <table>"));
+        };
+        let d = self.db.describe_table(name.to_string())?;
+
+        Ok(format!(
+            "name: {}\nid: {}\ncolumns: {}\nkey column: {}\nrecords: {}\nbase records: {}\n\
+             tail records: {}\nindexed columns: {:?}\nsize on disk: {} bytes",
+            d.name,
+            d.table_id,
+            d.num_columns,
+            d.primary_key_column,
+            d.num_records,
+            d.num_base_records,
+            d.num_tail_records,
+            d.indexed_columns,
+            d.disk_size
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        match repl.execute(line) {
+            Ok(Reply::Print(text)) => text,
+            Ok(Reply::Exit) => panic!("{} should not exit", line),
+            Err(e) => panic!("{} failed: {}", line, e),
+        }
+    }
+
+    #[test]
+    fn repl_commands_test() {
+        let mut repl = Repl::open(None).unwrap();
+
+        run(&mut repl, "create Grades 3 0");
+        assert!(repl.execute("create Grades 3 0").is_err());
+        run(&mut repl, "insert Grades 1 90 80");
+        run(&mut repl, "insert Grades 2 70 60");
+        assert!(matches!(
+            repl.execute("insert Grades 1 0 0"),
+            Err(CommandError::Failed(_))
+        ));
+        run(&mut repl, "update Grades 1 _ 95 _");
+        run(&mut repl, "update Grades 1 _ 99 _");
+
+        assert_eq!(run(&mut repl, "select Grades 1"), "1 | 99 | 80");
+        assert_eq!(run(&mut repl, "sum Grades 1 2 1"), "169");
+        assert_eq!(
+            run(&mut repl, "versions Grades 1"),
+            "   0: 1 | 99 | 80\n  -1: 1 | 95 | 80\n  -2: 1 | 90 | 80"
+        );
+        assert_eq!(run(&mut repl, "versions Grades 1 1"), "   0: 1 | 99 | 80");
+
+        run(&mut repl, "merge Grades");
+        assert_eq!(run(&mut repl, "select Grades 1"), "1 | 99 | 80");
+
+        run(&mut repl, "delete Grades 2");
+        assert_eq!(run(&mut repl, "select Grades 2"), "No records");
+        assert!(run(&mut repl, "stats Grades").contains("records: 1\n"));
+        assert_eq!(run(&mut repl, "tables"), "Grades");
+
+        run(&mut repl, "drop Grades");
+        assert_eq!(run(&mut repl, "tables"), "");
+    }
+
+    #[test]
+    fn repl_errors_test() {
+        let mut repl = Repl::open(None).unwrap();
+
+        assert!(matches!(
+            repl.execute("frobnicate"),
+            Err(CommandError::UnknownCommand(_))
+        ));
+        assert!(matches!(
+            repl.execute("create Grades three 0"),
+            Err(CommandError::NotANumber(_))
+        ));
+        assert!(matches!(
+            repl.execute("create Grades 3 3"),
+            Err(CommandError::Database(DatabaseError::InvalidSchema { .. }))
+        ));
+        assert!(matches!(
+            repl.execute("select Missing 1"),
+            Err(CommandError::Database(DatabaseError::NoSuchTable(_)))
+        ));
+        assert!(matches!(repl.execute("   "), Ok(Reply::Print(text)) if text.is_empty()));
+    }
+}
@@ -0,0 +1,385 @@
+//! A TCP server that shares one database between processes
+//!
+//! The protocol is one JSON object per line each way. A request names its operation in `op`:
+//!
+//! ```text
+//! > {"op": "create_table", "name": "Grades", "num_columns": 3, "primary_key_column": 0}
+//! < {"ok": null}
+//! > {"op": "insert", "table": "Grades", "values": [1, 90, 80]}
+//! < {"ok": null}
+//! > {"op": "select", "table": "Grades", "key": 1, "column": 0, "projected": [1, 1, 1]}
+//! < {"ok": [[1, 90, 80]]}
+//! > {"op": "sum", "table": "Grades", "start": 0, "end": 10, "column": 1}
+//! < {"ok": 90}
+//! > {"op": "delete", "table": "Missing", "primary_key": 1}
+//! < {"error": "No table named Missing"}
+//! ```
+//!
+//! Every connection can have one transaction. Between `begin` and `commit` or `abort` its
+//! inserts, updates, deletes, increments, selects and sums run in that transaction and take
+//! locks like any other. A connection that closes in the middle of a transaction aborts it.
+//! `sql` statements always run on their own.
+
+use super::api::{Database, Error, Operation, QueryResult, SqlOutput, Transaction};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// One line a client sends
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// Rows of a SELECT, the result of SUM, how many records changed or null
+    Sql {
+        sql: String,
+    },
+    CreateTable {
+        name: String,
+        num_columns: usize,
+        primary_key_column: usize,
+    },
+    DropTable {
+        name: String,
+    },
+    ListTables,
+    Insert {
+        table: String,
+        values: Vec<i64>,
+    },
+    /// null in `columns` keeps the old value
+    Update {
+        table: String,
+        primary_key: i64,
+        columns: Vec<Option<i64>>,
+    },
+    Delete {
+        table: String,
+        primary_key: i64,
+    },
+    Increment {
+        table: String,
+        primary_key: i64,
+        column: usize,
+    },
+    /// Records whose `column` is `key`, `projected` has a 1 for every column to return
+    Select {
+        table: String,
+        key: i64,
+        column: usize,
+        projected: Vec<i64>,
+    },
+    Sum {
+        table: String,
+        start: i64,
+        end: i64,
+        column: usize,
+    },
+    /// Start the transaction of this connection, answers with its id
+    Begin,
+    Commit,
+    Abort,
+    /// Save the database
+    Checkpoint,
+    /// Save the database and stop accepting connections
+    Shutdown,
+}
+
+/// One line the server answers with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok(Value),
+    Error(String),
+}
+
+/// Serves one database to every client that connects
+pub struct Server {
+    listener: TcpListener,
+    db: Arc<Mutex<Database>>,
+    stop: Arc<AtomicBool>,
+}
+
+// What a connection shares with the server
+#[derive(Clone)]
+struct Shared {
+    db: Arc<Mutex<Database>>,
+    stop: Arc<AtomicBool>,
+    addr: SocketAddr,
+}
+
+impl Server {
+    /// Listen on `addr`, port 0 picks a free port that `local_addr` tells
+    pub fn bind(addr: impl ToSocketAddrs, db: Database) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            db: Arc::new(Mutex::new(db)),
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve every connection on its own thread until a client sends `shutdown`
+    pub fn run(self) -> io::Result<()> {
+        let shared = Shared {
+            db: self.db.clone(),
+            stop: self.stop.clone(),
+            addr: self.local_addr()?,
+        };
+        info!("Listening on {}", shared.addr);
+
+        for stream in self.listener.incoming() {
+            if self.stop.load(Ordering::SeqCst) {
+                break;
+            }
+
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Could not accept a connection: {}", e);
+                    continue;
+                }
+            };
+
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                debug!("Connection from {:?}", peer);
+
+                if let Err(e) = serve_connection(stream, shared) {
+                    debug!("Connection from {:?} failed: {}", peer, e);
+                }
+            });
+        }
+
+        info!("Stopped listening on {}", shared.addr);
+        Ok(())
+    }
+}
+
+fn serve_connection(stream: TcpStream, shared: Shared) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+    let mut transaction = Transaction::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (response, shutdown) = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let shutdown = request == Request::Shutdown;
+                let response = match handle(request, &shared.db, &mut transaction) {
+                    Ok(value) => Response::Ok(value),
+                    Err(e) => Response::Error(e.to_string()),
+                };
+                (response, shutdown)
+            }
+            Err(e) => (Response::Error(format!("Bad request: {}", e)), false),
+        };
+
+        let mut out = serde_json::to_string(&response)?;
+        out.push('\n');
+        writer.write_all(out.as_bytes())?;
+
+        if shutdown {
+            shared.stop.store(true, Ordering::SeqCst);
+            // Wake up the accept loop so it sees the stop flag
+            let _ = TcpStream::connect(shared.addr);
+            break;
+        }
+    }
+
+    // The client went away, dropping its transaction aborts it if nobody finished it
+    Ok(())
+}
+
+fn sql_value(output: SqlOutput) -> Value {
+    match output {
+        SqlOutput::Rows(rows) => json!(rows),
+        SqlOutput::Sum(sum) => json!(sum),
+        SqlOutput::Affected(count) => json!(count),
+        SqlOutput::Done => Value::Null,
+    }
+}
+
+fn result_value(result: QueryResult) -> Value {
+    match result {
+        QueryResult::Records(records) => {
+            let rows: Vec<Vec<Option<i64>>> = records.into_iter().map(|r| r.columns).collect();
+            json!(rows)
+        }
+        QueryResult::Sum(sum) => json!(sum),
+        QueryResult::Done(_) => Value::Null,
+    }
+}
+
+// The table and operation of a query, an open transaction runs these
+fn as_operation(request: &Request) -> Option<(&str, Operation)> {
+    Some(match request {
+        Request::Insert { table, values } => (table, Operation::Insert(values.clone())),
+        Request::Update {
+            table,
+            primary_key,
+            columns,
+        } => (
+            table,
+            Operation::Update {
+                primary_key: *primary_key,
+                columns: columns.clone(),
+            },
+        ),
+        Request::Delete { table, primary_key } => (
+            table,
+            Operation::Delete {
+                primary_key: *primary_key,
+            },
+        ),
+        Request::Increment {
+            table,
+            primary_key,
+            column,
+        } => (
+            table,
+            Operation::Increment {
+                primary_key: *primary_key,
+                column: *column as i64,
+            },
+        ),
+        Request::Select {
+            table,
+            key,
+            column,
+            projected,
+        } => (
+            table,
+            Operation::Select {
+                key: *key,
+                key_index: *column as i64,
+                projected: projected.clone(),
+            },
+        ),
+        Request::Sum {
+            table,
+            start,
+            end,
+            column,
+        } => (
+            table,
+            Operation::Sum {
+                start: *start,
+                end: *end,
+                column: *column as i64,
+            },
+        ),
+        _ => return None,
+    })
+}
+
+// Run a request, the database is only locked to look up tables and for statements on it
+fn handle(
+    request: Request,
+    db: &Mutex<Database>,
+    transaction: &mut Transaction,
+) -> Result<Value, Error> {
+    let table = |name: &str| db.lock().unwrap().table(name);
+
+    if transaction.is_active() {
+        if let Some((name, op)) = as_operation(&request) {
+            return Ok(result_value(transaction.execute(&table(name)?, op)?));
+        }
+    }
+
+    Ok(match request {
+        Request::Sql { sql } => sql_value(db.lock().unwrap().execute(&sql)?),
+        Request::CreateTable {
+            name,
+            num_columns,
+            primary_key_column,
+        } => {
+            db.lock()
+                .unwrap()
+                .create_table(&name, num_columns, primary_key_column)?;
+            Value::Null
+        }
+        Request::DropTable { name } => {
+            db.lock().unwrap().drop_table(&name)?;
+            Value::Null
+        }
+        Request::ListTables => json!(db.lock().unwrap().list_tables()),
+        Request::Insert {
+            table: name,
+            values,
+        } => {
+            table(&name)?.query().insert(&values)?;
+            Value::Null
+        }
+        Request::Update {
+            table: name,
+            primary_key,
+            columns,
+        } => {
+            table(&name)?.query().update(primary_key, &columns)?;
+            Value::Null
+        }
+        Request::Delete {
+            table: name,
+            primary_key,
+        } => {
+            table(&name)?.query().delete(primary_key)?;
+            Value::Null
+        }
+        Request::Increment {
+            table: name,
+            primary_key,
+            column,
+        } => {
+            table(&name)?.query().increment(primary_key, column)?;
+            Value::Null
+        }
+        Request::Select {
+            table: name,
+            key,
+            column,
+            projected,
+        } => json!(table(&name)?.query().select(key, column, &projected)?),
+        Request::Sum {
+            table: name,
+            start,
+            end,
+            column,
+        } => json!(table(&name)?.query().sum(start, end, column)?),
+        Request::Begin => {
+            if transaction.is_active() {
+                return Err(Error::AlreadyActive(transaction.id()));
+            }
+
+            // A new id makes the transaction younger than every earlier one, which the
+            // deadlock strategies rely on
+            *transaction = Transaction::new();
+            transaction.begin()?;
+            json!(transaction.id())
+        }
+        Request::Commit => {
+            transaction.commit()?;
+            Value::Null
+        }
+        Request::Abort => {
+            transaction.abort()?;
+            Value::Null
+        }
+        Request::Checkpoint | Request::Shutdown => {
+            db.lock().unwrap().close()?;
+            Value::Null
+        }
+    })
+}
@@ -0,0 +1,941 @@
+//! A small SQL dialect, planned onto `RQuery` and `RTable`
+//!
+//! Columns have no names in RedoxQL, so statements call them by position: `c0`, `c1`, ...
+//! Every value is an integer.
+//!
+//! ```sql
+//! CREATE TABLE Grades (c0 INT PRIMARY KEY, c1 INT, c2 INT);
+//! INSERT INTO Grades VALUES (1, 90, 80), (2, 70, 60);
+//! SELECT c0, c2 FROM Grades WHERE c1 >= 75;
+//! SELECT SUM(c1) FROM Grades WHERE c0 BETWEEN 1 AND 2;
+//! UPDATE Grades SET c1 = 95 WHERE c0 = 1;
+//! DELETE FROM Grades WHERE c0 = 2;
+//! DROP TABLE Grades;
+//! ```
+//!
+//! A WHERE clause is comparisons (`=`, `<`, `<=`, `>`, `>=` and `BETWEEN`) joined by AND.
+
+use super::container::NUM_RESERVED_COLUMNS;
+use super::database::{DatabaseError, RDatabase};
+use super::query::RQuery;
+use super::table::RTableHandle;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What a statement gives back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SqlOutput {
+    /// The selected columns of every matching record, ordered by primary key
+    Rows(Vec<Vec<i64>>),
+    Sum(i64),
+    /// How many records an INSERT, UPDATE or DELETE changed
+    Affected(usize),
+    /// CREATE TABLE and DROP TABLE
+    Done,
+}
+
+/// Why a statement could not run
+#[derive(Debug)]
+pub enum SqlError {
+    /// The statement is not in the dialect, `position` is the byte offset of the problem
+    Syntax {
+        position: usize,
+        message: String,
+    },
+    Database(DatabaseError),
+    /// Columns are called c0, c1, ... and the table has fewer columns than that
+    NoSuchColumn(String),
+    /// A record with this primary key already exists
+    DuplicateKey(i64),
+    /// There is no record with this primary key
+    NoSuchRecord(i64),
+    /// The statement parsed but does not fit the table, e.g. too few values
+    Invalid(String),
+}
+
+impl fmt::Display for SqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlError::Syntax { position, message } => {
+                write!(f, "Syntax error at {}: {}", position, message)
+            }
+            SqlError::Database(err) => write!(f, "{}", err),
+            SqlError::NoSuchColumn(name) => write!(f, "No column {}", name),
+            SqlError::DuplicateKey(key) => write!(f, "A record with key {} already exists", key),
+            SqlError::NoSuchRecord(key) => write!(f, "No record with key {}", key),
+            SqlError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SqlError {}
+
+impl From<DatabaseError> for SqlError {
+    fn from(err: DatabaseError) -> Self {
+        SqlError::Database(err)
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<SqlError> for pyo3::PyErr {
+    fn from(err: SqlError) -> pyo3::PyErr {
+        match err {
+            SqlError::Database(err) => err.into(),
+            _ => pyo3::exceptions::PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Number(i64),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 10] = ["<=", ">=", "(", ")", ",", "*", "=", "<", ">", ";"];
+
+// Split the statement into tokens, each with the byte offset it starts at
+fn tokenize(sql: &str) -> Result<Vec<(usize, Token)>, SqlError> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push((start, Token::Word(sql[start..i].to_string())));
+        } else if c.is_ascii_digit()
+            || (c == b'-' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit))
+        {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            let number = sql[start..i].parse().map_err(|_| SqlError::Syntax {
+                position: start,
+                message: format!("{} does not fit in 64 bits", &sql[start..i]),
+            })?;
+            tokens.push((start, Token::Number(number)));
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| sql[i..].starts_with(**s)) {
+            i += symbol.len();
+            tokens.push((start, Token::Symbol(symbol)));
+        } else {
+            let c = sql[i..].chars().next().unwrap();
+            return Err(SqlError::Syntax {
+                position: start,
+                message: format!("Unexpected {:?}", c),
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Projection {
+    All,
+    Columns(Vec<String>),
+    Sum(String),
+}
+
+// `column` is between `low` and `high`, both included
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Condition {
+    column: String,
+    low: i64,
+    high: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Statement {
+    CreateTable {
+        name: String,
+        columns: Vec<String>,
+        primary_key: String,
+    },
+    DropTable {
+        name: String,
+    },
+    Insert {
+        table: String,
+        columns: Option<Vec<String>>,
+        rows: Vec<Vec<i64>>,
+    },
+    Select {
+        table: String,
+        projection: Projection,
+        filter: Vec<Condition>,
+    },
+    Update {
+        table: String,
+        assignments: Vec<(String, i64)>,
+        filter: Vec<Condition>,
+    },
+    Delete {
+        table: String,
+        filter: Vec<Condition>,
+    },
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    // Where errors at the end of the statement point
+    end: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, SqlError> {
+        let position = self.tokens.get(self.pos).map_or(self.end, |(p, _)| *p);
+        Err(SqlError::Syntax {
+            position,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.is_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), SqlError> {
+        if !self.eat_keyword(keyword) {
+            return self.error(format!("Expected {}", keyword));
+        }
+        Ok(())
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn symbol(&mut self, symbol: &str) -> Result<(), SqlError> {
+        if !self.eat_symbol(symbol) {
+            return self.error(format!("Expected {}", symbol));
+        }
+        Ok(())
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, SqlError> {
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error(format!("Expected {}", what)),
+        }
+    }
+
+    fn number(&mut self) -> Result<i64, SqlError> {
+        match self.peek() {
+            Some(&Token::Number(n)) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            _ => self.error("Expected a number"),
+        }
+    }
+
+    // `(item, item, ...)`
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Parser) -> Result<T, SqlError>,
+    ) -> Result<Vec<T>, SqlError> {
+        self.symbol("(")?;
+        let mut items = vec![item(self)?];
+        while self.eat_symbol(",") {
+            items.push(item(self)?);
+        }
+        self.symbol(")")?;
+        Ok(items)
+    }
+
+    fn statement(&mut self) -> Result<Statement, SqlError> {
+        let statement = if self.eat_keyword("CREATE") {
+            self.create_table()?
+        } else if self.eat_keyword("DROP") {
+            self.keyword("TABLE")?;
+            Statement::DropTable {
+                name: self.word("a table name")?,
+            }
+        } else if self.eat_keyword("INSERT") {
+            self.insert()?
+        } else if self.eat_keyword("SELECT") {
+            self.select()?
+        } else if self.eat_keyword("UPDATE") {
+            self.update()?
+        } else if self.eat_keyword("DELETE") {
+            self.keyword("FROM")?;
+            let table = self.word("a table name")?;
+            let filter = self.filter()?;
+            Statement::Delete { table, filter }
+        } else {
+            return self.error("Expected CREATE, DROP, INSERT, SELECT, UPDATE or DELETE");
+        };
+
+        self.eat_symbol(";");
+        if self.pos < self.tokens.len() {
+            return self.error("Expected the end of the statement");
+        }
+
+        Ok(statement)
+    }
+
+    fn create_table(&mut self) -> Result<Statement, SqlError> {
+        self.keyword("TABLE")?;
+        let name = self.word("a table name")?;
+
+        let mut primary_key = None;
+        let columns = self.list(|p| {
+            let column = p.word("a column")?;
+            // The type is optional, every column holds 64 bit integers
+            let _ = ["INT", "INTEGER", "BIGINT"]
+                .iter()
+                .any(|t| p.eat_keyword(t));
+            if p.eat_keyword("PRIMARY") {
+                p.keyword("KEY")?;
+                if primary_key.is_some() {
+                    return p.error("A table has one primary key");
+                }
+                primary_key = Some(column.clone());
+            }
+            Ok(column)
+        })?;
+
+        let Some(primary_key) = primary_key else {
+            return self.error("One column has to be the PRIMARY KEY");
+        };
+
+        Ok(Statement::CreateTable {
+            name,
+            columns,
+            primary_key,
+        })
+    }
+
+    fn insert(&mut self) -> Result<Statement, SqlError> {
+        self.keyword("INTO")?;
+        let table = self.word("a table name")?;
+
+        let columns = match self.peek() {
+            Some(Token::Symbol("(")) => Some(self.list(|p| p.word("a column"))?),
+            _ => None,
+        };
+
+        self.keyword("VALUES")?;
+        let mut rows = vec![self.list(Parser::number)?];
+        while self.eat_symbol(",") {
+            rows.push(self.list(Parser::number)?);
+        }
+
+        Ok(Statement::Insert {
+            table,
+            columns,
+            rows,
+        })
+    }
+
+    fn select(&mut self) -> Result<Statement, SqlError> {
+        let projection = if self.eat_symbol("*") {
+            Projection::All
+        } else if self.eat_keyword("SUM") {
+            self.symbol("(")?;
+            let column = self.word("a column")?;
+            self.symbol(")")?;
+            Projection::Sum(column)
+        } else {
+            let mut columns = vec![self.word("* or a column")?];
+            while self.eat_symbol(",") {
+                columns.push(self.word("a column")?);
+            }
+            Projection::Columns(columns)
+        };
+
+        self.keyword("FROM")?;
+        let table = self.word("a table name")?;
+        let filter = self.filter()?;
+
+        Ok(Statement::Select {
+            table,
+            projection,
+            filter,
+        })
+    }
+
+    fn update(&mut self) -> Result<Statement, SqlError> {
+        let table = self.word("a table name")?;
+        self.keyword("SET")?;
+
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.word("a column")?;
+            self.symbol("=")?;
+            assignments.push((column, self.number()?));
+
+            if !self.eat_symbol(",") {
+                break;
+            }
+        }
+
+        let filter = self.filter()?;
+
+        Ok(Statement::Update {
+            table,
+            assignments,
+            filter,
+        })
+    }
+
+    // An optional WHERE clause
+    fn filter(&mut self) -> Result<Vec<Condition>, SqlError> {
+        let mut conditions = Vec::new();
+        if !self.eat_keyword("WHERE") {
+            return Ok(conditions);
+        }
+
+        loop {
+            conditions.push(self.condition()?);
+            if !self.eat_keyword("AND") {
+                break;
+            }
+        }
+
+        Ok(conditions)
+    }
+
+    fn condition(&mut self) -> Result<Condition, SqlError> {
+        let column = self.word("a column")?;
+
+        if self.eat_keyword("BETWEEN") {
+            let low = self.number()?;
+            self.keyword("AND")?;
+            let high = self.number()?;
+            return Ok(Condition { column, low, high });
+        }
+
+        let op = match self.peek() {
+            Some(Token::Symbol(op @ ("=" | "<" | "<=" | ">" | ">="))) => *op,
+            _ => return self.error("Expected =, <, <=, >, >= or BETWEEN"),
+        };
+        self.pos += 1;
+        let value = self.number()?;
+
+        // Strict comparisons past the smallest or largest value match nothing
+        let (low, high) = match op {
+            "=" => (value, value),
+            "<" => value
+                .checked_sub(1)
+                .map_or((i64::MAX, i64::MIN), |v| (i64::MIN, v)),
+            "<=" => (i64::MIN, value),
+            ">" => value
+                .checked_add(1)
+                .map_or((i64::MAX, i64::MIN), |v| (v, i64::MAX)),
+            _ => (value, i64::MAX),
+        };
+
+        Ok(Condition { column, low, high })
+    }
+}
+
+fn parse(sql: &str) -> Result<Statement, SqlError> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+        end: sql.len(),
+    };
+    parser.statement()
+}
+
+/// Parse one statement and run it on `db`
+pub fn execute(db: &mut RDatabase, sql: &str) -> Result<SqlOutput, SqlError> {
+    match parse(sql)? {
+        Statement::CreateTable {
+            name,
+            columns,
+            primary_key,
+        } => {
+            // Positional names keep the statement honest about which column is which
+            for (i, column) in columns.iter().enumerate() {
+                if column_index(column) != Some(i) {
+                    return Err(SqlError::Invalid(format!(
+                        "Columns are called by position, expected c{} but found {}",
+                        i, column
+                    )));
+                }
+            }
+
+            let primary_key = column_index(&primary_key).unwrap();
+            db.create_table(name, columns.len() as i64, primary_key as i64)?;
+            Ok(SqlOutput::Done)
+        }
+        Statement::DropTable { name } => {
+            db.drop_table(name)?;
+            Ok(SqlOutput::Done)
+        }
+        Statement::Insert {
+            table,
+            columns,
+            rows,
+        } => {
+            let table = db.get_table(table)?;
+            insert(&table, columns, rows)
+        }
+        Statement::Select {
+            table,
+            projection,
+            filter,
+        } => {
+            let table = db.get_table(table)?;
+            select(&table, projection, &filter)
+        }
+        Statement::Update {
+            table,
+            assignments,
+            filter,
+        } => {
+            let table = db.get_table(table)?;
+            update(&table, assignments, &filter)
+        }
+        Statement::Delete { table, filter } => {
+            let table = db.get_table(table)?;
+            let keys = matching_keys(&table, &filter)?;
+            Ok(SqlOutput::Affected(delete_records(&table, &keys)))
+        }
+    }
+}
+
+// `c3` is column 3
+pub(crate) fn column_index(name: &str) -> Option<usize> {
+    let digits = name.strip_prefix('c').or(name.strip_prefix('C'))?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn column(table: &RTableHandle, name: &str) -> Result<usize, SqlError> {
+    column_index(name)
+        .filter(|&i| i < table.get_num_columns())
+        .ok_or_else(|| SqlError::NoSuchColumn(name.to_string()))
+}
+
+fn insert(
+    table: &RTableHandle,
+    columns: Option<Vec<String>>,
+    rows: Vec<Vec<i64>>,
+) -> Result<SqlOutput, SqlError> {
+    let num_columns = table.get_num_columns();
+
+    // Where each value of a row goes
+    let order = match columns {
+        Some(names) => {
+            let order = names
+                .iter()
+                .map(|name| column(table, name))
+                .collect::<Result<Vec<usize>, _>>()?;
+
+            let mut sorted = order.clone();
+            sorted.sort_unstable();
+            if sorted != (0..num_columns).collect::<Vec<_>>() {
+                return Err(SqlError::Invalid(String::from(
+                    "INSERT has to name every column exactly once",
+                )));
+            }
+            order
+        }
+        None => (0..num_columns).collect(),
+    };
+
+    let primary_key_column = table.get_primary_key_column();
+    let mut query = RQuery::new(table.clone());
+
+    // Like any statement outside a transaction, the rows before a failing one stay inserted
+    for row in &rows {
+        if row.len() != num_columns {
+            return Err(SqlError::Invalid(format!(
+                "Expected {} values but found {}",
+                num_columns,
+                row.len()
+            )));
+        }
+
+        let mut values = vec![0; num_columns];
+        for (&i, &value) in order.iter().zip(row) {
+            values[i] = value;
+        }
+
+        let key = values[primary_key_column];
+        if !query.insert(values) {
+            return Err(SqlError::DuplicateKey(key));
+        }
+    }
+
+    Ok(SqlOutput::Affected(rows.len()))
+}
+
+// Every record the filter matches, without the reserved columns and ordered by primary key
+//
+// Records are found through the primary key index, narrowed to the range the filter allows on
+// the key column, then checked against the rest of the filter.
+fn matching_rows(table: &RTableHandle, filter: &[Condition]) -> Result<Vec<Vec<i64>>, SqlError> {
+    let mut bounds: BTreeMap<usize, (i64, i64)> = BTreeMap::new();
+    for condition in filter {
+        let bound = bounds
+            .entry(column(table, &condition.column)?)
+            .or_insert((i64::MIN, i64::MAX));
+        bound.0 = bound.0.max(condition.low);
+        bound.1 = bound.1.min(condition.high);
+    }
+
+    if bounds.values().any(|(low, high)| low > high) {
+        return Ok(Vec::new());
+    }
+
+    let t = table.table.read().unwrap();
+    let (low, high) = bounds
+        .get(&t.primary_key_column)
+        .copied()
+        .unwrap_or((i64::MIN, i64::MAX));
+
+    let keys: Vec<i64> = t
+        .index
+        .read()
+        .unwrap()
+        .index
+        .range(low..=high)
+        .map(|(&k, _)| k)
+        .collect();
+
+    Ok(keys
+        .into_iter()
+        .filter_map(|key| t.read(key))
+        .map(|mut row| {
+            row.drain(..NUM_RESERVED_COLUMNS as usize);
+            row
+        })
+        .filter(|row| {
+            bounds
+                .iter()
+                .all(|(&column, &(low, high))| (low..=high).contains(&row[column]))
+        })
+        .collect())
+}
+
+// The primary keys of the records the filter matches
+fn matching_keys(table: &RTableHandle, filter: &[Condition]) -> Result<Vec<i64>, SqlError> {
+    let primary_key_column = table.get_primary_key_column();
+
+    Ok(matching_rows(table, filter)?
+        .into_iter()
+        .map(|row| row[primary_key_column])
+        .collect())
+}
+
+fn select(
+    table: &RTableHandle,
+    projection: Projection,
+    filter: &[Condition],
+) -> Result<SqlOutput, SqlError> {
+    // Check the columns before reading anything
+    let columns = match &projection {
+        Projection::All => (0..table.get_num_columns()).collect(),
+        Projection::Columns(names) => names
+            .iter()
+            .map(|name| column(table, name))
+            .collect::<Result<Vec<usize>, _>>()?,
+        Projection::Sum(name) => vec![column(table, name)?],
+    };
+
+    let rows = matching_rows(table, filter)?;
+
+    if let Projection::Sum(_) = projection {
+        let sum = rows
+            .iter()
+            .try_fold(0i64, |sum, row| sum.checked_add(row[columns[0]]))
+            .ok_or_else(|| SqlError::Invalid(String::from("The sum does not fit in an i64")))?;
+        return Ok(SqlOutput::Sum(sum));
+    }
+
+    Ok(SqlOutput::Rows(
+        rows.iter()
+            .map(|row| columns.iter().map(|&c| row[c]).collect())
+            .collect(),
+    ))
+}
+
+fn update(
+    table: &RTableHandle,
+    assignments: Vec<(String, i64)>,
+    filter: &[Condition],
+) -> Result<SqlOutput, SqlError> {
+    let mut columns = vec![None; table.get_num_columns()];
+    for (name, value) in assignments {
+        columns[column(table, &name)?] = Some(value);
+    }
+
+    let keys = matching_keys(table, filter)?;
+    update_records(table, &columns, &keys)
+}
+
+// Records can be deleted by someone else after they matched the filter
+fn update_records(
+    table: &RTableHandle,
+    columns: &[Option<i64>],
+    keys: &[i64],
+) -> Result<SqlOutput, SqlError> {
+    let primary_key_column = table.get_primary_key_column();
+    let mut query = RQuery::new(table.clone());
+
+    for &key in keys {
+        if query.update(key, columns.to_vec()) {
+            continue;
+        }
+
+        // Either the record would get the primary key of another one or it is gone
+        let new_key = columns[primary_key_column].unwrap_or(key);
+        if new_key != key && table.read(new_key).is_some() {
+            return Err(SqlError::DuplicateKey(new_key));
+        }
+        return Err(SqlError::NoSuchRecord(key));
+    }
+
+    Ok(SqlOutput::Affected(keys.len()))
+}
+
+// Only the records that were still there count
+fn delete_records(table: &RTableHandle, keys: &[i64]) -> usize {
+    let mut query = RQuery::new(table.clone());
+    keys.iter().filter(|&&key| query.delete(key)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(db: &mut RDatabase, sql: &str) -> SqlOutput {
+        execute(db, sql).unwrap_or_else(|e| panic!("{} failed: {}", sql, e))
+    }
+
+    fn grades() -> RDatabase {
+        let mut db = RDatabase::new();
+        run(
+            &mut db,
+            "CREATE TABLE Grades (c0 INT PRIMARY KEY, c1 INT, c2 INT)",
+        );
+        run(
+            &mut db,
+            "insert into Grades values (1, 90, 80), (2, 70, 60), (3, 50, 95), (10, 100, 0);",
+        );
+        db
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(
+            parse("SELECT c0, c2 FROM t WHERE c1 BETWEEN -5 AND 5 AND c0 < 3").unwrap(),
+            Statement::Select {
+                table: String::from("t"),
+                projection: Projection::Columns(vec![String::from("c0"), String::from("c2")]),
+                filter: vec![
+                    Condition {
+                        column: String::from("c1"),
+                        low: -5,
+                        high: 5
+                    },
+                    Condition {
+                        column: String::from("c0"),
+                        low: i64::MIN,
+                        high: 2
+                    },
+                ],
+            }
+        );
+
+        assert_eq!(
+            parse("DELETE FROM t WHERE c0 > 9223372036854775807").unwrap(),
+            Statement::Delete {
+                table: String::from("t"),
+                filter: vec![Condition {
+                    column: String::from("c0"),
+                    low: i64::MAX,
+                    high: i64::MIN
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn syntax_errors_test() {
+        let position = |sql| match parse(sql) {
+            Err(SqlError::Syntax { position, .. }) => position,
+            other => panic!("{} parsed to {:?}", sql, other),
+        };
+
+        assert_eq!(position("SELEC * FROM t"), 0);
+        assert_eq!(position("SELECT * FROM t WHERE c0 != 1"), 25);
+        assert_eq!(position("SELECT * FROM t WHERE c0 = 1 OR c0 = 2"), 29);
+        assert_eq!(position("INSERT INTO t VALUES (1, 2"), 26);
+        assert_eq!(position("CREATE TABLE t (c0, c1)"), 23);
+        assert_eq!(position("SELECT * FROM t WHERE c0 = 'a'"), 27);
+    }
+
+    #[test]
+    fn select_test() {
+        let mut db = grades();
+
+        assert_eq!(
+            run(&mut db, "SELECT * FROM Grades WHERE c0 = 2"),
+            SqlOutput::Rows(vec![vec![2, 70, 60]])
+        );
+        assert_eq!(
+            run(&mut db, "SELECT c2, c0 FROM Grades WHERE c1 >= 70"),
+            SqlOutput::Rows(vec![vec![80, 1], vec![60, 2], vec![0, 10]])
+        );
+        assert_eq!(
+            run(
+                &mut db,
+                "SELECT c0 FROM Grades WHERE c0 BETWEEN 2 AND 10 AND c2 > 0"
+            ),
+            SqlOutput::Rows(vec![vec![2], vec![3]])
+        );
+        assert_eq!(
+            run(&mut db, "SELECT * FROM Grades WHERE c0 > 3 AND c0 < 3"),
+            SqlOutput::Rows(vec![])
+        );
+        assert_eq!(
+            run(&mut db, "SELECT SUM(c1) FROM Grades WHERE c0 <= 3"),
+            SqlOutput::Sum(210)
+        );
+        assert_eq!(
+            run(&mut db, "SELECT SUM(c2) FROM Grades"),
+            SqlOutput::Sum(235)
+        );
+    }
+
+    #[test]
+    fn write_test() {
+        let mut db = grades();
+
+        assert_eq!(
+            run(&mut db, "UPDATE Grades SET c1 = 95 WHERE c0 = 1"),
+            SqlOutput::Affected(1)
+        );
+        assert_eq!(
+            run(&mut db, "UPDATE Grades SET c2 = 1, c1 = 2 WHERE c1 < 60"),
+            SqlOutput::Affected(1)
+        );
+        assert_eq!(
+            run(&mut db, "SELECT * FROM Grades WHERE c0 <= 3"),
+            SqlOutput::Rows(vec![vec![1, 95, 80], vec![2, 70, 60], vec![3, 2, 1]])
+        );
+
+        assert_eq!(
+            run(&mut db, "DELETE FROM Grades WHERE c1 > 90"),
+            SqlOutput::Affected(2)
+        );
+        assert_eq!(
+            run(&mut db, "SELECT c0 FROM Grades"),
+            SqlOutput::Rows(vec![vec![2], vec![3]])
+        );
+
+        assert_eq!(
+            run(&mut db, "INSERT INTO Grades (c2, c0, c1) VALUES (7, 1, 8)"),
+            SqlOutput::Affected(1)
+        );
+        assert_eq!(
+            run(&mut db, "SELECT * FROM Grades WHERE c0 = 1"),
+            SqlOutput::Rows(vec![vec![1, 8, 7]])
+        );
+
+        assert_eq!(run(&mut db, "DROP TABLE Grades"), SqlOutput::Done);
+        assert!(db.list_tables().is_empty());
+    }
+
+    #[test]
+    fn errors_test() {
+        let mut db = grades();
+
+        assert!(matches!(
+            execute(&mut db, "INSERT INTO Grades VALUES (4, 0, 0), (2, 0, 0)"),
+            Err(SqlError::DuplicateKey(2))
+        ));
+        // The rows before the duplicate stay
+        assert_eq!(
+            run(&mut db, "SELECT c0 FROM Grades WHERE c0 = 4"),
+            SqlOutput::Rows(vec![vec![4]])
+        );
+
+        assert!(matches!(
+            execute(&mut db, "UPDATE Grades SET c0 = 3 WHERE c0 = 1"),
+            Err(SqlError::DuplicateKey(3))
+        ));
+        // Records that matched but were deleted before the statement got to them
+        let table = db.get_table(String::from("Grades")).unwrap();
+        assert!(matches!(
+            update_records(&table, &[None, Some(0), None], &[9]),
+            Err(SqlError::NoSuchRecord(9))
+        ));
+        assert_eq!(delete_records(&table, &[4, 4, 9]), 1);
+        assert!(matches!(
+            execute(&mut db, "INSERT INTO Grades VALUES (5, 0)"),
+            Err(SqlError::Invalid(_))
+        ));
+        assert!(matches!(
+            execute(&mut db, "INSERT INTO Grades (c0, c0, c1) VALUES (5, 5, 0)"),
+            Err(SqlError::Invalid(_))
+        ));
+        assert!(matches!(
+            execute(&mut db, "SELECT c3 FROM Grades"),
+            Err(SqlError::NoSuchColumn(name)) if name == "c3"
+        ));
+        assert!(matches!(
+            execute(&mut db, "SELECT * FROM Grades WHERE grade = 1"),
+            Err(SqlError::NoSuchColumn(_))
+        ));
+        assert!(matches!(
+            execute(&mut db, "SELECT * FROM Missing"),
+            Err(SqlError::Database(DatabaseError::NoSuchTable(_)))
+        ));
+        assert!(matches!(
+            execute(&mut db, "CREATE TABLE Grades (c0 PRIMARY KEY)"),
+            Err(SqlError::Database(DatabaseError::TableExists(_)))
+        ));
+        assert!(matches!(
+            execute(&mut db, "CREATE TABLE Other (id PRIMARY KEY)"),
+            Err(SqlError::Invalid(_))
+        ));
+
+        run(
+            &mut db,
+            "INSERT INTO Grades VALUES (20, 9223372036854775807, 0)",
+        );
+        assert!(matches!(
+            execute(&mut db, "SELECT SUM(c1) FROM Grades"),
+            Err(SqlError::Invalid(_))
+        ));
+    }
+}
@@ -6,22 +6,20 @@ pub fn get_all_drives() -> Vec<String> {
     let mut drives = Vec::<String>::new();
 
     if let Ok(entries) = read_dir("/dev") {
-        for entry in entries {
-            if let Ok(entry) = entry {
-                if let Some(name) = entry.file_name().to_str() {
-                    // Check if the file is a drive
-                    if name.starts_with("nvme") || name.starts_with("sd") {
-                        // Check if the drive exists in /sys/block
-                        if Path::new(&format!("/sys/block/{}", name)).exists() {
-                            drives.push(name.to_string());
-                        }
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str() {
+                // Check if the file is a drive
+                if name.starts_with("nvme") || name.starts_with("sd") {
+                    // Check if the drive exists in /sys/block
+                    if Path::new(&format!("/sys/block/{}", name)).exists() {
+                        drives.push(name.to_string());
                     }
                 }
             }
         }
     }
 
-    return drives;
+    drives
 }
 
 /// Read the block size for the machine
@@ -34,11 +32,10 @@ fn read_block_size(drive: &str, type_of_block: &str) -> i16 {
     block_size_str.pop();
 
     // Cast the number into an i16 from the String
-    let block_size = block_size_str
-        .parse::<i16>()
-        .expect("Should parse block size.");
 
-    block_size
+    block_size_str
+        .parse::<i16>()
+        .expect("Should parse block size.")
 }
 
 pub fn get_logical_block_size(drive: &str) -> i16 {
@@ -1,13 +1,25 @@
-use super::filewriter::{BinaryFileWriter, Writer};
+#[cfg(feature = "python")]
+use super::bulk::OnDuplicate;
+use super::bulk::{self, BulkError, ExportOptions, ImportOptions, ImportSummary};
+use super::filewriter::{BinaryFileWriter, FileError, Writer};
 use super::index::RIndex;
-use super::page::PhysicalPage;
+use super::lock_manager::LockManager;
+use super::mvcc::{self, Snapshot, VersionMap};
+use super::page::PageHandle;
 use super::pagerange::{PageRange, PageRangeMetadata};
-use super::record::{Record, RecordMetadata};
+use super::record::{Record, RecordAddress, RecordMetadata};
 use crate::container::{ReservedColumns, NUM_RESERVED_COLUMNS};
 use crate::index::RIndexHandle;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "python")]
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
 use std::sync::{Arc, Mutex, RwLock};
 
 type RedoxQLHashMap<K, V> = FxHashMap<K, V>;
@@ -32,39 +44,34 @@ impl PageDirectory {
     pub fn display(&self) {
         for (rid, record) in self.directory.clone() {
             print!("{rid} -> ");
-            let m = record.addresses;
-            let b = m.lock().unwrap();
-            let c = b.iter();
-
-            for addr in c {
+            for addr in record.addresses.iter() {
                 println!("{:?}", addr);
             }
             print!("\n\n\n");
         }
     }
 
-    fn load_state(page_range: &PageRange) -> PageDirectory {
+    fn load_state(dir: &str, page_range: &PageRange) -> Result<PageDirectory, FileError> {
         let base_phys_pages = &page_range.base_container.physical_pages;
         let tail_phys_pages = &page_range.tail_container.physical_pages;
 
         // Create a map of column_indexes to the physical pages there are stored in
-        let mut base_pages = RedoxQLHashMap::<i64, Arc<Mutex<PhysicalPage>>>::default();
-        let mut tail_pages = RedoxQLHashMap::<i64, Arc<Mutex<PhysicalPage>>>::default();
+        let mut base_pages = RedoxQLHashMap::<i64, PageHandle>::default();
+        let mut tail_pages = RedoxQLHashMap::<i64, PageHandle>::default();
 
         // Load the base pages into the map
         for page in base_phys_pages {
-            let m = page.lock().unwrap();
-            base_pages.insert(m.column_index, page.clone());
+            base_pages.insert(page.column_index(), page.clone());
         }
 
         // Load the tail pages into the map
         for page in tail_phys_pages {
-            let m = page.lock().unwrap();
-            tail_pages.insert(m.column_index, page.clone());
+            tail_pages.insert(page.column_index(), page.clone());
         }
 
         let writer = Writer::new(Box::new(BinaryFileWriter::new()));
-        let page_meta: PageDirectoryMetadata = writer.read_file("./redoxdata/page_directory.data");
+        let page_meta: PageDirectoryMetadata =
+            writer.read_file(&format!("{}/page_directory.data", dir))?;
 
         let mut pd: PageDirectory = PageDirectory {
             directory: RedoxQLHashMap::default(),
@@ -78,24 +85,87 @@ impl PageDirectory {
 
         // pd.display();
 
-        return pd;
+        Ok(pd)
     }
 
-    fn save_state(&self) {
+    fn save_state(&self, dir: &str, page_range: &PageRange) -> io::Result<()> {
         let mut pd_meta = PageDirectoryMetadata {
             directory: RedoxQLHashMap::default(),
         };
 
+        // Tail records are the ones whose RID is on the RID page of the tail container
+        let tail_rid_page = page_range.tail_container.rid_page();
+
         for (rid, record) in &self.directory {
-            let r: RecordMetadata = record.get_metadata();
+            let tail = Arc::ptr_eq(
+                &record.addresses[ReservedColumns::RID as usize].page,
+                &tail_rid_page,
+            );
+            let r: RecordMetadata = record.get_metadata(tail);
             pd_meta.directory.insert(*rid, r);
         }
 
         let writer = Writer::new(Box::new(BinaryFileWriter::new()));
-        writer.write_file("./redoxdata/page_directory.data", &pd_meta);
+        writer.write_file(&format!("{}/page_directory.data", dir), &pd_meta)
+    }
+}
+
+/// What `describe_table` tells about a table
+#[cfg_attr(feature = "python", pyclass(get_all))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableDescription {
+    pub name: String,
+    pub table_id: i64,
+    pub num_columns: usize,
+    pub primary_key_column: usize,
+    /// Records that can be selected, deleted ones don't count
+    pub num_records: usize,
+    pub num_base_records: i64,
+    /// Every update adds a tail record, so this grows with the number of updates
+    pub num_tail_records: i64,
+    /// The columns with an index, the primary key column first
+    pub indexed_columns: Vec<i64>,
+    /// Bytes the table takes in the current snapshot, 0 if it was never saved
+    pub disk_size: u64,
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl TableDescription {
+    fn __str__(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn __repr__(&self) -> String {
+        self.__str__()
+    }
+}
+
+/// Why a schema change was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// The table has fewer columns than that
+    NoSuchColumn(usize),
+    /// Records are found by their primary key, so its column can't go away
+    PrimaryKeyColumn,
+    /// A new order has to name every column exactly once
+    NotAPermutation(Vec<usize>),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::NoSuchColumn(col) => write!(f, "No column {}", col),
+            SchemaError::PrimaryKeyColumn => write!(f, "Can't drop the primary key column"),
+            SchemaError::NotAPermutation(order) => {
+                write!(f, "{:?} does not name every column exactly once", order)
+            }
+        }
     }
 }
 
+impl std::error::Error for SchemaError {}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct RTableMetadata {
     pub name: String,
@@ -108,13 +178,13 @@ pub struct RTableMetadata {
 }
 
 pub trait StatePersistence {
-    fn load_state(&self, table_num: i64) -> RTable {
-        let filename = format!("./redoxdata/{}-table.data", table_num);
+    fn load_state(&self, dir: &str, table_num: i64) -> Result<RTable, FileError> {
+        let filename = format!("{}/{}-table.data", dir, table_num);
         let writer = Writer::new(Box::new(BinaryFileWriter::new()));
-        let table_meta: RTableMetadata = writer.read_file(&filename);
+        let table_meta: RTableMetadata = writer.read_file(&filename)?;
 
-        let pr = PageRange::load_state();
-        let pd = PageDirectory::load_state(&pr);
+        let pr = PageRange::load_state(dir)?;
+        let pd = PageDirectory::load_state(dir, &pr)?;
 
         let mut t = RTable {
             name: table_meta.name.clone(),
@@ -127,17 +197,20 @@ pub trait StatePersistence {
             index: Arc::new(RwLock::new(RIndex::new())),
             table_num: table_meta.table_num,
             updates_since_merge: table_meta.updates_since_merge,
+            // The database hands its own lock manager to the table after loading it
+            lock_manager: Arc::new(LockManager::default()),
+            versions: VersionMap::default(),
         };
 
         // It does not make sense to clone here
         let arc_table = Arc::new(RwLock::new(t.clone()));
         let weak_table = Arc::downgrade(&arc_table);
 
-        let index = RIndex::load_state(weak_table);
+        let index = RIndex::load_state(dir, weak_table)?;
 
         t.index = Arc::new(RwLock::new(index));
 
-        return t;
+        Ok(t)
     }
 }
 
@@ -145,7 +218,7 @@ impl StatePersistence for RTableMetadata {}
 impl StatePersistence for RTable {}
 
 #[derive(Clone, Default)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct RTable {
     pub name: String,
 
@@ -158,15 +231,20 @@ pub struct RTable {
 
     pub num_records: i64,
 
-    #[pyo3(get)]
     pub num_columns: usize,
 
     pub index: Arc<RwLock<RIndex>>,
 
-    /// The nth table that was created will have the value n here and is indexed by zero
+    /// The id of the table, the database hands them out in order and never reuses one
     pub table_num: i64,
 
     pub updates_since_merge: i64,
+
+    /// The locks of the database this table is in, shared by all of its tables
+    pub lock_manager: Arc<LockManager>,
+
+    /// Which transaction wrote each record version and when it committed
+    pub versions: VersionMap,
 }
 
 impl RTable {
@@ -185,16 +263,45 @@ impl RTable {
         self.page_directory.directory.insert(rid, rec.clone());
 
         self.num_records += 1;
-        return rec;
+        rec
+    }
+
+    /// `write` for many records, each page takes one append and the index is locked once for all of them
+    pub fn write_many(&mut self, rows: Vec<Vec<i64>>) -> Vec<Record> {
+        let first_rid = self.num_records;
+        let keys: Vec<i64> = rows.iter().map(|v| v[self.primary_key_column]).collect();
+
+        let records = self.page_range.write_many(
+            rows.into_iter()
+                .enumerate()
+                .map(|(i, values)| (first_rid + i as i64, values))
+                .collect(),
+        );
+
+        {
+            let mut index = self.index.write().unwrap();
+            for (&key, record) in keys.iter().zip(&records) {
+                index.add(key, record.rid);
+            }
+        }
+
+        for record in &records {
+            self.page_directory
+                .directory
+                .insert(record.rid, record.clone());
+        }
+
+        self.num_records += records.len() as i64;
+        records
     }
 
     pub fn read_base(&self, primary_key: i64) -> Option<Vec<i64>> {
         // Lookup RID from primary_key
-        let index = self.index.try_read().unwrap();
+        let index = self.index.read().unwrap();
         let rid = index.get(primary_key);
 
         if let Some(r) = rid {
-            let rec = self.page_directory.directory.get(&r);
+            let rec = self.page_directory.directory.get(r);
 
             // If the rec exists in the page_directory, return the read values
             match rec {
@@ -207,9 +314,7 @@ impl RTable {
     }
 
     pub fn read(&self, primary_key: i64) -> Option<Vec<i64>> {
-        let Some(result) = self.read_base(primary_key as i64) else {
-            return None;
-        };
+        let result = self.read_base(primary_key)?;
         let base_rid = result[ReservedColumns::RID as usize];
         let base_indirection_column = result[ReservedColumns::Indirection as usize];
 
@@ -217,11 +322,12 @@ impl RTable {
             return Some(result);
         }
 
-        let Some(tail_record) = self.page_directory.directory.get(&base_indirection_column) else {
-            return None;
-        };
+        let tail_record = self
+            .page_directory
+            .directory
+            .get(&base_indirection_column)?;
 
-        return self.page_range.read(tail_record.clone());
+        self.page_range.read(tail_record.clone())
     }
 
     // Given a RID, get the record's values
@@ -233,9 +339,7 @@ impl RTable {
     }
 
     pub fn read_relative(&self, primary_key: i64, relative_version: i64) -> Option<Vec<i64>> {
-        let Some(base) = self.read_base(primary_key as i64) else {
-            return None;
-        };
+        let base = self.read_base(primary_key)?;
         let base_rid = base[ReservedColumns::RID as usize];
         let base_indirection_column = base[ReservedColumns::Indirection as usize];
         if base_rid == base_indirection_column {
@@ -244,17 +348,13 @@ impl RTable {
 
         let mut current_rid = base_indirection_column;
         let mut versions_back = 0;
-        let target_version = relative_version.abs() as i64;
+        let target_version = relative_version.abs();
 
         while versions_back < target_version {
-            let Some(current_record) = self.page_directory.directory.get(&current_rid) else {
-                return None;
-            };
+            let current_record = self.page_directory.directory.get(&current_rid)?;
 
             // read the current record
-            let Some(record_data) = self.page_range.read(current_record.clone()) else {
-                return None;
-            };
+            let record_data = self.page_range.read(current_record.clone())?;
 
             // get the indirection of the previous version
             let prev_indirection: i64 = record_data[ReservedColumns::Indirection as usize];
@@ -270,33 +370,156 @@ impl RTable {
         }
 
         // read the final record we want
-        let Some(final_record) = self.page_directory.directory.get(&current_rid) else {
-            return None;
+        let final_record = self.page_directory.directory.get(&current_rid)?;
+
+        self.page_range.read(final_record.clone())
+    }
+
+    /// Read the newest version of a record that the snapshot can see
+    pub fn read_at(&self, primary_key: i64, snapshot: &Snapshot) -> Option<Vec<i64>> {
+        self.read_relative_at(primary_key, 0, snapshot)
+    }
+
+    /// Like `read_relative`, but only counts the versions the snapshot can see
+    ///
+    /// The record is the one that had the primary key when the snapshot started, even if it was
+    /// deleted or got another key since.
+    pub fn read_relative_at(
+        &self,
+        primary_key: i64,
+        relative_version: i64,
+        snapshot: &Snapshot,
+    ) -> Option<Vec<i64>> {
+        let key_column = NUM_RESERVED_COLUMNS as usize + self.primary_key_column;
+
+        let current = self
+            .index
+            .read()
+            .unwrap()
+            .get(primary_key)
+            .and_then(|rid| self.page_directory.directory.get(rid))
+            .cloned();
+        let former = self.versions.former_records(primary_key, snapshot);
+
+        // Only one of them had the key in the newest version the snapshot sees
+        let base = current.into_iter().chain(former).find(|base| {
+            self.read_version_at(base.clone(), 0, snapshot)
+                .is_some_and(|values| values[key_column] == primary_key)
+        })?;
+
+        self.read_version_at(base, relative_version, snapshot)
+    }
+
+    // Read a version of the base record that the snapshot can see
+    fn read_version_at(
+        &self,
+        base: Record,
+        relative_version: i64,
+        snapshot: &Snapshot,
+    ) -> Option<Vec<i64>> {
+        let base = self.page_range.read(base)?;
+        let base_rid = base[ReservedColumns::RID as usize];
+
+        let mut current_rid = base[ReservedColumns::Indirection as usize];
+        let mut versions_back = 0;
+        let target_version = relative_version.abs();
+
+        // Walk from the newest tail record towards the base record
+        while current_rid != base_rid {
+            let record_data = self.read_by_rid(current_rid)?;
+
+            if self.versions.is_visible(current_rid, snapshot) {
+                if versions_back == target_version {
+                    return Some(record_data);
+                }
+                versions_back += 1;
+            }
+
+            current_rid = record_data[ReservedColumns::Indirection as usize];
+        }
+
+        // A base record the snapshot can't see was inserted after the snapshot started
+        if self.versions.is_visible(base_rid, snapshot) {
+            Some(base)
+        } else {
+            None
+        }
+    }
+
+    /// Delete the record, snapshots that started before `writer` commits can still read it
+    /// Delete the record with this primary key, false if there is none
+    pub fn delete(&mut self, primary_key: i64, writer: i64) -> bool {
+        // Lookup RID from primary_key and forget the key, so it can be inserted again
+        let mut index = self.index.write().unwrap();
+        let Some(rid) = index.index.remove(&primary_key) else {
+            return false;
         };
 
-        return self.page_range.read(final_record.clone());
+        let Some(base) = self.page_directory.directory.remove(&rid) else {
+            return false;
+        };
+        self.versions.record_former_key(primary_key, base, writer);
+        true
     }
 
-    pub fn delete(&mut self, primary_key: i64) {
-        // Lookup RID from primary_key
-        let index = self.index.read().unwrap();
-        let rid = index.get(primary_key);
+    /// The base record with this primary key and the RID of its newest version
+    pub fn base_record(&self, primary_key: i64) -> Option<(Record, i64)> {
+        let rid = *self.index.read().unwrap().get(primary_key)?;
+        let base = self.page_directory.directory.get(&rid)?.clone();
+
+        let address = &base.addresses[ReservedColumns::Indirection as usize];
+        let newest_rid = address.page.read(address.offset as usize)?;
+
+        Some((base, newest_rid))
+    }
+
+    /// Take back an insert that was not committed
+    pub fn undo_insert(&mut self, primary_key: i64) {
+        let rid = self.index.write().unwrap().index.remove(&primary_key);
 
         if let Some(r) = rid {
             self.page_directory.directory.remove(&r);
         }
     }
 
+    /// Take back an update that was not committed, before it the base record had `primary_key`
+    /// and its newest version was `newest_rid`
+    pub fn undo_update(
+        &mut self,
+        primary_key: i64,
+        new_primary_key: i64,
+        base: &Record,
+        newest_rid: i64,
+    ) {
+        let address = &base.addresses[ReservedColumns::Indirection as usize];
+        if let Some(tail_rid) = address.page.read(address.offset as usize) {
+            self.page_directory.directory.remove(&tail_rid);
+        }
+        address.page.overwrite(address.offset as usize, newest_rid);
+
+        if new_primary_key != primary_key {
+            let mut index = self.index.write().unwrap();
+            index.index.remove(&new_primary_key);
+            index.add(primary_key, base.rid);
+        }
+    }
+
+    /// Take back a delete that was not committed
+    pub fn undo_delete(&mut self, primary_key: i64, base: Record) {
+        self.index.write().unwrap().add(primary_key, base.rid);
+        self.page_directory.directory.insert(base.rid, base);
+    }
+
     pub fn sum(&mut self, start_primary_key: i64, end_primary_key: i64, col_index: i64) -> i64 {
         let mut agg = 0i64;
 
         for primary_key in start_primary_key..end_primary_key + 1 {
             if let Some(v) = self.read(primary_key) {
-                agg += v[(col_index + NUM_RESERVED_COLUMNS) as usize] as i64;
+                agg += v[(col_index + NUM_RESERVED_COLUMNS) as usize];
             }
         }
 
-        return agg;
+        agg
     }
 
     pub fn sum_version(
@@ -310,27 +533,47 @@ impl RTable {
 
         for primary_key in start_primary_key..end_primary_key + 1 {
             if let Some(v) = self.read_relative(primary_key, relative_version) {
-                agg += v[(col_index + NUM_RESERVED_COLUMNS) as usize] as i64;
+                agg += v[(col_index + NUM_RESERVED_COLUMNS) as usize];
             }
         }
 
-        return agg;
+        agg
     }
 
-    /// Save the state of RTable in a file
-    pub fn save_state(&self) {
+    /// `sum_version`, but only with the versions the snapshot can see, None if the sum overflows
+    pub fn sum_at(
+        &self,
+        start_primary_key: i64,
+        end_primary_key: i64,
+        col_index: i64,
+        relative_version: i64,
+        snapshot: &Snapshot,
+    ) -> Option<i64> {
+        let mut agg = 0i64;
+
+        for primary_key in start_primary_key..end_primary_key + 1 {
+            if let Some(v) = self.read_relative_at(primary_key, relative_version, snapshot) {
+                agg = agg.checked_add(v[(col_index + NUM_RESERVED_COLUMNS) as usize])?;
+            }
+        }
+
+        Some(agg)
+    }
+
+    /// Save the state of RTable into the files of `dir`
+    pub fn save_state(&self, dir: &str) -> io::Result<()> {
         // Save the state of the page range
-        self.page_range.save_state();
+        self.page_range.save_state(dir)?;
 
-        self.page_directory.save_state();
+        self.page_directory.save_state(dir, &self.page_range)?;
 
-        self.index.read().unwrap().save_state();
+        self.index.read().unwrap().save_state(dir)?;
 
         let table_meta = self.get_metadata();
 
-        let filename = format!("./redoxdata/{}-table.data", self.table_num);
+        let filename = format!("{}/{}-table.data", dir, self.table_num);
         let writer = Writer::new(Box::new(BinaryFileWriter::new()));
-        writer.write_file(&filename, &table_meta);
+        writer.write_file(&filename, &table_meta)
     }
 
     pub fn get_metadata(&self) -> RTableMetadata {
@@ -345,23 +588,201 @@ impl RTable {
         }
     }
 
+    /// Records that can be selected right now
+    pub fn num_live_records(&self) -> usize {
+        self.index.read().unwrap().index.len()
+    }
+
+    pub fn num_base_records(&self) -> i64 {
+        self.page_range.base_container.rid_page().num_records()
+    }
+
+    pub fn num_tail_records(&self) -> i64 {
+        self.page_range.tail_container.rid_page().num_records()
+    }
+
+    /// The primary key column followed by the columns with a secondary index, in order
+    pub fn indexed_columns(&self) -> Vec<i64> {
+        let mut secondary: Vec<i64> = self
+            .index
+            .read()
+            .unwrap()
+            .secondary_indices
+            .keys()
+            .copied()
+            .filter(|&col| col != self.primary_key_column as i64)
+            .collect();
+        secondary.sort_unstable();
+
+        let mut columns = vec![self.primary_key_column as i64];
+        columns.extend(secondary);
+        columns
+    }
+
+    /// Everything about the table but its size on disk, which only the database knows
+    pub fn describe(&self) -> TableDescription {
+        TableDescription {
+            name: self.name.clone(),
+            table_id: self.table_num,
+            num_columns: self.num_columns,
+            primary_key_column: self.primary_key_column,
+            num_records: self.num_live_records(),
+            num_base_records: self.num_base_records(),
+            num_tail_records: self.num_tail_records(),
+            indexed_columns: self.indexed_columns(),
+            disk_size: 0,
+        }
+    }
+
+    /// Add a column at the end, every record already there gets `default` in it
+    ///
+    /// Tail records get the default too, so older versions stay readable. Returns the index of
+    /// the new column.
+    pub fn add_column(&mut self, default: i64) -> usize {
+        let (base_page, tail_page) = self.page_range.add_column(default);
+        let tail_rid_page = self.page_range.tail_container.rid_page();
+
+        let add_address = |record: &mut Record| {
+            let rid_address = &record.addresses[ReservedColumns::RID as usize];
+
+            // Rows of a container line up across its pages, so the new value sits at the offset
+            // of the record's RID. A record on pages a merge replaced is a base record, and the
+            // merged base container keeps it at the same offset.
+            let page = if Arc::ptr_eq(&rid_address.page, &tail_rid_page) {
+                tail_page.clone()
+            } else {
+                base_page.clone()
+            };
+            let address = RecordAddress {
+                page,
+                offset: rid_address.offset,
+            };
+
+            let mut addresses = record.addresses.to_vec();
+            addresses.push(address);
+            record.addresses = Arc::new(addresses);
+        };
+
+        self.page_directory
+            .directory
+            .values_mut()
+            .for_each(add_address);
+        // Deleted and re-keyed records that older snapshots still read get the column too
+        self.versions.remap_former_records(add_address);
+
+        self.num_columns += 1;
+        self.num_columns - 1
+    }
+
+    /// Remove a column from the table and from every version of every record
+    pub fn drop_column(&mut self, column: usize) -> Result<(), SchemaError> {
+        if column >= self.num_columns {
+            return Err(SchemaError::NoSuchColumn(column));
+        }
+        if column == self.primary_key_column {
+            return Err(SchemaError::PrimaryKeyColumn);
+        }
+
+        let order: Vec<usize> = (0..self.num_columns).filter(|&c| c != column).collect();
+        self.arrange_columns(&order);
+
+        Ok(())
+    }
+
+    /// Move the columns around, `order[i]` is the current index of the column that goes to i
+    pub fn reorder_columns(&mut self, order: &[usize]) -> Result<(), SchemaError> {
+        let mut sorted = order.to_vec();
+        sorted.sort_unstable();
+        if sorted != (0..self.num_columns).collect::<Vec<usize>>() {
+            return Err(SchemaError::NotAPermutation(order.to_vec()));
+        }
+
+        self.arrange_columns(order);
+
+        Ok(())
+    }
+
+    // Keep the columns in `order` in that order, for the pages, the records and the indexes
+    fn arrange_columns(&mut self, order: &[usize]) {
+        let reserved = NUM_RESERVED_COLUMNS as usize;
+
+        self.page_range.arrange_columns(order);
+
+        let arrange = |record: &mut Record| {
+            let mut addresses = record.addresses[..reserved].to_vec();
+            addresses.extend(
+                order
+                    .iter()
+                    .map(|&c| record.addresses[reserved + c].clone()),
+            );
+            record.addresses = Arc::new(addresses);
+        };
+
+        self.page_directory.directory.values_mut().for_each(arrange);
+        self.versions.remap_former_records(arrange);
+
+        let new_position = |col: usize| order.iter().position(|&c| c == col);
+
+        // Secondary indexes move with their column and go away with it
+        {
+            let mut index = self.index.write().unwrap();
+            index.secondary_indices = std::mem::take(&mut index.secondary_indices)
+                .into_iter()
+                .filter_map(|(col, tree)| Some((new_position(col as usize)? as i64, tree)))
+                .collect();
+        }
+
+        self.primary_key_column = new_position(self.primary_key_column)
+            .expect("Should never drop the primary key column");
+        self.num_columns = order.len();
+    }
+
+    /// Make the writes of `writer` visible at `commit_ts` and forget the history that no
+    /// snapshot in use can read anymore
+    pub fn publish(&mut self, writer: i64, commit_ts: u64) {
+        self.versions.publish(writer, commit_ts);
+        self.versions.prune(mvcc::oldest_snapshot_ts());
+    }
+
     pub fn merge(&mut self) {
+        self.versions.prune(mvcc::oldest_snapshot_ts());
+
+        // Merging folds tail records into the base records, which would publish versions of
+        // transactions that are still running
+        if self.versions.has_pending() {
+            return;
+        }
+
         self.page_range
             .merge(Arc::new(Mutex::new(self.page_directory.clone())));
     }
 }
 
+#[cfg(feature = "python")]
+#[pymethods]
+impl RTable {
+    #[getter(num_columns)]
+    fn py_num_columns(&self) -> usize {
+        self.num_columns
+    }
+}
+
 #[derive(Default, Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct RTableHandle {
     pub table: Arc<RwLock<RTable>>,
 }
 
-#[pymethods]
 impl RTableHandle {
     pub fn write(&self, values: Vec<i64>) {
-        let mut table = self.table.write().expect("Failed to acquire write lock");
-        table.write(values);
+        let writer = {
+            let mut table = self.table.write().expect("Failed to acquire write lock");
+            let writer = mvcc::autocommit_writer();
+            let record = table.write(values);
+            table.versions.record_write(record.rid, writer);
+            writer
+        };
+        self.publish(writer);
     }
 
     pub fn read(&self, primary_key: i64) -> Option<Vec<i64>> {
@@ -370,8 +791,24 @@ impl RTableHandle {
     }
 
     pub fn delete(&self, primary_key: i64) {
-        let mut table = self.table.write().expect("Failed to acquire write lock");
-        table.delete(primary_key);
+        let writer = {
+            let mut table = self.table.write().expect("Failed to acquire write lock");
+            let writer = mvcc::autocommit_writer();
+            table.delete(primary_key, writer);
+            writer
+        };
+        self.publish(writer);
+    }
+
+    /// Commit what `writer` wrote outside of a transaction
+    ///
+    /// Don't hold the lock of the table, transactions take the commit lock first.
+    pub fn publish(&self, writer: i64) {
+        if writer == mvcc::UNSTAMPED_WRITER {
+            return;
+        }
+
+        mvcc::commit(|ts| self.table.write().unwrap().publish(writer, ts));
     }
 
     pub fn debug_page_dir(&self) {
@@ -379,14 +816,11 @@ impl RTableHandle {
         t.page_directory.display();
     }
 
-    // Allow access to properties
-    #[getter]
     pub fn get_num_records(&self) -> i64 {
         let table = self.table.read().expect("Failed to acquire read lock");
         table.num_records
     }
 
-    #[getter]
     pub fn index(&self) -> RIndexHandle {
         let table = self.table.read().expect("Failed to acquire read lock");
         RIndexHandle {
@@ -394,34 +828,291 @@ impl RTableHandle {
         }
     }
 
-    #[getter]
     pub fn get_name(&self) -> String {
         let table = self.table.read().expect("Failed to acquire read lock");
         table.name.clone()
     }
 
-    #[getter]
     pub fn get_num_columns(&self) -> usize {
         let table = self.table.read().expect("Failed to acquire read lock");
         table.num_columns
     }
 
-    #[getter]
     pub fn get_primary_key_column(&self) -> usize {
         let table = self.table.read().expect("Failed to acquire read lock");
         table.primary_key_column
     }
+
+    pub fn get_table_id(&self) -> i64 {
+        let table = self.table.read().expect("Failed to acquire read lock");
+        table.table_num
+    }
+
+    pub fn get_num_live_records(&self) -> usize {
+        let table = self.table.read().expect("Failed to acquire read lock");
+        table.num_live_records()
+    }
+
+    pub fn get_num_base_records(&self) -> i64 {
+        let table = self.table.read().expect("Failed to acquire read lock");
+        table.num_base_records()
+    }
+
+    pub fn get_num_tail_records(&self) -> i64 {
+        let table = self.table.read().expect("Failed to acquire read lock");
+        table.num_tail_records()
+    }
+
+    pub fn get_indexed_columns(&self) -> Vec<i64> {
+        let table = self.table.read().expect("Failed to acquire read lock");
+        table.indexed_columns()
+    }
+
+    /// Add a column at the end with `default` in every existing record, returns its index
+    pub fn add_column(&self, default: i64) -> usize {
+        let mut table = self.table.write().expect("Failed to acquire write lock");
+        table.add_column(default)
+    }
+
+    pub fn drop_column(&self, column: usize) -> Result<(), SchemaError> {
+        let mut table = self.table.write().expect("Failed to acquire write lock");
+        table.drop_column(column)
+    }
+
+    /// `order[i]` is the current index of the column that goes to position i
+    pub fn reorder_columns(&self, order: &[usize]) -> Result<(), SchemaError> {
+        let mut table = self.table.write().expect("Failed to acquire write lock");
+        table.reorder_columns(order)
+    }
+
+    /// Insert every line of a CSV file as a record, see the `bulk` module for the format
+    pub fn import_csv(
+        &self,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Result<ImportSummary, BulkError> {
+        bulk::import_csv(self, path, options)
+    }
+
+    pub fn import_jsonl(
+        &self,
+        path: &str,
+        options: &ImportOptions,
+    ) -> Result<ImportSummary, BulkError> {
+        bulk::import_jsonl(self, path, options)
+    }
+
+    /// Write a version of every record to a CSV file, returns how many were written
+    pub fn export_csv(&self, path: &str, options: &ExportOptions) -> Result<usize, BulkError> {
+        bulk::export_csv(self, path, options)
+    }
+
+    pub fn export_jsonl(&self, path: &str, options: &ExportOptions) -> Result<usize, BulkError> {
+        bulk::export_jsonl(self, path, options)
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<SchemaError> for PyErr {
+    fn from(err: SchemaError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RTableHandle {
+    #[pyo3(name = "write")]
+    fn py_write(&self, values: Vec<i64>) {
+        self.write(values)
+    }
+
+    #[pyo3(name = "read")]
+    fn py_read(&self, primary_key: i64) -> Option<Vec<i64>> {
+        self.read(primary_key)
+    }
+
+    #[pyo3(name = "delete")]
+    fn py_delete(&self, primary_key: i64) {
+        self.delete(primary_key)
+    }
+
+    #[pyo3(name = "debug_page_dir")]
+    fn py_debug_page_dir(&self) {
+        self.debug_page_dir()
+    }
+
+    #[getter(num_records)]
+    fn py_num_records(&self) -> i64 {
+        self.get_num_records()
+    }
+
+    #[getter(index)]
+    fn py_index(&self) -> RIndexHandle {
+        self.index()
+    }
+
+    #[getter(name)]
+    fn py_name(&self) -> String {
+        self.get_name()
+    }
+
+    #[getter(num_columns)]
+    fn py_num_columns(&self) -> usize {
+        self.get_num_columns()
+    }
+
+    #[getter(primary_key_column)]
+    fn py_primary_key_column(&self) -> usize {
+        self.get_primary_key_column()
+    }
+
+    #[getter(table_id)]
+    fn py_table_id(&self) -> i64 {
+        self.get_table_id()
+    }
+
+    #[getter(num_live_records)]
+    fn py_num_live_records(&self) -> usize {
+        self.get_num_live_records()
+    }
+
+    #[getter(num_base_records)]
+    fn py_num_base_records(&self) -> i64 {
+        self.get_num_base_records()
+    }
+
+    #[getter(num_tail_records)]
+    fn py_num_tail_records(&self) -> i64 {
+        self.get_num_tail_records()
+    }
+
+    #[getter(indexed_columns)]
+    fn py_indexed_columns(&self) -> Vec<i64> {
+        self.get_indexed_columns()
+    }
+
+    /// Add a column at the end with `default` in every existing record, returns its index
+    #[pyo3(name = "add_column", signature = (default=0))]
+    fn py_add_column(&self, default: i64) -> usize {
+        self.add_column(default)
+    }
+
+    #[pyo3(name = "drop_column")]
+    fn py_drop_column(&self, column: usize) -> PyResult<()> {
+        Ok(self.drop_column(column)?)
+    }
+
+    /// `order[i]` is the current index of the column that goes to position i
+    #[pyo3(name = "reorder_columns")]
+    fn py_reorder_columns(&self, order: Vec<usize>) -> PyResult<()> {
+        Ok(self.reorder_columns(&order)?)
+    }
+
+    /// `columns` maps header names to column indexes, on_duplicate is "abort" or "skip"
+    #[pyo3(
+        name = "import_csv",
+        signature = (path, delimiter=',', has_header=true, columns=None, batch_size=1000, on_duplicate="abort")
+    )]
+    fn py_import_csv(
+        &self,
+        path: &str,
+        delimiter: char,
+        has_header: bool,
+        columns: Option<HashMap<String, usize>>,
+        batch_size: usize,
+        on_duplicate: &str,
+    ) -> PyResult<ImportSummary> {
+        let options = ImportOptions {
+            delimiter,
+            has_header,
+            columns,
+            batch_size,
+            on_duplicate: py_on_duplicate(on_duplicate)?,
+        };
+        Ok(self.import_csv(path, &options)?)
+    }
+
+    #[pyo3(
+        name = "import_jsonl",
+        signature = (path, columns=None, batch_size=1000, on_duplicate="abort")
+    )]
+    fn py_import_jsonl(
+        &self,
+        path: &str,
+        columns: Option<HashMap<String, usize>>,
+        batch_size: usize,
+        on_duplicate: &str,
+    ) -> PyResult<ImportSummary> {
+        let options = ImportOptions {
+            columns,
+            batch_size,
+            on_duplicate: py_on_duplicate(on_duplicate)?,
+            ..ImportOptions::default()
+        };
+        Ok(self.import_jsonl(path, &options)?)
+    }
+
+    #[pyo3(
+        name = "export_csv",
+        signature = (path, delimiter=',', has_header=true, columns=None, relative_version=0)
+    )]
+    fn py_export_csv(
+        &self,
+        path: &str,
+        delimiter: char,
+        has_header: bool,
+        columns: Option<Vec<String>>,
+        relative_version: i64,
+    ) -> PyResult<usize> {
+        let options = ExportOptions {
+            delimiter,
+            has_header,
+            columns,
+            relative_version,
+        };
+        Ok(self.export_csv(path, &options)?)
+    }
+
+    #[pyo3(
+        name = "export_jsonl",
+        signature = (path, columns=None, relative_version=0)
+    )]
+    fn py_export_jsonl(
+        &self,
+        path: &str,
+        columns: Option<Vec<String>>,
+        relative_version: i64,
+    ) -> PyResult<usize> {
+        let options = ExportOptions {
+            columns,
+            relative_version,
+            ..ExportOptions::default()
+        };
+        Ok(self.export_jsonl(path, &options)?)
+    }
+}
+
+#[cfg(feature = "python")]
+fn py_on_duplicate(name: &str) -> PyResult<OnDuplicate> {
+    OnDuplicate::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unknown on_duplicate {}, use \"abort\" or \"skip\"",
+            name
+        ))
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::database::RDatabase;
+    use crate::query::RQuery;
 
     #[test]
     fn load_and_save_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Scores".to_string(), 3, 0);
+        let table_ref = db.create_table("Scores".to_string(), 3, 0).unwrap();
         let mut table = table_ref.table.write().unwrap();
 
         table.write(vec![0, 10, 12]);
@@ -429,9 +1120,11 @@ mod tests {
         table.write(vec![0, 10, 12]);
         table.write(vec![0, 10, 12]);
 
-        table.save_state();
+        let dir = "./test-outputs/load-and-save-table";
+        std::fs::create_dir_all(dir).unwrap();
+        table.save_state(dir).unwrap();
 
-        let new_table: RTable = table.load_state(0);
+        let new_table: RTable = table.load_state(dir, 0).unwrap();
 
         assert_eq!(table.name, new_table.name);
         assert_eq!(table.primary_key_column, new_table.primary_key_column);
@@ -441,10 +1134,126 @@ mod tests {
         assert_eq!(new_table.num_records, 4);
     }
 
+    #[test]
+    fn save_to_missing_dir_fails_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Scores".to_string(), 3, 0).unwrap();
+        let mut table = table_ref.table.write().unwrap();
+        table.write(vec![0, 10, 12]);
+
+        // A file where the directory should be makes every write fail
+        let dir = "./test-outputs/save-into-a-file";
+        std::fs::write(dir, b"not a directory").unwrap();
+
+        assert!(table.save_state(dir).is_err());
+    }
+
+    #[test]
+    fn schema_changes_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Grades".to_string(), 3, 0).unwrap();
+        let mut query = RQuery::new(table_ref.clone());
+        let select = |query: &mut RQuery, key, key_index, width| {
+            query.select(key, key_index, vec![1; width]).unwrap()[0]
+                .clone()
+                .unwrap()
+                .columns
+        };
+
+        query.insert(vec![1, 10, 100]);
+        query.insert(vec![2, 20, 200]);
+        query.update(1, vec![None, Some(11), None]);
+
+        // Existing records and their older versions get the default
+        assert_eq!(table_ref.add_column(5), 3);
+        assert_eq!(
+            select(&mut query, 1, 0, 4),
+            vec![Some(1), Some(11), Some(100), Some(5)]
+        );
+        assert_eq!(
+            query.select_version(1, 0, vec![1, 1, 1, 1], -1).unwrap()[0]
+                .clone()
+                .unwrap()
+                .columns,
+            vec![Some(1), Some(10), Some(100), Some(5)]
+        );
+
+        // New records and updates use the new column
+        query.insert(vec![3, 30, 300, 3000]);
+        query.update(2, vec![None, None, None, Some(2000)]);
+        assert_eq!(
+            select(&mut query, 2, 0, 4),
+            vec![Some(2), Some(20), Some(200), Some(2000)]
+        );
+
+        table_ref.index().create_index(2);
+        assert!(table_ref.drop_column(0).is_err());
+        assert!(table_ref.drop_column(4).is_err());
+        table_ref.drop_column(1).unwrap();
+        assert_eq!(
+            select(&mut query, 1, 0, 3),
+            vec![Some(1), Some(100), Some(5)]
+        );
+
+        // The primary key and the index on the old column 2 move with their columns
+        assert!(table_ref.reorder_columns(&[0, 0, 1]).is_err());
+        table_ref.reorder_columns(&[2, 1, 0]).unwrap();
+        assert_eq!(table_ref.get_primary_key_column(), 2);
+        assert_eq!(
+            select(&mut query, 3, 2, 3),
+            vec![Some(3000), Some(300), Some(3)]
+        );
+        assert_eq!(
+            table_ref
+                .index()
+                .get_secondary_indices()
+                .keys()
+                .collect::<Vec<_>>(),
+            vec![&1]
+        );
+        assert_eq!(
+            select(&mut query, 300, 1, 3),
+            vec![Some(3000), Some(300), Some(3)]
+        );
+    }
+
+    #[test]
+    fn describe_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Grades".to_string(), 3, 0).unwrap();
+        let mut query = RQuery::new(table_ref.clone());
+
+        query.insert(vec![1, 10, 100]);
+        query.insert(vec![2, 20, 200]);
+        query.insert(vec![3, 30, 300]);
+        query.update(1, vec![None, Some(11), None]);
+        query.update(1, vec![None, Some(12), None]);
+        query.delete(3);
+        table_ref.index().create_index(2);
+
+        let description = table_ref.table.read().unwrap().describe();
+        assert_eq!(
+            description,
+            TableDescription {
+                name: String::from("Grades"),
+                table_id: 0,
+                num_columns: 3,
+                primary_key_column: 0,
+                num_records: 2,
+                num_base_records: 3,
+                num_tail_records: 2,
+                indexed_columns: vec![0, 2],
+                disk_size: 0,
+            }
+        );
+        assert_eq!(table_ref.get_num_live_records(), 2);
+        assert_eq!(table_ref.get_num_tail_records(), 2);
+    }
+
     #[test]
     fn read_and_write_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Scores".to_string(), 3, 0);
+        let table_ref = db.create_table("Scores".to_string(), 3, 0).unwrap();
         let mut table = table_ref.table.write().unwrap();
 
         // Write
@@ -463,7 +1272,7 @@ mod tests {
     #[test]
     fn read_base_and_write_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Scores".to_string(), 3, 0);
+        let table_ref = db.create_table("Scores".to_string(), 3, 0).unwrap();
         let mut table = table_ref.table.write().unwrap();
 
         // Write
@@ -482,7 +1291,7 @@ mod tests {
     #[test]
     fn sum_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Scores".to_string(), 2, 0);
+        let table_ref = db.create_table("Scores".to_string(), 2, 0).unwrap();
         let mut table = table_ref.table.write().unwrap();
 
         table.write(vec![0, 10]);
@@ -503,7 +1312,7 @@ mod tests {
     #[test]
     fn large_sum_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Scores".to_string(), 2, 0);
+        let table_ref = db.create_table("Scores".to_string(), 2, 0).unwrap();
         let mut table = table_ref.table.write().unwrap();
 
         for x in 0..100_000 {
@@ -517,7 +1326,7 @@ mod tests {
     #[test]
     fn delete_test() {
         let mut db = RDatabase::new();
-        let table_ref = db.create_table("Scores".to_string(), 3, 0);
+        let table_ref = db.create_table("Scores".to_string(), 3, 0).unwrap();
         let mut table = table_ref.table.write().unwrap();
 
         // Write
@@ -526,8 +1335,154 @@ mod tests {
         assert_eq!(table.read_base(0).unwrap(), vec![0, 0, 0, 0, 0, 10, 12]);
 
         // Delete
-        table.delete(0);
+        table.delete(0, mvcc::autocommit_writer());
         // Read and find None
         assert_eq!(table.read_base(0), None);
     }
+
+    #[test]
+    fn snapshot_reads_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Snapshots".to_string(), 3, 0).unwrap();
+        let column = |values: Option<Vec<i64>>| values.unwrap()[NUM_RESERVED_COLUMNS as usize + 1];
+
+        RQuery::new(table_ref.clone()).insert(vec![1, 10, 0]);
+
+        // Transaction 900 updates the record but has not committed yet
+        RQuery::for_transaction(table_ref.clone(), 900).update(1, vec![None, Some(20), None]);
+
+        let before = Snapshot::new(901);
+        {
+            let table = table_ref.table.read().unwrap();
+            assert_eq!(column(table.read_at(1, &before)), 10);
+            assert_eq!(column(table.read_at(1, &Snapshot::new(900))), 20);
+        }
+
+        mvcc::commit(|ts| table_ref.table.write().unwrap().publish(900, ts));
+
+        // The old snapshot keeps its view, a new one sees the commit
+        let after = Snapshot::new(902);
+        {
+            let table = table_ref.table.read().unwrap();
+            assert_eq!(column(table.read_at(1, &before)), 10);
+            assert_eq!(column(table.read_at(1, &after)), 20);
+            assert_eq!(column(table.read_relative_at(1, -1, &after)), 10);
+        }
+
+        // Writes outside of a transaction are committed too, so older snapshots don't see them
+        RQuery::new(table_ref.clone()).update(1, vec![None, Some(30), None]);
+        {
+            let table = table_ref.table.read().unwrap();
+            assert_eq!(column(table.read_at(1, &after)), 20);
+            assert_eq!(column(table.read_at(1, &Snapshot::new(904))), 30);
+        }
+
+        // Records inserted by a transaction that has not committed don't exist yet
+        RQuery::for_transaction(table_ref.clone(), 905).insert(vec![2, 0, 0]);
+        let table = table_ref.table.read().unwrap();
+        assert_eq!(table.read_at(2, &Snapshot::new(906)), None);
+        assert!(table.read_at(2, &Snapshot::new(905)).is_some());
+    }
+
+    #[test]
+    fn snapshot_reads_deleted_record_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Snapshots".to_string(), 3, 0).unwrap();
+        let column = |values: Option<Vec<i64>>| values.unwrap()[NUM_RESERVED_COLUMNS as usize + 1];
+
+        let mut query = RQuery::new(table_ref.clone());
+        query.insert(vec![1, 10, 0]);
+        query.update(1, vec![None, Some(20), None]);
+
+        let before = Snapshot::new(910);
+        query.delete(1);
+        // The key can be taken again right away
+        query.insert(vec![1, 30, 0]);
+
+        let table = table_ref.table.read().unwrap();
+        assert_eq!(column(table.read_at(1, &before)), 20);
+        assert_eq!(column(table.read_relative_at(1, -1, &before)), 10);
+        assert_eq!(table.sum_at(1, 1, 1, 0, &before), Some(20));
+        assert_eq!(column(table.read_at(1, &Snapshot::new(911))), 30);
+    }
+
+    #[test]
+    fn snapshot_reads_rekeyed_record_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Snapshots".to_string(), 3, 0).unwrap();
+        let column = |values: Option<Vec<i64>>| values.unwrap()[NUM_RESERVED_COLUMNS as usize + 1];
+
+        let mut query = RQuery::new(table_ref.clone());
+        query.insert(vec![1, 10, 0]);
+
+        let before = Snapshot::new(920);
+        query.update(1, vec![Some(2), Some(20), None]);
+
+        let table = table_ref.table.read().unwrap();
+        assert_eq!(column(table.read_at(1, &before)), 10);
+        assert_eq!(table.read_at(2, &before), None);
+
+        let after = Snapshot::new(921);
+        assert_eq!(table.read_at(1, &after), None);
+        assert_eq!(column(table.read_at(2, &after)), 20);
+        assert_eq!(column(table.read_relative_at(2, -1, &after)), 10);
+        assert_eq!(column(table.read(2)), 20);
+    }
+
+    #[test]
+    fn snapshot_reads_across_schema_changes_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Snapshots".to_string(), 2, 0).unwrap();
+        let columns =
+            |values: Option<Vec<i64>>| values.unwrap()[NUM_RESERVED_COLUMNS as usize..].to_vec();
+
+        let mut query = RQuery::new(table_ref.clone());
+        query.insert(vec![1, 10]);
+        query.insert(vec![2, 20]);
+
+        let before = Snapshot::new(940);
+        query.delete(1);
+
+        // The deleted record gets the new column like every other one
+        table_ref.add_column(7);
+        {
+            let table = table_ref.table.read().unwrap();
+            assert_eq!(columns(table.read_at(1, &before)), vec![1, 10, 7]);
+            assert_eq!(table.sum_at(0, 5, 2, 0, &before), Some(14));
+        }
+
+        table_ref.reorder_columns(&[2, 0, 1]).unwrap();
+        let table = table_ref.table.read().unwrap();
+        assert_eq!(columns(table.read_at(1, &before)), vec![7, 1, 10]);
+        assert_eq!(table.sum_at(0, 5, 2, 0, &before), Some(30));
+        assert_eq!(table.read_at(1, &Snapshot::new(941)), None);
+    }
+
+    #[test]
+    fn history_is_kept_for_live_snapshots_test() {
+        let mut db = RDatabase::new();
+        let table_ref = db.create_table("Snapshots".to_string(), 3, 0).unwrap();
+
+        let mut query = RQuery::new(table_ref.clone());
+        query.insert(vec![1, 10, 0]);
+
+        let snapshot = Snapshot::new(930);
+        query.update(1, vec![None, Some(20), None]);
+        query.delete(1);
+        // Commits after the change prune, but not what the snapshot still needs
+        query.insert(vec![2, 0, 0]);
+
+        let tail_rid = 1;
+        let mut table = table_ref.table.write().unwrap();
+        assert!(table.versions.stamp(tail_rid).is_some());
+        assert_eq!(table.versions.former_records(1, &snapshot).len(), 1);
+
+        // Other tests may hold snapshots too, so prune as if this one was the last
+        let old = Snapshot::at(snapshot.start_ts, 930);
+        drop(snapshot);
+        table.versions.prune(mvcc::snapshot_ts());
+
+        assert_eq!(table.versions.stamp(tail_rid), None);
+        assert!(table.versions.former_records(1, &old).is_empty());
+    }
 }
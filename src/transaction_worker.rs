@@ -1,26 +1,242 @@
 use super::transaction::RTransaction;
+use super::worker_pool::{FinishOnDrop, PendingJobs, RWorkerPool};
 use log::debug;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 use std::collections::VecDeque;
-use std::thread::{self, JoinHandle};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-#[pyclass]
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 10;
+pub const DEFAULT_INITIAL_BACKOFF_MS: u64 = 1;
+pub const DEFAULT_MAX_BACKOFF_MS: u64 = 50;
+
+/// How often and how patiently an aborted transaction gets run again
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// How many times a transaction runs in total before the worker gives up on it
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff_ms: DEFAULT_INITIAL_BACKOFF_MS,
+            max_backoff_ms: DEFAULT_MAX_BACKOFF_MS,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait before the given retry (starting at 1)
+    ///
+    /// The wait doubles every retry up to `max_backoff_ms`. Up to half of it is jitter taken from
+    /// the transaction id, so two transactions that keep conflicting don't keep retrying in step.
+    pub fn backoff(&self, retry: u32, transaction_id: i64) -> Duration {
+        let exp = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << (retry - 1).min(32))
+            .min(self.max_backoff_ms);
+
+        let half = exp / 2;
+        let jitter = if half == 0 {
+            0
+        } else {
+            // Cheap mix of the id and the retry so every transaction gets its own jitter
+            let mut x = (transaction_id as u64) ^ ((retry as u64) << 32);
+            x ^= x >> 33;
+            x = x.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+            x ^= x >> 33;
+            x % (half + 1)
+        };
+
+        Duration::from_millis(exp - half + jitter)
+    }
+}
+
+/// Counters shared by a worker and the threads running its transactions
+#[derive(Debug, Default)]
+pub struct WorkerStats {
+    /// Transactions that eventually committed
+    pub committed: AtomicU64,
+    /// Transactions that still aborted on their last attempt
+    pub aborted: AtomicU64,
+    /// Attempts after the first one, over all transactions
+    pub retries: AtomicU64,
+}
+
+/// Run one attempt (starting at 1) of a transaction, returns how long to back off before the next
+/// one if it aborted and has attempts left
+pub fn run_attempt(
+    transaction: &RTransaction,
+    attempt: u32,
+    policy: &RetryPolicy,
+    stats: &WorkerStats,
+) -> Option<Duration> {
+    // RTransaction::run consumes its queries, so every attempt runs a fresh copy
+    let mut t = transaction.clone();
+
+    if t.run() {
+        stats.committed.fetch_add(1, Ordering::Relaxed);
+        return None;
+    }
+
+    if attempt >= policy.max_attempts {
+        debug!(
+            "Transaction {} aborted after {} attempts",
+            transaction.transaction_id(),
+            attempt
+        );
+        stats.aborted.fetch_add(1, Ordering::Relaxed);
+        return None;
+    }
+
+    stats.retries.fetch_add(1, Ordering::Relaxed);
+    Some(policy.backoff(attempt, transaction.transaction_id()))
+}
+
+// A transaction on its way through the pool, it counts as pending until it is dropped
+struct Attempt {
+    transaction: RTransaction,
+    attempt: u32,
+    policy: RetryPolicy,
+    stats: Arc<WorkerStats>,
+    pool: RWorkerPool,
+    _guard: FinishOnDrop,
+}
+
+impl Attempt {
+    // An aborted attempt goes back to the pool after its backoff, so no thread sleeps through it
+    fn run(mut self) {
+        debug!("Started {:?}", thread::current().id());
+        let backoff = run_attempt(&self.transaction, self.attempt, &self.policy, &self.stats);
+        debug!("Finished {:?}", thread::current().id());
+
+        if let Some(backoff) = backoff {
+            self.attempt += 1;
+            let pool = self.pool.clone();
+            pool.submit_after(backoff, move || self.run());
+        }
+    }
+}
+
+#[cfg_attr(feature = "python", pyclass)]
 pub struct RTransactionWorker {
     transactions: VecDeque<RTransaction>,
-    handles: Vec<JoinHandle<()>>,
+    policy: RetryPolicy,
+    stats: Arc<WorkerStats>,
+    /// The threads that run the transactions, shared with other workers
+    pool: RWorkerPool,
+    /// Transactions handed to the pool that have not finished yet
+    pending: Arc<PendingJobs>,
+}
+
+impl Default for RTransactionWorker {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_MAX_ATTEMPTS,
+            DEFAULT_INITIAL_BACKOFF_MS,
+            DEFAULT_MAX_BACKOFF_MS,
+            None,
+        )
+    }
 }
 
-#[pymethods]
 impl RTransactionWorker {
-    #[new]
-    pub fn new() -> Self {
+    /// Queue a transaction from Rust, add_transaction does the same for the Python wrapper
+    pub fn push_transaction(&mut self, t: RTransaction) {
+        self.transactions.push_back(t);
+    }
+
+    /// Wait for the transactions from Rust, join does the same for the Python wrapper
+    pub fn wait(&self) {
+        self.pending.wait();
+    }
+
+    pub fn new(
+        max_attempts: u32,
+        initial_backoff_ms: u64,
+        max_backoff_ms: u64,
+        pool: Option<RWorkerPool>,
+    ) -> Self {
         RTransactionWorker {
             transactions: VecDeque::new(),
-            handles: Vec::new(),
+            policy: RetryPolicy {
+                // A transaction always gets run at least once
+                max_attempts: max_attempts.max(1),
+                initial_backoff_ms,
+                max_backoff_ms,
+            },
+            stats: Arc::new(WorkerStats::default()),
+            // Without a pool of their own, all workers share one sized to the machine
+            pool: pool.unwrap_or_else(RWorkerPool::global),
+            pending: Arc::new(PendingJobs::default()),
         }
     }
 
-    pub fn add_transaction(&mut self, t: PyObject) {
+    /// Hand every queued transaction to the pool and return right away
+    pub fn run(&mut self) {
+        debug!(
+            "Started run on transaction_worker! Transactions: {}",
+            self.transactions.len()
+        );
+
+        while let Some(transaction) = self.transactions.pop_front() {
+            self.pending.start();
+            let attempt = Attempt {
+                transaction,
+                attempt: 1,
+                policy: self.policy,
+                stats: self.stats.clone(),
+                pool: self.pool.clone(),
+                _guard: FinishOnDrop(self.pending.clone()),
+            };
+
+            self.pool.submit(move || attempt.run());
+        }
+    }
+
+    /// Number of transactions that committed
+    pub fn committed(&self) -> u64 {
+        self.stats.committed.load(Ordering::Relaxed)
+    }
+
+    /// Number of transactions that were still aborting after the last attempt
+    pub fn aborted(&self) -> u64 {
+        self.stats.aborted.load(Ordering::Relaxed)
+    }
+
+    /// Number of times any transaction was run again after an abort
+    pub fn retries(&self) -> u64 {
+        self.stats.retries.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RTransactionWorker {
+    #[new]
+    #[pyo3(signature = (
+        max_attempts=DEFAULT_MAX_ATTEMPTS,
+        initial_backoff_ms=DEFAULT_INITIAL_BACKOFF_MS,
+        max_backoff_ms=DEFAULT_MAX_BACKOFF_MS,
+        pool=None
+    ))]
+    fn py_new(
+        max_attempts: u32,
+        initial_backoff_ms: u64,
+        max_backoff_ms: u64,
+        pool: Option<RWorkerPool>,
+    ) -> Self {
+        RTransactionWorker::new(max_attempts, initial_backoff_ms, max_backoff_ms, pool)
+    }
+
+    fn add_transaction(&mut self, t: PyObject) {
         // TODO: Find a way to do this better
         // It might be find since adding a transaction only happens a few times
         Python::with_gil(|py| {
@@ -43,43 +259,164 @@ impl RTransactionWorker {
         })
     }
 
-    pub fn run(&mut self) {
-        debug!(
-            "Started run on transaction_worker! Transactions: {}",
-            self.transactions.len()
-        );
+    #[pyo3(name = "run")]
+    fn py_run(&mut self) {
+        self.run()
+    }
 
-        // TODO: Keep the count of the number of threads in use
-        // Only run a transaction if there is a thread ready to do so
+    /// Wait until every transaction this worker ran has committed or given up
+    fn join(&self, py: Python<'_>) {
+        let pending = self.pending.clone();
 
-        while self.transactions.len() > 0 {
-            let transaction = self.transactions.pop_front();
+        // Let other Python threads run while we wait
+        py.allow_threads(move || pending.wait());
+    }
 
-            // TODO: Limit threads to total_threads - 1
-            // TODO: Don't spawn new threads when needed, spawn at the start and later assign them
-            let handle = thread::spawn(|| {
-                debug!("Started {:?}", thread::current().id());
+    #[getter(committed)]
+    fn py_committed(&self) -> u64 {
+        self.committed()
+    }
 
-                // TODO: I am going to need to push running threads into a new vec
-                // so that .join can check to see if all of the threads are done
-                if let Some(mut t) = transaction {
-                    t.run();
-                }
+    #[getter(aborted)]
+    fn py_aborted(&self) -> u64 {
+        self.aborted()
+    }
+
+    #[getter(retries)]
+    fn py_retries(&self) -> u64 {
+        self.retries()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::RDatabase;
+
+    #[test]
+    fn backoff_doubles_up_to_max_test() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff_ms: 4,
+            max_backoff_ms: 20,
+        };
+
+        for id in 0..20 {
+            let first = policy.backoff(1, id).as_millis();
+            assert!((2..=4).contains(&first));
 
-                debug!("Finished {:?}", thread::current().id());
-            });
+            let second = policy.backoff(2, id).as_millis();
+            assert!((4..=8).contains(&second));
 
-            self.handles.push(handle);
+            let capped = policy.backoff(30, id).as_millis();
+            assert!((10..=20).contains(&capped));
         }
     }
 
-    pub fn join(&mut self) {
-        while self.handles.len() > 0 {
-            let handle = self.handles.pop();
+    #[test]
+    fn worker_counts_commits_test() {
+        let mut db = RDatabase::new();
+        let table = db.create_table(String::from("Workers"), 3, 0).unwrap();
 
-            if let Some(h) = handle {
-                let _ = h.join();
+        let mut worker = RTransactionWorker::default();
+        for pk in 0..20 {
+            let mut t = RTransaction::new();
+            t.add_query("insert", table.clone(), vec![Some(pk), Some(1), Some(2)])
+                .unwrap();
+            worker.push_transaction(t);
+        }
+
+        worker.run();
+        worker.wait();
+
+        assert_eq!(worker.committed(), 20);
+        assert_eq!(worker.aborted(), 0);
+    }
+
+    #[test]
+    fn worker_gives_up_after_max_attempts_test() {
+        let mut db = RDatabase::new();
+        let table = db.create_table(String::from("Workers"), 3, 0).unwrap();
+
+        let mut first = RTransaction::new();
+        first
+            .add_query("insert", table.clone(), vec![Some(1), Some(1), Some(1)])
+            .unwrap();
+        assert!(first.run());
+
+        // A duplicate primary key aborts every time, no matter how often it is retried
+        let mut duplicate = RTransaction::new();
+        duplicate
+            .add_query("insert", table.clone(), vec![Some(1), Some(2), Some(2)])
+            .unwrap();
+
+        let mut worker = RTransactionWorker::new(3, 0, 0, None);
+        worker.push_transaction(duplicate);
+        worker.run();
+        worker.wait();
+
+        assert_eq!(worker.committed(), 0);
+        assert_eq!(worker.aborted(), 1);
+        assert_eq!(worker.retries(), 2);
+    }
+
+    #[test]
+    fn workers_share_one_pool_test() {
+        let mut db = RDatabase::new();
+        let table = db.create_table(String::from("Workers"), 3, 0).unwrap();
+        let pool = RWorkerPool::new(Some(2));
+
+        let mut workers: Vec<RTransactionWorker> = (0..4)
+            .map(|_| RTransactionWorker::new(5, 0, 1, Some(pool.clone())))
+            .collect();
+
+        for (i, worker) in workers.iter_mut().enumerate() {
+            for j in 0..50 {
+                let mut t = RTransaction::new();
+                let pk = (i * 1000 + j) as i64;
+                t.add_query("insert", table.clone(), vec![Some(pk), Some(1), Some(2)])
+                    .unwrap();
+                worker.push_transaction(t);
             }
         }
+
+        // run only hands the transactions to the pool, so all four workers run at once
+        for worker in workers.iter_mut() {
+            worker.run();
+        }
+
+        for worker in &workers {
+            worker.wait();
+            assert_eq!(worker.committed() + worker.aborted(), 50);
+        }
+    }
+
+    #[test]
+    fn contended_updates_commit_with_waiting_strategies_test() {
+        for strategy in ["wait_die", "wound_wait", "timeout"] {
+            let mut db = RDatabase::new();
+            db.set_deadlock_strategy(strategy, Some(50)).unwrap();
+            let table = db.create_table(String::from("Counters"), 2, 0).unwrap();
+
+            let mut first = RTransaction::new();
+            first
+                .add_query("insert", table.clone(), vec![Some(1), Some(0)])
+                .unwrap();
+            assert!(first.run());
+
+            // Every transaction wants the same record
+            let mut worker = RTransactionWorker::new(100, 1, 10, Some(RWorkerPool::new(Some(4))));
+            for _ in 0..40 {
+                let mut t = RTransaction::new();
+                t.add_query("increment", table.clone(), vec![Some(1), Some(1)])
+                    .unwrap();
+                worker.push_transaction(t);
+            }
+
+            worker.run();
+            worker.wait();
+
+            assert_eq!(worker.committed(), 40, "{} lost transactions", strategy);
+        }
     }
 }
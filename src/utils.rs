@@ -41,15 +41,12 @@ pub fn decode_bytes_from_ints(ints: Vec<i64>) -> Vec<u8> {
         }
     }
 
-    return out_ints;
+    out_ints
 }
 
 pub fn decode_string_from_ints(ints: Vec<i64>) -> String {
     let out_ints = decode_bytes_from_ints(ints);
 
     let s = String::from_utf8(out_ints);
-    match s {
-        Ok(a) => a,
-        Err(_) => String::new(),
-    }
+    s.unwrap_or_default()
 }
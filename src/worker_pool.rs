@@ -0,0 +1,338 @@
+use log::debug;
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct PoolInner {
+    sender: Mutex<Option<Sender<Job>>>,
+    /// Jobs that may only run after some time, the timer thread queues them when they are due
+    delayed: Mutex<Option<Sender<(Instant, Job)>>>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    num_threads: usize,
+}
+
+impl Drop for PoolInner {
+    fn drop(&mut self) {
+        // The timer hands over what it still holds before it stops, so it goes first. Closing
+        // the channels makes every thread stop once its queue is empty.
+        self.delayed.lock().unwrap().take();
+        self.sender.lock().unwrap().take();
+
+        // A job can hold the last handle to its own pool, that thread stops on its own
+        for handle in self.threads.lock().unwrap().drain(..) {
+            if handle.thread().id() != thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+    }
+}
+
+/// A fixed number of threads that run whatever gets submitted to them
+///
+/// Cloning a pool gives another handle to the same threads, so many transaction workers can share
+/// one pool instead of each spawning their own threads.
+#[cfg_attr(feature = "python", pyclass)]
+#[derive(Clone)]
+pub struct RWorkerPool {
+    inner: Arc<PoolInner>,
+}
+
+/// The number of threads a pool gets when nobody says otherwise
+pub fn default_num_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+impl RWorkerPool {
+    /// The pool that workers use when they are not given one
+    pub fn global() -> RWorkerPool {
+        static GLOBAL: OnceLock<RWorkerPool> = OnceLock::new();
+
+        GLOBAL.get_or_init(|| RWorkerPool::new(None)).clone()
+    }
+
+    /// Queue a job, it runs as soon as one of the threads is free
+    pub fn submit(&self, job: impl FnOnce() + Send + 'static) {
+        let sender = self.inner.sender.lock().unwrap();
+
+        sender
+            .as_ref()
+            .expect("Should only submit to a running pool.")
+            .send(Box::new(job))
+            .expect("Should have threads to receive jobs.");
+    }
+
+    /// Queue a job once `delay` is over, no thread of the pool is busy until then
+    pub fn submit_after(&self, delay: Duration, job: impl FnOnce() + Send + 'static) {
+        let delayed = self.inner.delayed.lock().unwrap();
+
+        delayed
+            .as_ref()
+            .expect("Should only submit to a running pool.")
+            .send((Instant::now() + delay, Box::new(job)))
+            .expect("Should have a timer to receive jobs.");
+    }
+
+    // Keep the delayed jobs in the order they are due and queue each one when its time comes
+    fn time(receiver: Receiver<(Instant, Job)>, sender: Sender<Job>) {
+        // The counter keeps jobs that are due at the same instant apart
+        let mut waiting: BTreeMap<(Instant, u64), Job> = BTreeMap::new();
+        let mut received = 0u64;
+        let mut open = true;
+
+        while open || !waiting.is_empty() {
+            let now = Instant::now();
+            while let Some(entry) = waiting.first_entry() {
+                if entry.key().0 > now {
+                    break;
+                }
+                let _ = sender.send(entry.remove());
+            }
+
+            let next_due = waiting.keys().next().map(|&(due, _)| due - now);
+            let job = match (open, next_due) {
+                (true, Some(wait)) => receiver.recv_timeout(wait),
+                (true, None) => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                // Nothing new can come, the rest only has to become due
+                (false, wait) => {
+                    thread::sleep(wait.unwrap_or_default());
+                    continue;
+                }
+            };
+
+            match job {
+                Ok((due, job)) => {
+                    waiting.insert((due, received), job);
+                    received += 1;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => open = false,
+            }
+        }
+
+        debug!("Worker pool timer stopping");
+    }
+
+    fn work(id: usize, receiver: Arc<Mutex<Receiver<Job>>>) {
+        loop {
+            // Only hold the lock while taking a job, not while running it
+            let job = receiver.lock().unwrap().recv();
+
+            let Ok(job) = job else {
+                debug!("Worker pool thread {} stopping", id);
+                return;
+            };
+
+            // One bad job should not take a thread away from everyone else
+            if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                debug!("Job on worker pool thread {} panicked", id);
+            }
+        }
+    }
+
+    pub fn new(num_threads: Option<usize>) -> Self {
+        let num_threads = num_threads.unwrap_or_else(default_num_threads).max(1);
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let (delayed, delayed_receiver) = mpsc::channel();
+        let timer = {
+            let sender = sender.clone();
+            thread::spawn(move || RWorkerPool::time(delayed_receiver, sender))
+        };
+
+        let threads = std::iter::once(timer)
+            .chain((0..num_threads).map(|id| {
+                let receiver = receiver.clone();
+                thread::spawn(move || RWorkerPool::work(id, receiver))
+            }))
+            .collect();
+
+        RWorkerPool {
+            inner: Arc::new(PoolInner {
+                sender: Mutex::new(Some(sender)),
+                delayed: Mutex::new(Some(delayed)),
+                threads: Mutex::new(threads),
+                num_threads,
+            }),
+        }
+    }
+
+    pub fn num_threads(&self) -> usize {
+        self.inner.num_threads
+    }
+}
+
+#[cfg(feature = "python")]
+#[pymethods]
+impl RWorkerPool {
+    #[new]
+    #[pyo3(signature = (num_threads=None))]
+    fn py_new(num_threads: Option<usize>) -> Self {
+        RWorkerPool::new(num_threads)
+    }
+
+    #[getter(num_threads)]
+    fn py_num_threads(&self) -> usize {
+        self.num_threads()
+    }
+}
+
+/// Counts jobs that were submitted but have not finished yet, so a caller can wait for them
+#[derive(Default)]
+pub struct PendingJobs {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl PendingJobs {
+    pub fn start(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    pub fn finish(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count -= 1;
+
+        if *count == 0 {
+            self.done.notify_all();
+        }
+    }
+
+    /// Block until every started job has finished
+    pub fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+
+        while *count > 0 {
+            count = self.done.wait(count).unwrap();
+        }
+    }
+}
+
+/// Calls `PendingJobs::finish` when dropped, so a job that panics still counts as finished
+pub struct FinishOnDrop(pub Arc<PendingJobs>);
+
+impl Drop for FinishOnDrop {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn pool_runs_every_job_test() {
+        let pool = RWorkerPool::new(Some(4));
+        let pending = Arc::new(PendingJobs::default());
+        let counter = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..1000 {
+            let counter = counter.clone();
+            let guard = FinishOnDrop(pending.clone());
+            pending.start();
+
+            pool.submit(move || {
+                let _guard = guard;
+                counter.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        pending.wait();
+        assert_eq!(counter.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn pool_uses_fixed_number_of_threads_test() {
+        let pool = RWorkerPool::new(Some(2));
+        let pending = Arc::new(PendingJobs::default());
+        let thread_ids = Arc::new(Mutex::new(HashSet::new()));
+
+        for _ in 0..200 {
+            let thread_ids = thread_ids.clone();
+            let guard = FinishOnDrop(pending.clone());
+            pending.start();
+
+            pool.submit(move || {
+                let _guard = guard;
+                thread_ids.lock().unwrap().insert(thread::current().id());
+            });
+        }
+
+        pending.wait();
+        assert!(thread_ids.lock().unwrap().len() <= 2);
+        assert_eq!(pool.num_threads(), 2);
+    }
+
+    #[test]
+    fn delayed_jobs_leave_threads_free_test() {
+        let pool = RWorkerPool::new(Some(1));
+        let pending = Arc::new(PendingJobs::default());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+
+        for (name, delay) in [("late", 60), ("soon", 20)] {
+            let order = order.clone();
+            let guard = FinishOnDrop(pending.clone());
+            pending.start();
+
+            pool.submit_after(Duration::from_millis(delay), move || {
+                let _guard = guard;
+                order.lock().unwrap().push((name, start.elapsed()));
+            });
+        }
+
+        // The only thread is not waiting for the delayed jobs
+        let order_clone = order.clone();
+        let guard = FinishOnDrop(pending.clone());
+        pending.start();
+        pool.submit(move || {
+            let _guard = guard;
+            order_clone.lock().unwrap().push(("now", start.elapsed()));
+        });
+
+        pending.wait();
+        let order = order.lock().unwrap();
+        let names: Vec<&str> = order.iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, vec!["now", "soon", "late"]);
+        assert!(order[1].1 >= Duration::from_millis(20));
+        assert!(order[2].1 >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn pool_survives_panicking_job_test() {
+        let pool = RWorkerPool::new(Some(1));
+        let pending = Arc::new(PendingJobs::default());
+
+        let guard = FinishOnDrop(pending.clone());
+        pending.start();
+        pool.submit(move || {
+            let _guard = guard;
+            panic!("Job failed");
+        });
+
+        let ran = Arc::new(AtomicUsize::new(0));
+        let ran_clone = ran.clone();
+        let guard = FinishOnDrop(pending.clone());
+        pending.start();
+        pool.submit(move || {
+            let _guard = guard;
+            ran_clone.fetch_add(1, Ordering::Relaxed);
+        });
+
+        pending.wait();
+        assert_eq!(ran.load(Ordering::Relaxed), 1);
+    }
+}
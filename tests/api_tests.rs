@@ -0,0 +1,169 @@
+use redoxql::api::{Database, Error, Operation, QueryResult, SqlOutput, Transaction};
+use redoxql::database::DatabaseError;
+
+#[test]
+fn query_errors_test() {
+    let mut db = Database::new();
+    let grades = db.create_table("Grades", 3, 0).unwrap();
+    let mut q = grades.query();
+
+    q.insert(&[1, 10, 100]).unwrap();
+    q.insert(&[2, 20, 200]).unwrap();
+
+    assert!(matches!(q.insert(&[1, 0, 0]), Err(Error::DuplicateKey(1))));
+    assert!(matches!(
+        q.insert(&[3, 0]),
+        Err(Error::WrongProjection {
+            expected: 3,
+            found: 2
+        })
+    ));
+    assert!(matches!(
+        q.update(1, &[Some(2), None, None]),
+        Err(Error::DuplicateKey(2))
+    ));
+    assert!(matches!(
+        q.update(5, &[None, Some(0), None]),
+        Err(Error::NoSuchRecord(5))
+    ));
+    assert!(matches!(q.delete(5), Err(Error::NoSuchRecord(5))));
+    assert!(matches!(q.select(1, 7, &[1, 1, 1]), Err(Error::Schema(_))));
+
+    q.increment(1, 1).unwrap();
+    assert_eq!(q.sum(1, 2, 1).unwrap(), 31);
+    assert_eq!(
+        q.select_version(1, &[1, 1, 1], -1).unwrap(),
+        vec![vec![Some(1), Some(10), Some(100)]]
+    );
+
+    q.delete(2).unwrap();
+    assert!(q.select(2, 0, &[1, 1, 1]).unwrap().is_empty());
+    assert_eq!(grades.num_records(), 1);
+}
+
+#[test]
+fn transaction_test() {
+    let mut db = Database::new();
+    let accounts = db.create_table("Accounts", 2, 0).unwrap();
+
+    let mut t = Transaction::new();
+    t.add(&accounts, Operation::Insert(vec![1, 100])).unwrap();
+    t.add(
+        &accounts,
+        Operation::Increment {
+            primary_key: 1,
+            column: 1,
+        },
+    )
+    .unwrap();
+    assert_eq!(t.run().unwrap().len(), 2);
+
+    // Records are locked by primary key, so selects can't search another column
+    let by_balance = Operation::Select {
+        key: 101,
+        key_index: 1,
+        projected: vec![1, 1],
+    };
+    assert!(matches!(
+        t.add(&accounts, by_balance.clone()),
+        Err(Error::Operation(_))
+    ));
+
+    let mut t = Transaction::new();
+    assert!(matches!(t.commit(), Err(Error::NotActive(_))));
+    t.begin().unwrap();
+    assert!(matches!(t.begin(), Err(Error::AlreadyActive(_))));
+
+    let select = Operation::Select {
+        key: 1,
+        key_index: 0,
+        projected: vec![1, 1],
+    };
+    match t.execute(&accounts, select).unwrap() {
+        QueryResult::Records(records) => assert_eq!(records[0].columns, vec![Some(1), Some(101)]),
+        other => panic!("Expected records, got {:?}", other),
+    }
+
+    t.savepoint("before").unwrap();
+    assert!(matches!(
+        t.execute(&accounts, Operation::Insert(vec![1, 0])),
+        Err(Error::QueryFailed {
+            operation: "insert",
+            aborted: false
+        })
+    ));
+    t.rollback_to_savepoint("before").unwrap();
+    assert!(matches!(
+        t.execute(&accounts, by_balance),
+        Err(Error::Operation(_))
+    ));
+    assert!(t.is_active());
+    assert!(matches!(
+        t.release_savepoint("missing"),
+        Err(Error::NoSuchSavepoint(_))
+    ));
+    t.commit().unwrap();
+    assert!(!t.is_active());
+
+    // Dropping a transaction that was not finished aborts it and frees its locks
+    let mut t = Transaction::new();
+    t.begin().unwrap();
+    let increment = Operation::Increment {
+        primary_key: 1,
+        column: 1,
+    };
+    t.execute(&accounts, increment.clone()).unwrap();
+    drop(t);
+
+    let mut t = Transaction::new();
+    t.begin().unwrap();
+    t.execute(&accounts, increment).unwrap();
+    t.commit().unwrap();
+    assert_eq!(accounts.query().sum(1, 1, 1).unwrap(), 102);
+}
+
+#[test]
+fn database_errors_test() {
+    let mut db = Database::new();
+    db.create_table("Scores", 2, 0).unwrap();
+
+    assert!(matches!(
+        db.create_table("Scores", 3, 0),
+        Err(Error::Database(DatabaseError::TableExists(_)))
+    ));
+    assert_eq!(db.list_tables(), vec!["Scores"]);
+    assert!(matches!(db.table("Missing"), Err(Error::Database(_))));
+    assert!(db.rename_table("Scores", "Scores").is_err());
+    db.rename_table("Scores", "Points").unwrap();
+    assert_eq!(db.list_tables(), vec!["Points"]);
+    assert_eq!(db.table("Points").unwrap().id(), 0);
+    db.drop_table("Points").unwrap();
+    assert!(db.drop_table("Points").is_err());
+}
+
+#[test]
+fn sql_test() {
+    let mut db = Database::new();
+    db.execute("CREATE TABLE Grades (c0 INT PRIMARY KEY, c1 INT)")
+        .unwrap();
+    db.execute("INSERT INTO Grades VALUES (1, 10), (2, 20)")
+        .unwrap();
+
+    // Statements and the query API see the same records
+    let grades = db.table("Grades").unwrap();
+    grades.query().update(1, &[None, Some(15)]).unwrap();
+    assert_eq!(
+        db.execute("SELECT SUM(c1) FROM Grades").unwrap(),
+        SqlOutput::Sum(35)
+    );
+
+    assert!(matches!(
+        db.execute("INSERT INTO Grades VALUES (2, 0)"),
+        Err(Error::DuplicateKey(2))
+    ));
+    assert!(matches!(
+        db.execute("SELECT * FROM Missing"),
+        Err(Error::Database(_))
+    ));
+    assert!(matches!(db.execute("SELECT"), Err(Error::Sql(_))));
+}
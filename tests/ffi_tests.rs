@@ -0,0 +1,162 @@
+use redoxql::ffi::*;
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+fn last_error() -> String {
+    unsafe { CStr::from_ptr(redoxql_last_error()) }
+        .to_string_lossy()
+        .to_string()
+}
+
+#[test]
+fn queries_through_c_api_test() {
+    unsafe {
+        let db = redoxql_database_new();
+        let name = CString::new("Grades").unwrap();
+        let mut table = ptr::null_mut();
+
+        assert_eq!(
+            redoxql_create_table(db, name.as_ptr(), 3, 0, &mut table),
+            RedoxStatus::Ok
+        );
+        assert_eq!(redoxql_table_num_columns(table), 3);
+
+        let mut duplicate_table = ptr::null_mut();
+        assert_eq!(
+            redoxql_create_table(db, name.as_ptr(), 2, 0, &mut duplicate_table),
+            RedoxStatus::TableExists
+        );
+        assert!(duplicate_table.is_null());
+        assert_eq!(last_error(), "A table named Grades already exists");
+
+        for pk in 0..5 {
+            let values = [pk, pk * 10, 7];
+            assert_eq!(redoxql_insert(table, values.as_ptr(), 3), RedoxStatus::Ok);
+        }
+        let duplicate = [1, 0, 0];
+        assert_eq!(
+            redoxql_insert(table, duplicate.as_ptr(), 3),
+            RedoxStatus::DuplicateKey
+        );
+        assert_eq!(last_error(), "A record with key 1 already exists");
+
+        let values = [0, 99, 0];
+        let present = [false, true, false];
+        assert_eq!(
+            redoxql_update(table, 2, values.as_ptr(), present.as_ptr(), 3),
+            RedoxStatus::Ok
+        );
+        assert_eq!(redoxql_delete(table, 4), RedoxStatus::Ok);
+        assert_eq!(redoxql_delete(table, 4), RedoxStatus::NoSuchRecord);
+
+        let mut sum = 0;
+        assert_eq!(redoxql_sum(table, 0, 4, 1, &mut sum), RedoxStatus::Ok);
+        assert_eq!(sum, 10 + 99 + 30);
+
+        let projected = [1, 1, 0];
+        let mut rows = ptr::null_mut();
+        assert_eq!(
+            redoxql_select(table, 2, 0, projected.as_ptr(), 3, &mut rows),
+            RedoxStatus::Ok
+        );
+        assert_eq!(redoxql_rows_count(rows), 1);
+        let mut value = 0;
+        assert_eq!(redoxql_rows_value(rows, 0, 1, &mut value), RedoxStatus::Ok);
+        assert_eq!(value, 99);
+        assert_eq!(
+            redoxql_rows_value(rows, 0, 2, &mut value),
+            RedoxStatus::NullValue
+        );
+        assert_eq!(
+            redoxql_rows_value(rows, 1, 0, &mut value),
+            RedoxStatus::OutOfRange
+        );
+        redoxql_rows_free(rows);
+
+        let missing = CString::new("Missing").unwrap();
+        let mut other = ptr::null_mut();
+        assert_eq!(
+            redoxql_get_table(db, missing.as_ptr(), &mut other),
+            RedoxStatus::NoSuchTable
+        );
+        assert_eq!(
+            redoxql_insert(ptr::null(), values.as_ptr(), 3),
+            RedoxStatus::NullPointer
+        );
+
+        redoxql_table_free(table);
+        redoxql_database_free(db);
+    }
+}
+
+#[test]
+fn transaction_through_c_api_test() {
+    unsafe {
+        let db = redoxql_database_new();
+        let name = CString::new("Accounts").unwrap();
+        let mut table = ptr::null_mut();
+        redoxql_create_table(db, name.as_ptr(), 2, 0, &mut table);
+
+        let t = redoxql_transaction_begin();
+        let account = [1, 100];
+        assert_eq!(
+            redoxql_transaction_insert(t, table, account.as_ptr(), 2),
+            RedoxStatus::Ok
+        );
+        assert_eq!(
+            redoxql_transaction_increment(t, table, 1, 1),
+            RedoxStatus::Ok
+        );
+
+        // Nothing is visible outside the transaction before it commits
+        let mut sum = 0;
+        redoxql_sum(table, 0, 10, 1, &mut sum);
+        assert_eq!(sum, 0);
+        assert_eq!(
+            redoxql_transaction_sum(t, table, 0, 10, 1, &mut sum),
+            RedoxStatus::Ok
+        );
+        assert_eq!(sum, 101);
+
+        assert_eq!(redoxql_transaction_commit(t), RedoxStatus::Ok);
+        assert!(!redoxql_transaction_is_active(t));
+        assert_eq!(redoxql_transaction_commit(t), RedoxStatus::TransactionState);
+        redoxql_transaction_free(t);
+
+        redoxql_sum(table, 0, 10, 1, &mut sum);
+        assert_eq!(sum, 101);
+
+        // A failed query without a savepoint aborts the transaction
+        let t = redoxql_transaction_begin();
+        assert_eq!(
+            redoxql_transaction_insert(t, table, account.as_ptr(), 2),
+            RedoxStatus::QueryFailed
+        );
+        assert!(!redoxql_transaction_is_active(t));
+        redoxql_transaction_free(t);
+
+        redoxql_table_free(table);
+        redoxql_database_free(db);
+    }
+}
+
+#[test]
+fn header_declares_every_function_test() {
+    let source = include_str!("../src/ffi.rs");
+    let header = include_str!("../include/redoxql.h");
+
+    let functions: Vec<&str> = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .map(|rest| rest.split('(').next().unwrap())
+        .collect();
+    assert!(functions.len() > 30);
+
+    for function in functions {
+        assert!(
+            header.contains(&format!("{}(", function)),
+            "{} is missing from include/redoxql.h, run cargo build --features header",
+            function
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use redoxql::api::Database;
+use redoxql::client::{Client, ClientError};
+use redoxql::server::Server;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread::{self, JoinHandle};
+
+fn start() -> (SocketAddr, JoinHandle<()>) {
+    let server = Server::bind("127.0.0.1:0", Database::new()).unwrap();
+    let addr = server.local_addr().unwrap();
+    let handle = thread::spawn(move || server.run().unwrap());
+    (addr, handle)
+}
+
+#[test]
+fn queries_over_tcp_test() {
+    let (addr, server) = start();
+    let mut a = Client::connect(addr).unwrap();
+    let mut b = Client::connect(addr).unwrap();
+
+    a.create_table("Grades", 3, 0).unwrap();
+    assert!(matches!(
+        a.create_table("Grades", 3, 0),
+        Err(ClientError::Server(_))
+    ));
+    a.insert("Grades", &[1, 90, 80]).unwrap();
+    a.insert("Grades", &[2, 70, 60]).unwrap();
+
+    // Both connections see the same database
+    b.update("Grades", 1, &[None, Some(95), None]).unwrap();
+    b.increment("Grades", 2, 2).unwrap();
+    assert_eq!(
+        a.select("Grades", 1, 0, &[1, 1, 0]).unwrap(),
+        vec![vec![Some(1), Some(95), None]]
+    );
+    assert_eq!(a.sum("Grades", 1, 2, 2).unwrap(), 141);
+    assert_eq!(b.list_tables().unwrap(), vec!["Grades"]);
+    assert_eq!(
+        b.sql("SELECT c0 FROM Grades WHERE c1 > 80").unwrap(),
+        serde_json::json!([[1]])
+    );
+
+    b.delete("Grades", 2).unwrap();
+    assert!(a.select("Grades", 2, 0, &[1, 1, 1]).unwrap().is_empty());
+    assert!(matches!(
+        a.insert("Missing", &[1]),
+        Err(ClientError::Server(_))
+    ));
+
+    a.shutdown().unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn transactions_over_tcp_test() {
+    let (addr, server) = start();
+    let mut a = Client::connect(addr).unwrap();
+    let mut b = Client::connect(addr).unwrap();
+
+    a.create_table("Accounts", 2, 0).unwrap();
+    a.insert("Accounts", &[1, 100]).unwrap();
+
+    a.begin().unwrap();
+    assert!(matches!(a.begin(), Err(ClientError::Server(_))));
+    a.update("Accounts", 1, &[None, Some(50)]).unwrap();
+
+    // The lock of a is held, so the transaction of b gives up
+    b.begin().unwrap();
+    assert!(matches!(
+        b.update("Accounts", 1, &[None, Some(0)]),
+        Err(ClientError::Server(_))
+    ));
+    assert!(matches!(b.commit(), Err(ClientError::Server(_))));
+
+    a.commit().unwrap();
+    assert_eq!(b.sum("Accounts", 1, 1, 1).unwrap(), 50);
+
+    // Closing a connection aborts its transaction and frees its locks
+    {
+        let mut c = Client::connect(addr).unwrap();
+        c.begin().unwrap();
+        c.update("Accounts", 1, &[None, Some(0)]).unwrap();
+    }
+    let mut attempts = 0;
+    loop {
+        b.begin().unwrap();
+        if b.update("Accounts", 1, &[None, Some(75)]).is_ok() {
+            break;
+        }
+        // The server notices the closed connection on its own thread
+        attempts += 1;
+        assert!(attempts < 100, "the closed connection kept its lock");
+        thread::sleep(std::time::Duration::from_millis(10));
+    }
+    b.commit().unwrap();
+    assert_eq!(a.sum("Accounts", 1, 1, 1).unwrap(), 75);
+
+    b.shutdown().unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn json_lines_test() {
+    let (addr, server) = start();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut send = |line: &str| {
+        stream.write_all(line.as_bytes()).unwrap();
+        stream.write_all(b"\n").unwrap();
+        let mut answer = String::new();
+        reader.read_line(&mut answer).unwrap();
+        answer.trim_end().to_string()
+    };
+
+    assert_eq!(
+        send(r#"{"op": "sql", "sql": "CREATE TABLE t (c0 PRIMARY KEY, c1)"}"#),
+        r#"{"ok":null}"#
+    );
+    assert_eq!(
+        send(r#"{"op": "sql", "sql": "INSERT INTO t VALUES (1, 2), (3, 4)"}"#),
+        r#"{"ok":2}"#
+    );
+    assert_eq!(
+        send(r#"{"op": "sum", "table": "t", "start": 0, "end": 5, "column": 1}"#),
+        r#"{"ok":6}"#
+    );
+    // A table whose primary key is not one of its columns is refused, not created
+    assert!(send(
+        r#"{"op": "create_table", "name": "bad", "num_columns": 3, "primary_key_column": 5}"#
+    )
+    .starts_with(r#"{"error":"#));
+    assert!(
+        send(r#"{"op": "insert", "table": "bad", "values": [1, 2, 3]}"#)
+            .starts_with(r#"{"error":"#)
+    );
+    assert!(send(r#"{"op": "fly"}"#).starts_with(r#"{"error":"Bad request"#));
+    assert!(send("not json").starts_with(r#"{"error":"Bad request"#));
+    assert_eq!(send(r#"{"op": "shutdown"}"#), r#"{"ok":null}"#);
+
+    server.join().unwrap();
+}
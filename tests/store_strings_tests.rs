@@ -6,7 +6,7 @@ use redoxql::utils::{decode_string_from_ints, encode_str_to_ints};
 #[test]
 fn store_strings() {
     let mut db = RDatabase::new();
-    let table_ref = db.create_table("Books".to_string(), 64, 0);
+    let table_ref = db.create_table("Books".to_string(), 64, 0).unwrap();
     let mut q = RQuery::new(table_ref);
 
     let name = "Cracking the Coding Interview";
@@ -24,10 +24,8 @@ fn store_strings() {
 
     let v = q.select(0, 0, vec![1; 64]);
     let mut vals = vec![];
-    for x in &v.unwrap()[0].as_mut().unwrap().columns {
-        if let Some(d) = x {
-            vals.push(*d);
-        }
+    for d in v.unwrap()[0].as_mut().unwrap().columns.iter().flatten() {
+        vals.push(*d);
     }
 
     assert_eq!(
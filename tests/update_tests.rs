@@ -4,7 +4,7 @@ use redoxql::query::RQuery;
 #[test]
 fn thousands_of_updates_test() {
     let mut db = RDatabase::new();
-    let table_ref = db.create_table("Scores5".to_string(), 3, 0);
+    let table_ref = db.create_table("Scores5".to_string(), 3, 0).unwrap();
     let mut q = RQuery::new(table_ref);
 
     q.insert(vec![0, 2, 3]);
@@ -19,7 +19,7 @@ fn thousands_of_updates_test() {
 #[test]
 fn thousands_of_ops_test() {
     let mut db = RDatabase::new();
-    let table_ref = db.create_table("Scores6".to_string(), 3, 0);
+    let table_ref = db.create_table("Scores6".to_string(), 3, 0).unwrap();
     let mut q = RQuery::new(table_ref);
 
     q.insert(vec![0, 2, 3]);